@@ -0,0 +1,833 @@
+// animation.rs
+// Sprite animations and how they play.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Frame-by-frame sprite animations.
+//!
+//! An [`Animation`] is a sequence of spritesheet [`Frame`]s and an [`AnimationType`]
+//! saying what happens at the end of the sequence: start over, play it backwards, or stop.
+//! An animation that stops either holds its last frame or disappears, depending on its
+//! [`OnFinish`].
+//!
+//! Each frame is shown for the animation's `frame_length` unless it gives its own
+//! `duration`. Frames can also name events, like `footstep` or `hit`, to be sent as
+//! [`AnimationEvent`]s whenever playback reaches them, so sounds and hitboxes can line up
+//! with the art. In YAML, a frame that needs neither can be written as just its index:
+//!
+//! ```yaml
+//! frames: [0, 1, { index: 2, duration: 250, events: [footstep] }, 3]
+//! ```
+//!
+//! Animations can also move, turn, scale, tint and fade the sprite, with [`Keyframe`]s
+//! that set some of those at points in time, relative to where the sprite is and how it's
+//! tinted. In between, they change with the keyframe's [`Interpolation`]. A spinning
+//! pickup that bobs up and down might be:
+//!
+//! ```yaml
+//! frames: [0]
+//! animation_type: Loop
+//! frame_length: 100
+//! keyframes:
+//!   - { time: 0, translation: [0, 0], rotation: 0 }
+//!   - { time: 500, translation: [0, 8], interpolation: { Ease: QuadInOut } }
+//!   - { time: 1000, translation: [0, 0], rotation: 6.283, interpolation: { Ease: QuadInOut } }
+//! ```
+//!
+//! Which frame is showing is worked out from how long the animation has been playing, so
+//! nothing needs to be updated every frame just to keep it moving. The [`AnimationSystem`]
+//! sends the events, and an [`AnimationFinished`] event when an animation that stops gets
+//! to its end. Each sprite's [`Playback`] keeps track of how long its animation has been
+//! playing in game time, and can be paused, resumed, and sped up or slowed down.
+//!
+//! An [`AnimationController`] picks which animation a sprite plays, as a state machine. Each
+//! state plays one animation and lists [`Transition`]s to other states, taken when a
+//! parameter set by the game passes some value, when an event happens, or when the
+//! animation finishes:
+//!
+//! ```yaml
+//! AnimationController:
+//!   state: idle
+//!   states:
+//!     idle:
+//!       animation: 0
+//!       transitions:
+//!         - { to: walk, when: { Parameter: { name: speed, test: Greater, value: 0 } } }
+//!         - { to: attack, when: { Event: attack } }
+//!     walk:
+//!       animation: 1
+//!       transitions:
+//!         - { to: idle, when: { Parameter: { name: speed, test: Equal, value: 0 } } }
+//!     attack:
+//!       animation: 2
+//!       transitions:
+//!         - { to: idle, when: Finished }
+//! ```
+//!
+//! [`Animation`]: struct.Animation.html
+//! [`AnimationController`]: struct.AnimationController.html
+//! [`AnimationEvent`]: ../event/struct.AnimationEvent.html
+//! [`AnimationFinished`]: ../event/struct.AnimationFinished.html
+//! [`AnimationSystem`]: ../system/struct.AnimationSystem.html
+//! [`AnimationType`]: enum.AnimationType.html
+//! [`Frame`]: struct.Frame.html
+//! [`Interpolation`]: enum.Interpolation.html
+//! [`Keyframe`]: struct.Keyframe.html
+//! [`OnFinish`]: enum.OnFinish.html
+//! [`Playback`]: struct.Playback.html
+//! [`Transition`]: struct.Transition.html
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    serial::{Color, Index},
+    tween::{Easing, Pose},
+};
+
+/// A single animation sequence for a sprite.
+///
+/// Animations only specify the timing of each frame, how to repeat, which frame indices
+/// of a spritesheet to use, and any keyframed changes to the sprite's transform and tint.
+/// They aren't tied to specific spritesheets.
+///
+/// If the frames and keyframes take different amounts of time, a pass through the
+/// animation lasts as long as the longer of the two, and whichever ends first holds its
+/// last frame or keyframe until the pass is over. A `Bounce` animation plays its keyframes
+/// backwards on the way back, like its frames.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Animation {
+    /// The sequence of frames to use from the spritesheet.
+    pub frames: Vec<Frame>,
+    /// How the animation progresses through the frames.
+    pub animation_type: AnimationType,
+    /// The length in milliseconds of each frame that doesn't give its own `duration`.
+    pub frame_length: u32,
+    /// What's shown once a `Once` animation is over. Other animations never finish.
+    #[serde(default)]
+    pub on_finish: OnFinish,
+    /// Changes to the sprite's transform and tint over time, in order of `time`.
+    #[serde(default)]
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// A point in an [`Animation`] that sets some of the sprite's transform and tint.
+///
+/// Values are relative to the sprite's own: `translation` and `rotation` are added to
+/// its transform, and `scale`, `tint` and `opacity` multiply it. Anything a keyframe leaves
+/// out carries on from the keyframes around it.
+///
+/// [`Animation`]: struct.Animation.html
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Keyframe {
+    /// Milliseconds from the start of the animation.
+    pub time: u32,
+    #[serde(default)]
+    pub translation: Option<[f32; 2]>,
+    /// In radians.
+    #[serde(default)]
+    pub rotation: Option<f32>,
+    #[serde(default)]
+    pub scale: Option<[f32; 2]>,
+    /// The red, green and blue the sprite's tint is multiplied by.
+    #[serde(default)]
+    pub tint: Option<[f32; 3]>,
+    #[serde(default)]
+    pub opacity: Option<f32>,
+    /// How values change on the way to this keyframe from the one before it.
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+/// How keyframed values change between keyframes.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Interpolation {
+    /// Keep the earlier keyframe's values until the later keyframe's time comes.
+    Step,
+    /// Change smoothly, following an easing curve.
+    Ease(Easing),
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        Interpolation::Ease(Easing::Linear)
+    }
+}
+
+/// One step of an [`Animation`].
+///
+/// [`Animation`]: struct.Animation.html
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(from = "FrameData")]
+pub struct Frame {
+    /// The frame's index in the spritesheet.
+    pub index: u16,
+    /// How long the frame is shown, in milliseconds, if not the animation's `frame_length`.
+    pub duration: Option<u32>,
+    /// The names of events to send each time the frame starts being shown.
+    pub events: Vec<String>,
+}
+
+/// The ways a [`Frame`] can be written in data files.
+///
+/// [`Frame`]: struct.Frame.html
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FrameData {
+    Index(u16),
+    Full {
+        index: u16,
+        #[serde(default)]
+        duration: Option<u32>,
+        #[serde(default)]
+        events: Vec<String>,
+    },
+}
+
+impl From<FrameData> for Frame {
+    fn from(data: FrameData) -> Frame {
+        match data {
+            FrameData::Index(index) => Frame::from(index),
+            FrameData::Full {
+                index,
+                duration,
+                events,
+            } => Frame {
+                index,
+                duration,
+                events,
+            },
+        }
+    }
+}
+
+impl From<u16> for Frame {
+    fn from(index: u16) -> Frame {
+        Frame {
+            index,
+            duration: None,
+            events: vec![],
+        }
+    }
+}
+
+/// Whether and how the animation repeats.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum AnimationType {
+    /// Repeat by going through the sequence over and over: 1 2 3 1 2 3
+    Loop,
+    /// Repeat by going back and forth through the sequence: 1 2 3 2 1 2 3
+    Bounce,
+    /// Play once, then stop.
+    Once,
+}
+
+/// What a `Once` animation shows after its last frame.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum OnFinish {
+    /// Keep showing the last frame.
+    #[default]
+    Hold,
+    /// Show nothing.
+    Hide,
+}
+
+impl Animation {
+    /// How long the frame at `position` in `frames` is shown, in milliseconds.
+    pub fn frame_duration(&self, position: usize) -> u64 {
+        // A frame that takes no time would leave playback stuck on it.
+        u64::from(
+            self.frames[position]
+                .duration
+                .unwrap_or(self.frame_length)
+                .max(1),
+        )
+    }
+
+    /// The positions in `frames` in the order they're shown during one pass through the
+    /// animation. Turning around doesn't repeat the first or last frame, so one pass of a
+    /// `Bounce` animation through frames 1 2 3 is 1 2 3 2.
+    fn cycle(&self) -> Vec<usize> {
+        let count = self.frames.len();
+        match self.animation_type {
+            AnimationType::Bounce if count > 1 => (0..count).chain((1..count - 1).rev()).collect(),
+            _ => (0..count).collect(),
+        }
+    }
+
+    /// How long it takes to go through the frames once, in milliseconds.
+    fn frames_duration(&self) -> u64 {
+        self.cycle()
+            .into_iter()
+            .map(|position| self.frame_duration(position))
+            .sum()
+    }
+
+    /// The time of the last keyframe, in milliseconds.
+    fn keyframes_length(&self) -> u64 {
+        self.keyframes
+            .iter()
+            .map(|keyframe| u64::from(keyframe.time))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// How long it takes to go through the animation once, in milliseconds.
+    pub fn duration(&self) -> u64 {
+        let keyframes = match self.animation_type {
+            AnimationType::Bounce => self.keyframes_length() * 2,
+            _ => self.keyframes_length(),
+        };
+        self.frames_duration().max(keyframes)
+    }
+
+    /// The position in `frames` of the frame to show once the animation has been playing
+    /// for `elapsed` milliseconds, or `None` if nothing should be shown.
+    pub fn position_at(&self, elapsed: u64) -> Option<usize> {
+        let duration = self.duration();
+        if duration == 0 {
+            return None;
+        }
+        let mut time = match self.animation_type {
+            AnimationType::Once if elapsed >= duration => {
+                return match self.on_finish {
+                    OnFinish::Hold => self.frames.len().checked_sub(1),
+                    OnFinish::Hide => None,
+                };
+            }
+            AnimationType::Once => elapsed,
+            _ => elapsed % duration,
+        };
+        let cycle = self.cycle();
+        for &position in &cycle {
+            let frame_duration = self.frame_duration(position);
+            if time < frame_duration {
+                return Some(position);
+            }
+            time -= frame_duration;
+        }
+        // The keyframes are still going.
+        cycle.last().copied()
+    }
+
+    /// Where the keyframes put the sprite once the animation has been playing for
+    /// `elapsed` milliseconds, relative to where it is. With no keyframes, the sprite stays
+    /// as it is.
+    pub fn pose_at(&self, elapsed: u64) -> Pose {
+        let mut pose = Pose::default();
+        if self.keyframes.is_empty() {
+            return pose;
+        }
+        let duration = self.duration();
+        let length = self.keyframes_length();
+        let time = match self.animation_type {
+            AnimationType::Once => elapsed.min(duration),
+            _ => elapsed % duration.max(1),
+        };
+        let time = match self.animation_type {
+            AnimationType::Bounce if time > length => (length * 2).saturating_sub(time),
+            _ => time.min(length),
+        };
+
+        if let Some([x, y]) = self.sample(time, |keyframe| keyframe.translation) {
+            pose.transform.translation = vec![x, y];
+        }
+        if let Some([rotation]) = self.sample(time, |keyframe| keyframe.rotation.map(|r| [r])) {
+            pose.transform.rotation = rotation;
+        }
+        if let Some([x, y]) = self.sample(time, |keyframe| keyframe.scale) {
+            pose.transform.scaling = vec![x, y];
+        }
+        if let Some([r, g, b]) = self.sample(time, |keyframe| keyframe.tint) {
+            pose.tint = Color { r, g, b, a: 1.0 };
+        }
+        if let Some([opacity]) = self.sample(time, |keyframe| keyframe.opacity.map(|a| [a])) {
+            pose.tint.a = opacity;
+        }
+        pose
+    }
+
+    /// The value of one keyframed field at `time`, or `None` if no keyframe sets it.
+    fn sample<const N: usize>(
+        &self,
+        time: u64,
+        field: fn(&Keyframe) -> Option<[f32; N]>,
+    ) -> Option<[f32; N]> {
+        let mut keyed = self
+            .keyframes
+            .iter()
+            .filter_map(|keyframe| field(keyframe).map(|value| (keyframe, value)));
+        let (mut before, mut value) = keyed.next()?;
+        for (after, next) in keyed {
+            if u64::from(after.time) <= time {
+                before = after;
+                value = next;
+                continue;
+            }
+            if time < u64::from(before.time) {
+                break;
+            }
+            // Keyframes built out of order can leave no time to ease over, so they step.
+            let span = after.time.saturating_sub(before.time);
+            let amount = match after.interpolation {
+                _ if span == 0 => 0.0,
+                Interpolation::Step => 0.0,
+                Interpolation::Ease(easing) => {
+                    easing.apply((time - u64::from(before.time)) as f32 / span as f32)
+                }
+            };
+            for (value, next) in value.iter_mut().zip(next.iter()) {
+                *value += (next - *value) * amount;
+            }
+            break;
+        }
+        Some(value)
+    }
+
+    /// The spritesheet frame to show once the animation has been playing for `elapsed`
+    /// milliseconds, or `None` if nothing should be shown.
+    pub fn frame_at(&self, elapsed: u64) -> Option<u16> {
+        self.position_at(elapsed)
+            .map(|position| self.frames[position].index)
+    }
+
+    /// The positions in `frames` of every frame that starts being shown at or after `from`
+    /// milliseconds and before `to`, in order.
+    ///
+    /// If more than a whole pass of a repeating animation fits between the two, only the
+    /// last pass's worth is included.
+    pub fn frames_started(&self, from: u64, to: u64) -> Vec<usize> {
+        let duration = self.duration();
+        if duration == 0 || from >= to {
+            return vec![];
+        }
+        let once = self.animation_type == AnimationType::Once;
+        let from = if once {
+            from
+        } else {
+            from.max(to.saturating_sub(duration))
+        };
+
+        let cycle = self.cycle();
+        let mut started = vec![];
+        // When each pass and frame starts, beginning with the pass `from` falls in.
+        let mut pass = if once { 0 } else { from - from % duration };
+        while pass < to {
+            let mut start = pass;
+            for &position in &cycle {
+                if start >= to {
+                    return started;
+                }
+                if start >= from {
+                    started.push(position);
+                }
+                start += self.frame_duration(position);
+            }
+            if once {
+                break;
+            }
+            pass += duration;
+        }
+        started
+    }
+
+    /// Whether the animation has stopped after playing for `elapsed` milliseconds. Only
+    /// `Once` animations ever do.
+    pub fn is_finished(&self, elapsed: u64) -> bool {
+        self.animation_type == AnimationType::Once && elapsed >= self.duration()
+    }
+}
+
+/// How far along a sprite's animation is, and how fast it's going, in game time as kept by
+/// the [`GameClock`].
+///
+/// Playback that's been started or resumed doesn't begin counting until the
+/// [`AnimationSystem`] next runs, so things that start animations don't need to know the
+/// time. Data files can leave all of it out to start playing at normal speed when loaded.
+///
+/// [`AnimationSystem`]: ../system/struct.AnimationSystem.html
+/// [`GameClock`]: ../time/struct.GameClock.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Playback {
+    /// The game time, in milliseconds, when playback last started, resumed, or changed
+    /// speed, or `None` if it's waiting to start.
+    #[serde(default)]
+    since: Option<u64>,
+    /// The milliseconds played before `since`.
+    #[serde(default)]
+    played: u64,
+    #[serde(default = "default_speed")]
+    speed: f32,
+    #[serde(default)]
+    paused: bool,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback {
+            since: None,
+            played: 0,
+            speed: default_speed(),
+            paused: false,
+        }
+    }
+}
+
+impl Playback {
+    /// How long the animation has been playing at game time `now`, in milliseconds, counting
+    /// only the time it wasn't paused and scaled by its speed.
+    pub fn elapsed(&self, now: u64) -> u64 {
+        match self.since {
+            Some(since) if !self.paused => {
+                let playing = now.saturating_sub(since) as f64 * f64::from(self.speed);
+                self.played + playing as u64
+            }
+            _ => self.played,
+        }
+    }
+
+    /// Starts counting at game time `now`, if playback is waiting to start.
+    pub fn start(&mut self, now: u64) {
+        if self.since.is_none() {
+            self.since = Some(now);
+        }
+    }
+
+    /// Goes back to the beginning, without changing the speed or whether it's paused.
+    pub fn restart(&mut self) {
+        self.since = None;
+        self.played = 0;
+    }
+
+    /// Stops counting at game time `now`.
+    pub fn pause(&mut self, now: u64) {
+        if !self.paused {
+            self.played = self.elapsed(now);
+            self.paused = true;
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.since = None;
+            self.paused = false;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Changes how fast the animation plays from game time `now`: 2 is twice as fast as
+    /// normal, 0.5 half as fast. Negative speeds are taken as 0.
+    pub fn set_speed(&mut self, now: u64, speed: f32) {
+        self.played = self.elapsed(now);
+        if self.since.is_some() {
+            self.since = Some(now);
+        }
+        self.speed = speed.max(0.0);
+    }
+}
+
+/// Picks which of an entity's animations its [`Animation2D`] plays.
+///
+/// Each update, the first [`Transition`] out of the current state whose condition holds
+/// is taken. A sprite with a controller should be steered through its parameters and
+/// triggers; animations played on the [`Animation2D`] directly are replaced as soon as the
+/// controller next changes state.
+///
+/// [`Animation2D`]: ../component/struct.Animation2D.html
+/// [`Transition`]: struct.Transition.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AnimationController {
+    /// Every state, by name.
+    pub states: HashMap<String, AnimationState>,
+    /// The name of the current state.
+    state: String,
+    /// Values set by the game for transitions to test. Unset parameters are 0.
+    #[serde(default)]
+    parameters: HashMap<String, f32>,
+    /// Events set by the game since the last update.
+    #[serde(default)]
+    triggers: Vec<String>,
+}
+
+/// One state of an [`AnimationController`].
+///
+/// [`AnimationController`]: struct.AnimationController.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AnimationState {
+    /// The index of the animation played in this state, in the entity's [`Animation2D`].
+    ///
+    /// [`Animation2D`]: ../component/struct.Animation2D.html
+    pub animation: Index,
+    /// Tried in order.
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+}
+
+/// A way out of an [`AnimationState`].
+///
+/// [`AnimationState`]: struct.AnimationState.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Transition {
+    /// The name of the state to go to.
+    pub to: String,
+    pub when: Condition,
+    #[serde(default)]
+    pub clock: TransitionClock,
+}
+
+/// When a [`Transition`] is taken.
+///
+/// [`Transition`]: struct.Transition.html
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Condition {
+    /// A parameter compares to a value.
+    Parameter {
+        name: String,
+        test: Comparison,
+        value: f32,
+    },
+    /// An event with this name was named on a frame of the current animation, or set as a
+    /// trigger by the game.
+    Event(String),
+    /// The current animation finished.
+    Finished,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl Comparison {
+    fn test(self, left: f32, right: f32) -> bool {
+        match self {
+            Comparison::Equal => (left - right).abs() < f32::EPSILON,
+            Comparison::NotEqual => (left - right).abs() >= f32::EPSILON,
+            Comparison::Less => left < right,
+            Comparison::Greater => left > right,
+        }
+    }
+}
+
+/// What happens to the clock when a [`Transition`] is taken.
+///
+/// [`Transition`]: struct.Transition.html
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum TransitionClock {
+    /// The new state's animation starts from its first frame.
+    #[default]
+    Restart,
+    /// The new state's animation carries on from as far along as the old one was, like
+    /// switching between walk cycles that are the same length.
+    Continue,
+}
+
+impl AnimationController {
+    /// The name of the current state.
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    /// The index of the animation the current state plays, or `None` if the state doesn't
+    /// exist.
+    pub fn animation(&self) -> Option<Index> {
+        self.states.get(&self.state).map(|state| state.animation)
+    }
+
+    pub fn parameter(&self, name: &str) -> f32 {
+        self.parameters.get(name).cloned().unwrap_or(0.0)
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: f32) {
+        self.parameters.insert(name.to_string(), value);
+    }
+
+    /// Sets off an event, for transitions waiting on it, during the next update only.
+    pub fn trigger(&mut self, name: &str) {
+        self.triggers.push(name.to_string());
+    }
+
+    /// Takes the first transition out of the current state whose condition holds, given the
+    /// events that happened on the current animation and whether it finished. Returns the
+    /// new state's animation and what to do with the clock, or `None` if the state didn't
+    /// change.
+    ///
+    /// Triggers are used up whether or not anything happens.
+    pub fn update(
+        &mut self,
+        events: &[String],
+        finished: bool,
+    ) -> Option<(Index, TransitionClock)> {
+        let triggers = std::mem::take(&mut self.triggers);
+        let transition =
+            self.states.get(&self.state)?.transitions.iter().find(
+                |transition| match &transition.when {
+                    Condition::Parameter { name, test, value } => {
+                        test.test(self.parameter(name), *value)
+                    }
+                    Condition::Event(name) => events
+                        .iter()
+                        .chain(triggers.iter())
+                        .any(|event| event == name),
+                    Condition::Finished => finished,
+                },
+            )?;
+        let animation = self.states.get(&transition.to)?.animation;
+        let clock = transition.clock;
+        self.state = transition.to.clone();
+        Some((animation, clock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(animation_type: AnimationType, on_finish: OnFinish) -> Vec<Option<u16>> {
+        let animation = Animation {
+            frames: vec![Frame::from(1), Frame::from(2), Frame::from(3)],
+            animation_type,
+            frame_length: 10,
+            on_finish,
+            keyframes: vec![],
+        };
+        (0..8)
+            .map(|step| animation.frame_at(step * 10 + 5))
+            .collect()
+    }
+
+    #[test]
+    fn sequences_by_type() {
+        let some = |frames: &[u16]| frames.iter().map(|&frame| Some(frame)).collect::<Vec<_>>();
+        assert_eq!(
+            frames(AnimationType::Loop, OnFinish::Hold),
+            some(&[1, 2, 3, 1, 2, 3, 1, 2])
+        );
+        assert_eq!(
+            frames(AnimationType::Bounce, OnFinish::Hold),
+            some(&[1, 2, 3, 2, 1, 2, 3, 2])
+        );
+        assert_eq!(
+            frames(AnimationType::Once, OnFinish::Hold),
+            some(&[1, 2, 3, 3, 3, 3, 3, 3])
+        );
+        assert_eq!(
+            frames(AnimationType::Once, OnFinish::Hide)[2..4],
+            [Some(3), None]
+        );
+    }
+
+    #[test]
+    fn frame_timing_and_events() {
+        let animation: Animation = serde_yaml::from_str(
+            "{ frames: [4, { index: 5, duration: 30, events: [step] }, 6], \
+               animation_type: Bounce, frame_length: 10 }",
+        )
+        .unwrap();
+        // One pass is 4 5 6 5: 10 + 30 + 10 + 30 milliseconds.
+        assert_eq!(animation.duration(), 80);
+        let shown = [0, 9, 10, 39, 40, 50, 79, 80]
+            .iter()
+            .map(|&elapsed| animation.frame_at(elapsed).unwrap())
+            .collect::<Vec<u16>>();
+        assert_eq!(shown, vec![4, 4, 5, 5, 6, 5, 5, 4]);
+        assert_eq!(animation.frames_started(0, 51), vec![0, 1, 2, 1]);
+        assert_eq!(animation.frames_started(11, 50), vec![2]);
+        assert_eq!(animation.frames_started(50, 1000).len(), 4);
+    }
+
+    #[test]
+    fn keyframes_are_sampled_between_neighbours() {
+        let animation: Animation = serde_yaml::from_str(
+            "{ frames: [0], animation_type: Bounce, frame_length: 100, keyframes: [
+                 { time: 0, translation: [0, 0], opacity: 1 },
+                 { time: 100, rotation: 1 },
+                 { time: 150, opacity: 0, interpolation: Step },
+                 { time: 200, translation: [10, -20] } ] }",
+        )
+        .unwrap();
+        // Out to the last keyframe and back again.
+        assert_eq!(animation.duration(), 400);
+        let pose = animation.pose_at(50);
+        assert_eq!(pose.transform.translation, vec![2.5, -5.0]);
+        // Only one keyframe sets the rotation, so it's held throughout.
+        assert_eq!(pose.transform.rotation, 1.0);
+        assert_eq!(pose.tint.a, 1.0);
+        assert_eq!(animation.pose_at(149).tint.a, 1.0);
+        assert_eq!(animation.pose_at(150).tint.a, 0.0);
+        assert_eq!(animation.pose_at(350), animation.pose_at(50));
+        assert_eq!(animation.frame_at(350), Some(0));
+    }
+
+    #[test]
+    fn keyframes_out_of_order_still_sample() {
+        let mut animation: Animation =
+            serde_yaml::from_str("{ frames: [0], animation_type: Loop, frame_length: 100 }")
+                .unwrap();
+        for (time, x) in [(100, 10.0), (50, 0.0), (150, 20.0)].iter() {
+            animation.keyframes.push(Keyframe {
+                time: *time,
+                translation: Some([*x, 0.0]),
+                ..Keyframe::default()
+            });
+        }
+        for time in (0..=150).step_by(25) {
+            let translation = animation.pose_at(time).transform.translation;
+            assert!(translation.iter().all(|value| value.is_finite()));
+        }
+        // Before the first keyframe listed, its value is held.
+        assert_eq!(animation.pose_at(25).transform.translation, vec![10.0, 0.0]);
+    }
+
+    #[test]
+    fn controller_transitions() {
+        let controller = || -> AnimationController {
+            serde_yaml::from_str(
+                "{ state: idle, states: {
+                     idle: { animation: 0, transitions: [
+                       { to: walk, when: { Parameter: { name: speed, test: Greater, value: 0 } } },
+                       { to: attack, when: { Event: attack } } ] },
+                     walk: { animation: 1, transitions: [
+                       { to: idle, when: Finished, clock: Continue } ] },
+                     attack: { animation: 2 } } }",
+            )
+            .unwrap()
+        };
+
+        let mut attacking = controller();
+        assert_eq!(attacking.update(&[], true), None);
+        attacking.trigger("attack");
+        assert_eq!(
+            attacking.update(&[], false),
+            Some((2, TransitionClock::Restart))
+        );
+        assert_eq!(attacking.state(), "attack");
+
+        let mut walking = controller();
+        walking.trigger("jump");
+        walking.update(&[], false);
+        // Triggers only last one update.
+        walking.set_parameter("speed", 1.0);
+        assert_eq!(
+            walking.update(&["jump".to_string()], false),
+            Some((1, TransitionClock::Restart))
+        );
+        assert_eq!(
+            walking.update(&[], true),
+            Some((0, TransitionClock::Continue))
+        );
+    }
+}
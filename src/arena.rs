@@ -0,0 +1,380 @@
+// arena.rs
+// Storage that hands out generational handles instead of raw indices.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Generational arenas.
+//!
+//! An [`Arena`] stores values in slots and gives out a [`Handle`] for each one. Removing a
+//! value frees its slot for reuse, and bumps the slot's generation so that handles to the
+//! old value no longer match anything. Lookups with a stale [`Handle`] return `None`
+//! instead of quietly finding whatever was put in the slot afterwards.
+//!
+//! An [`ArenaSnapshot`] records an arena's slots exactly, empty ones and generations
+//! included, so that handles saved along with it still refer to the same values once it's
+//! restored.
+//!
+//! [`Arena`]: struct.Arena.html
+//! [`ArenaSnapshot`]: struct.ArenaSnapshot.html
+//! [`Handle`]: struct.Handle.html
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::iter::FromIterator;
+
+use crate::{error::Error, serial::Index};
+
+/// Refers to a value in an [`Arena`].
+///
+/// In YAML, a handle can be written as a plain number, which is taken as a slot with
+/// generation 0. That's the handle the value at that position in a freshly loaded
+/// [`Arena`] gets.
+///
+/// [`Arena`]: struct.Arena.html
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(from = "HandleData")]
+pub struct Handle {
+    pub index: Index,
+    pub generation: u32,
+}
+
+/// The ways a [`Handle`] can be written in data files.
+///
+/// [`Handle`]: struct.Handle.html
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HandleData {
+    Index(Index),
+    Full { index: Index, generation: u32 },
+}
+
+impl From<HandleData> for Handle {
+    fn from(data: HandleData) -> Handle {
+        match data {
+            HandleData::Index(index) => Handle {
+                index,
+                generation: 0,
+            },
+            HandleData::Full { index, generation } => Handle { index, generation },
+        }
+    }
+}
+
+/// A place for one value in an [`Arena`].
+///
+/// [`Arena`]: struct.Arena.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Slot<T> {
+    /// Incremented every time the slot's value is removed.
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A `Vec` whose elements can be removed without disturbing the others.
+#[derive(Clone, Debug)]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    /// Empty slots, reused most recently freed first.
+    free: Vec<Index>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Arena::default()
+    }
+
+    /// Stores a value, reusing an empty slot if there is one, and returns its handle.
+    pub fn insert(&mut self, value: T) -> Handle {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.value = Some(value);
+                Handle {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                Handle {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Takes the value out of the arena. Returns `None` if the handle is stale.
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        if !self.contains(handle) {
+            return None;
+        }
+        let slot = &mut self.slots[handle.index];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        slot.value.take()
+    }
+
+    /// Whether the handle still refers to a value.
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        match self.slots.get(handle.index) {
+            Some(slot) if slot.generation == handle.generation => slot.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        match self.slots.get_mut(handle.index) {
+            Some(slot) if slot.generation == handle.generation => slot.value.as_mut(),
+            _ => None,
+        }
+    }
+
+    /// The number of values in the arena.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the values in the arena along with their handles.
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value.as_ref().map(|value| {
+                (
+                    Handle {
+                        index,
+                        generation: slot.generation,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+
+    /// Records the arena's layout, converting each value with `convert`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error `convert` returns.
+    pub fn snapshot<U, E, F>(&self, mut convert: F) -> Result<ArenaSnapshot<U>, E>
+    where
+        F: FnMut(Handle, &T) -> Result<U, E>,
+    {
+        let slots = self
+            .slots
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| {
+                let handle = Handle {
+                    index,
+                    generation: slot.generation,
+                };
+                Ok(Slot {
+                    generation: slot.generation,
+                    value: match &slot.value {
+                        Some(value) => Some(convert(handle, value)?),
+                        None => None,
+                    },
+                })
+            })
+            .collect::<Result<Vec<Slot<U>>, E>>()?;
+        Ok(ArenaSnapshot {
+            slots,
+            free: self.free.clone(),
+        })
+    }
+
+    /// Iterates mutably over the values in the arena along with their handles.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let generation = slot.generation;
+            slot.value
+                .as_mut()
+                .map(|value| (Handle { index, generation }, value))
+        })
+    }
+}
+
+/// An [`Arena`]'s slots, generations, and free list, for saving and restoring it.
+///
+/// [`Arena`]: struct.Arena.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ArenaSnapshot<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<Index>,
+}
+
+impl<T> ArenaSnapshot<T> {
+    /// Rebuilds the arena, converting each value with `convert`. Every handle into the
+    /// original arena refers to the converted value of what it referred to before.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Snapshot`] if the free list names a slot that doesn't exist, isn't
+    /// empty, or is already on the list, or otherwise the first error `convert` returns.
+    ///
+    /// [`Error::Snapshot`]: ../error/enum.Error.html#variant.Snapshot
+    pub fn restore<U, E, F>(self, mut convert: F) -> Result<Arena<U>, E>
+    where
+        E: From<Error>,
+        F: FnMut(Handle, T) -> Result<U, E>,
+    {
+        self.check_free()?;
+        let slots = self
+            .slots
+            .into_iter()
+            .enumerate()
+            .map(|(index, slot)| {
+                let handle = Handle {
+                    index,
+                    generation: slot.generation,
+                };
+                Ok(Slot {
+                    generation: slot.generation,
+                    value: match slot.value {
+                        Some(value) => Some(convert(handle, value)?),
+                        None => None,
+                    },
+                })
+            })
+            .collect::<Result<Vec<Slot<U>>, E>>()?;
+        Ok(Arena {
+            slots,
+            free: self.free,
+        })
+    }
+
+    /// Makes sure that reusing the free slots won't overwrite a value or index past the end.
+    fn check_free(&self) -> Result<(), Error> {
+        for (position, index) in self.free.iter().enumerate() {
+            let problem = match self.slots.get(*index) {
+                None => "doesn't exist",
+                Some(slot) if slot.value.is_some() => "isn't empty",
+                Some(_) if self.free[..position].contains(index) => "is listed more than once",
+                Some(_) => continue,
+            };
+            return Err(Error::Snapshot(format!("free slot {} {}", index, problem)));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Clone> From<&Arena<T>> for ArenaSnapshot<T> {
+    fn from(arena: &Arena<T>) -> Self {
+        ArenaSnapshot {
+            slots: arena.slots.clone(),
+            free: arena.free.clone(),
+        }
+    }
+}
+
+impl<T> FromIterator<T> for Arena<T> {
+    /// Values are placed in order, so the `n`th value gets slot `n` at generation 0.
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        Arena {
+            slots: values
+                .into_iter()
+                .map(|value| Slot {
+                    generation: 0,
+                    value: Some(value),
+                })
+                .collect(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Arena<T> {
+    /// Loads an arena from a list of values, as with `from_iter`.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<T>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_handles_miss() {
+        let mut arena = Arena::new();
+        let first = arena.insert("first");
+        let second = arena.insert("second");
+
+        assert_eq!(arena.remove(first), Some("first"));
+        assert_eq!(arena.get(first), None);
+        assert_eq!(arena.remove(first), None);
+
+        // The freed slot is reused, but the old handle still doesn't match.
+        let third = arena.insert("third");
+        assert_eq!(third.index, first.index);
+        assert_ne!(third, first);
+        assert_eq!(arena.get(first), None);
+        assert_eq!(arena.get(third), Some(&"third"));
+        assert_eq!(arena.get(second), Some(&"second"));
+        assert_eq!(arena.len(), 2);
+    }
+
+    #[test]
+    fn broken_free_lists_are_refused() {
+        // Slot 0 is empty; slot 1 holds a value; there's no slot 2.
+        for free in ["[1]", "[2]", "[0, 0]"].iter() {
+            let snapshot: ArenaSnapshot<i32> = serde_yaml::from_str(&format!(
+                "{{ slots: [{{ generation: 1, value: ~ }}, {{ generation: 0, value: 2 }}], \
+                   free: {} }}",
+                free
+            ))
+            .unwrap();
+            let result = snapshot.restore(|_, value| Ok::<i32, Error>(value));
+            assert!(matches!(result, Err(Error::Snapshot(_))), "{}", free);
+        }
+    }
+
+    #[test]
+    fn load_from_yaml() {
+        let arena: Arena<String> = serde_yaml::from_str("[a, b]").unwrap();
+        let handles: Vec<Handle> = serde_yaml::from_str("[1, {index: 0, generation: 0}]").unwrap();
+        assert_eq!(arena.get(handles[0]).map(String::as_str), Some("b"));
+        assert_eq!(arena.get(handles[1]).map(String::as_str), Some("a"));
+    }
+
+    #[test]
+    fn snapshots_keep_handles() {
+        let mut arena = Arena::new();
+        let first = arena.insert(1);
+        let second = arena.insert(2);
+        arena.remove(first);
+
+        let yaml = serde_yaml::to_string(
+            &arena
+                .snapshot(|_, value| Ok::<i32, ()>(value * 10))
+                .unwrap(),
+        )
+        .unwrap();
+        let snapshot: ArenaSnapshot<i32> = serde_yaml::from_str(&yaml).unwrap();
+        let mut restored = snapshot
+            .restore(|_, value| Ok::<i32, Error>(value))
+            .unwrap();
+        assert_eq!(restored.get(second), Some(&20));
+        assert_eq!(restored.get(first), None);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored.insert(3).index, first.index);
+    }
+}
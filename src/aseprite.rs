@@ -0,0 +1,494 @@
+// aseprite.rs
+// Importing spritesheets exported from Aseprite.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Reading the JSON that Aseprite exports alongside a spritesheet image.
+//!
+//! Aseprite can write its frame data either as an array of frames or as a hash of them
+//! keyed by filename; both work. Each frame tag becomes a named [`Animation`], with the
+//! frames' own durations, and each slice becomes a named [`Slice`]:
+//!
+//! | Tag direction      | [`AnimationType`]              |
+//! |--------------------|--------------------------------|
+//! | `forward`          | `Loop`                         |
+//! | `reverse`          | `Loop`, frames in reverse      |
+//! | `pingpong`         | `Bounce`                       |
+//! | `pingpong_reverse` | `Bounce`, frames in reverse    |
+//!
+//! A tag that's set to repeat once becomes a `Once` animation instead of a `Loop`.
+//!
+//! JSON is (nearly) a subset of YAML, so the file is read with `serde_yaml` like the
+//! rest of the data.
+//!
+//! Frames that are all the same size, untrimmed and laid out by rows become a grid
+//! [`Spritesheet`]. Otherwise, each frame's rect is kept as it is, along with where it goes
+//! in the untrimmed frame. TexturePacker writes the same JSON, minus the tags and
+//! durations, so its output can be imported too, pivots and all. Frames can't be rotated.
+//!
+//! [`Animation`]: ../animation/struct.Animation.html
+//! [`AnimationType`]: ../animation/enum.AnimationType.html
+//! [`Slice`]: ../draw/struct.Slice.html
+//! [`Spritesheet`]: ../draw/struct.Spritesheet.html
+
+use serde::{
+    de::{MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{collections::HashMap, fmt, fs};
+
+use crate::{
+    animation::{Animation, AnimationType, Frame, OnFinish},
+    draw::{Slice, SliceKey, SpriteFrame, Spritesheet},
+    error::Error,
+    geometry::Rect,
+    serial::{Index, Position2D, Size},
+};
+
+/// The contents of an Aseprite JSON export.
+#[derive(Deserialize)]
+pub struct AsepriteSheet {
+    /// The frames, in order.
+    #[serde(deserialize_with = "frames_in_order")]
+    pub frames: Vec<AsepriteFrame>,
+    pub meta: Meta,
+}
+
+/// Where one frame is in the exported image, and how long it's shown.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AsepriteFrame {
+    /// The frame's name. In a hash of frames, this is its key.
+    #[serde(default)]
+    pub filename: Option<String>,
+    /// The frame's region of the image.
+    pub frame: AsepriteRect,
+    #[serde(default)]
+    pub rotated: bool,
+    /// Whether empty space around the frame was cut off when it was exported.
+    #[serde(default)]
+    pub trimmed: bool,
+    /// Where `frame` was in the untrimmed frame.
+    #[serde(default)]
+    pub sprite_source_size: Option<AsepriteRect>,
+    /// The size of the untrimmed frame.
+    #[serde(default)]
+    pub source_size: Option<AsepriteSize>,
+    /// From (0, 0) at the top-left corner to (1, 1) at the bottom-right. Only written by
+    /// TexturePacker.
+    #[serde(default)]
+    pub pivot: Option<AsepritePoint>,
+    /// In milliseconds.
+    #[serde(default = "default_duration")]
+    pub duration: u32,
+}
+
+/// Aseprite's own default frame duration.
+fn default_duration() -> u32 {
+    100
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    /// The exported image's filename.
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub frame_tags: Vec<FrameTag>,
+    #[serde(default)]
+    pub slices: Vec<AsepriteSlice>,
+}
+
+/// A named range of frames.
+#[derive(Clone, Debug, Deserialize)]
+pub struct FrameTag {
+    pub name: String,
+    /// The first frame in the tag.
+    pub from: u16,
+    /// The last frame in the tag, included.
+    pub to: u16,
+    #[serde(default = "forward")]
+    pub direction: String,
+    /// How many times the tag plays, if not forever. Aseprite writes this as a string.
+    #[serde(default)]
+    pub repeat: Option<String>,
+}
+
+fn forward() -> String {
+    "forward".to_string()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AsepriteSlice {
+    pub name: String,
+    pub keys: Vec<AsepriteSliceKey>,
+}
+
+/// A slice's bounds from `frame` on.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AsepriteSliceKey {
+    pub frame: u16,
+    pub bounds: AsepriteRect,
+    #[serde(default)]
+    pub pivot: Option<AsepritePoint>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AsepriteRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl From<AsepriteRect> for Rect {
+    fn from(rect: AsepriteRect) -> Rect {
+        Rect::new(rect.x, rect.y, rect.w, rect.h)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AsepriteSize {
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AsepritePoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Reads `frames` whether it was exported as an array or a hash. Aseprite writes the hash
+/// in frame order, so it's read in the order it's written rather than by key.
+fn frames_in_order<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<AsepriteFrame>, D::Error> {
+    struct FramesVisitor;
+
+    impl<'de> Visitor<'de> for FramesVisitor {
+        type Value = Vec<AsepriteFrame>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an array or hash of frames")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut frames = vec![];
+            while let Some(frame) = seq.next_element()? {
+                frames.push(frame);
+            }
+            Ok(frames)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut frames = vec![];
+            while let Some((filename, mut frame)) = map.next_entry::<String, AsepriteFrame>()? {
+                frame.filename.get_or_insert(filename);
+                frames.push(frame);
+            }
+            Ok(frames)
+        }
+    }
+
+    deserializer.deserialize_any(FramesVisitor)
+}
+
+/// A frame of the export as it's kept in a packed [`Spritesheet`].
+///
+/// [`Spritesheet`]: ../draw/struct.Spritesheet.html
+fn sprite_frame(frame: &AsepriteFrame) -> SpriteFrame {
+    let trim = frame.sprite_source_size.filter(|_| frame.trimmed);
+    SpriteFrame {
+        name: frame.filename.clone(),
+        rect: Rect::from(frame.frame),
+        source_size: frame
+            .source_size
+            .filter(|_| frame.trimmed)
+            .map(|size| Size {
+                x: size.w,
+                y: size.h,
+            }),
+        offset: trim.map(|trim| Position2D {
+            x: trim.x,
+            y: trim.y,
+        }),
+        pivot: frame.pivot.map(|pivot| Position2D {
+            x: pivot.x,
+            y: pivot.y,
+        }),
+    }
+}
+
+impl AsepriteSheet {
+    /// Reads an Aseprite JSON export.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file can't be read and [`Error::SerdeYaml`] if it isn't
+    /// an Aseprite export.
+    ///
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn load(path: &str) -> Result<AsepriteSheet, Error> {
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Makes a [`Spritesheet`] from the export, with its tags as animations and its
+    /// slices. `position` is where the exported image starts in the texture, if it's been
+    /// put in an atlas.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Aseprite`] if there are no frames or any are rotated, or if a tag
+    /// is out of range or has a direction that isn't known.
+    ///
+    /// [`Error::Aseprite`]: ../error/enum.Error.html#variant.Aseprite
+    /// [`Spritesheet`]: ../draw/struct.Spritesheet.html
+    pub fn spritesheet(&self, index: Index, position: Position2D) -> Result<Spritesheet, Error> {
+        if self.frames.is_empty() {
+            return Err(Error::Aseprite("there are no frames".to_string()));
+        }
+        if let Some(number) = self.frames.iter().position(|frame| frame.rotated) {
+            return Err(Error::Aseprite(format!(
+                "frame {} is rotated; export it without rotation",
+                number
+            )));
+        }
+
+        let mut spritesheet = Spritesheet {
+            index,
+            pitch: 0,
+            position,
+            size: Size::default(),
+            frame_size: Size::default(),
+            frames: vec![],
+            animations: self.animations()?,
+            slices: self.slices(),
+        };
+        match self.grid() {
+            Some((pitch, rows)) => {
+                let first = self.frames[0].frame;
+                spritesheet.pitch = pitch as u16;
+                spritesheet.position.x += first.x;
+                spritesheet.position.y += first.y;
+                spritesheet.size = Size {
+                    x: pitch as f32 * first.w,
+                    y: rows as f32 * first.h,
+                };
+                spritesheet.frame_size = Size {
+                    x: first.w,
+                    y: first.h,
+                };
+            }
+            None => spritesheet.frames = self.frames.iter().map(sprite_frame).collect(),
+        }
+        Ok(spritesheet)
+    }
+
+    /// The number of frames in each row and the number of rows, if the frames are all the
+    /// same size, untrimmed, and laid out by rows without any space between them.
+    fn grid(&self) -> Option<(usize, usize)> {
+        let first = self.frames.first()?.frame;
+        let pitch = self
+            .frames
+            .iter()
+            .take_while(|frame| frame.frame.y == first.y)
+            .count();
+        let in_grid = self.frames.iter().enumerate().all(|(number, frame)| {
+            !frame.trimmed
+                && frame.pivot.is_none()
+                && frame.frame.x == first.x + (number % pitch) as f32 * first.w
+                && frame.frame.y == first.y + (number / pitch) as f32 * first.h
+                && (frame.frame.w, frame.frame.h) == (first.w, first.h)
+        });
+        if in_grid {
+            Some((pitch, self.frames.len().div_ceil(pitch)))
+        } else {
+            None
+        }
+    }
+
+    /// The frame tags, as animations by name.
+    ///
+    /// Frames shown for as long as the tag's first frame use the animation's
+    /// `frame_length`; the rest get their own `duration`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Aseprite`] if a tag is out of range or has a direction that isn't
+    /// known.
+    ///
+    /// [`Error::Aseprite`]: ../error/enum.Error.html#variant.Aseprite
+    pub fn animations(&self) -> Result<HashMap<String, Animation>, Error> {
+        let mut animations = HashMap::new();
+        for tag in &self.meta.frame_tags {
+            if tag.from > tag.to || usize::from(tag.to) >= self.frames.len() {
+                return Err(Error::Aseprite(format!(
+                    "tag {} covers frames {} to {}, but there are {}",
+                    tag.name,
+                    tag.from,
+                    tag.to,
+                    self.frames.len()
+                )));
+            }
+            let mut indices = (tag.from..=tag.to).collect::<Vec<u16>>();
+            let once = tag.repeat.as_deref() == Some("1");
+            let animation_type = match tag.direction.as_str() {
+                "forward" | "reverse" if once => AnimationType::Once,
+                "forward" | "reverse" => AnimationType::Loop,
+                "pingpong" | "pingpong_reverse" => AnimationType::Bounce,
+                direction => {
+                    return Err(Error::Aseprite(format!(
+                        "tag {} has direction {}, which isn't known",
+                        tag.name, direction
+                    )))
+                }
+            };
+            if tag.direction.ends_with("reverse") {
+                indices.reverse();
+            }
+
+            let frame_length = self.frames[usize::from(indices[0])].duration;
+            let frames = indices
+                .into_iter()
+                .map(|index| {
+                    let duration = self.frames[usize::from(index)].duration;
+                    Frame {
+                        index,
+                        duration: if duration == frame_length {
+                            None
+                        } else {
+                            Some(duration)
+                        },
+                        events: vec![],
+                    }
+                })
+                .collect();
+            animations.insert(
+                tag.name.clone(),
+                Animation {
+                    frames,
+                    animation_type,
+                    frame_length,
+                    on_finish: OnFinish::default(),
+                    keyframes: vec![],
+                },
+            );
+        }
+        Ok(animations)
+    }
+
+    /// The slices, by name.
+    pub fn slices(&self) -> HashMap<String, Slice> {
+        self.meta
+            .slices
+            .iter()
+            .map(|slice| {
+                let keys = slice
+                    .keys
+                    .iter()
+                    .map(|key| SliceKey {
+                        frame: key.frame,
+                        bounds: Rect::from(key.bounds),
+                        pivot: key.pivot.map(|pivot| Position2D {
+                            x: pivot.x,
+                            y: pivot.y,
+                        }),
+                    })
+                    .collect();
+                (slice.name.clone(), Slice { keys })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_EXPORT: &str = r##"{
+        "frames": {
+            "hero 0.aseprite": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "rotated": false, "trimmed": false, "duration": 100 },
+            "hero 1.aseprite": { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 }, "rotated": false, "trimmed": false, "duration": 100 },
+            "hero 2.aseprite": { "frame": { "x": 0, "y": 16, "w": 16, "h": 16 }, "rotated": false, "trimmed": false, "duration": 300 }
+        },
+        "meta": {
+            "image": "hero.png",
+            "size": { "w": 32, "h": 32 },
+            "frameTags": [
+                { "name": "walk", "from": 0, "to": 2, "direction": "pingpong" },
+                { "name": "back", "from": 1, "to": 2, "direction": "reverse" }
+            ],
+            "slices": [
+                { "name": "hitbox", "color": "#0000ffff", "keys": [
+                    { "frame": 0, "bounds": { "x": 2, "y": 3, "w": 10, "h": 12 }, "pivot": { "x": 5, "y": 12 } }
+                ] }
+            ]
+        }
+    }"##;
+
+    #[test]
+    fn hash_export_imports_in_order() {
+        let sheet: AsepriteSheet = serde_yaml::from_str(HASH_EXPORT).unwrap();
+        let spritesheet = sheet
+            .spritesheet(0, Position2D { x: 0.0, y: 32.0 })
+            .unwrap();
+        assert_eq!(spritesheet.pitch, 2);
+        assert_eq!(
+            (spritesheet.position.x, spritesheet.position.y),
+            (0.0, 32.0)
+        );
+        assert_eq!((spritesheet.size.x, spritesheet.size.y), (32.0, 32.0));
+
+        let walk = &spritesheet.animations["walk"];
+        assert_eq!(walk.animation_type, AnimationType::Bounce);
+        assert_eq!(walk.frame_length, 100);
+        assert_eq!(
+            walk.frames[2],
+            Frame {
+                index: 2,
+                duration: Some(300),
+                events: vec![]
+            }
+        );
+        let back = &spritesheet.animations["back"];
+        assert_eq!(back.animation_type, AnimationType::Loop);
+        assert_eq!(
+            back.frames
+                .iter()
+                .map(|frame| frame.index)
+                .collect::<Vec<u16>>(),
+            vec![2, 1]
+        );
+        assert_eq!(back.frames[1].duration, Some(100));
+
+        let hitbox = spritesheet.slices["hitbox"].at(2).unwrap();
+        assert_eq!(hitbox.bounds.size.x, 10.0);
+        assert_eq!(hitbox.pivot.unwrap().y, 12.0);
+    }
+
+    #[test]
+    fn trimmed_frames_are_packed() {
+        let export = HASH_EXPORT.replacen(
+            r#""trimmed": false"#,
+            r#""trimmed": true, "spriteSourceSize": { "x": 2, "y": 1, "w": 12, "h": 14 }, "sourceSize": { "w": 16, "h": 16 }"#,
+            1,
+        ).replacen(r#""w": 16, "h": 16 }, "rotated""#, r#""w": 12, "h": 14 }, "rotated""#, 1);
+        let sheet: AsepriteSheet = serde_yaml::from_str(&export).unwrap();
+        let spritesheet = sheet.spritesheet(0, Position2D { x: 0.0, y: 0.0 }).unwrap();
+        assert_eq!(spritesheet.frame_count(), 3);
+        let frame = spritesheet.frame(0).unwrap();
+        assert_eq!(
+            frame.name.as_deref(),
+            Some("hero 0.aseprite")
+        );
+        assert_eq!((frame.rect.size.x, frame.rect.size.y), (12.0, 14.0));
+        assert_eq!(frame.offset.unwrap().x, 2.0);
+        // The trimmed texels sit where they were in the untrimmed frame.
+        let corner = frame.quad_matrix(None) * nalgebra_glm::vec3(-0.5, 0.5, 1.0);
+        assert!((corner.x - (2.0 / 16.0 - 0.5)).abs() < 1e-5);
+        assert!((corner.y - (0.5 - 1.0 / 16.0)).abs() < 1e-5);
+        assert!(spritesheet.frame(1).unwrap().offset.is_none());
+    }
+}
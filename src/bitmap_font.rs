@@ -0,0 +1,442 @@
+// bitmap_font.rs
+// Loading pre-rendered bitmap fonts.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Bitmap fonts in the AngelCode BMFont format.
+//!
+//! A BMFont is a descriptor file (`.fnt`) plus one or more page images holding the
+//! glyphs. The descriptor says where each character is on the pages, how to place it,
+//! and how much to kern pairs of characters. Both the text and the binary (version 3)
+//! variants of the descriptor are supported; the variant is detected from the file's
+//! contents.
+//!
+//! Page images are looked up relative to the descriptor.
+//!
+//! See <http://www.angelcode.com/products/bmfont/doc/file_format.html> for the format.
+
+use image::RgbaImage;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::error::Error;
+
+/// The first four bytes of a version 3 binary descriptor.
+const BINARY_MAGIC: &[u8] = b"BMF\x03";
+
+/// Where a character is on the font's pages and how to place it, in texels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BitmapChar {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// How far right of the caret the character's image starts.
+    pub x_offset: f32,
+    /// How far below the top of the line the character's image starts.
+    pub y_offset: f32,
+    /// How far to move the caret after drawing the character.
+    pub x_advance: f32,
+}
+
+/// A font whose glyphs are pre-rendered images.
+pub struct BitmapFont {
+    pub face: String,
+    /// The size the glyphs were drawn at, in texels.
+    pub size: f32,
+    /// The distance between the tops of consecutive lines, in texels.
+    pub line_height: f32,
+    /// The distance from the top of a line to its baseline, in texels.
+    pub base: f32,
+    pub pages: Vec<RgbaImage>,
+    pub chars: HashMap<char, BitmapChar>,
+    /// How far to move the second character of each pair, in texels.
+    pub kerning: HashMap<(char, char), f32>,
+}
+
+impl BitmapFont {
+    /// Loads a descriptor file and its page images.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BitmapFont`] if the descriptor is malformed, or [`Error::Io`] or
+    /// [`Error::Image`] if it or its pages can't be read.
+    ///
+    /// [`Error::BitmapFont`]: ../error/enum.Error.html#variant.BitmapFont
+    /// [`Error::Image`]: ../error/enum.Error.html#variant.Image
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    pub fn load(file: &str) -> Result<BitmapFont, Error> {
+        let (mut font, page_files) = BitmapFont::parse(&fs::read(file)?)?;
+        let directory = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+        font.pages = page_files
+            .iter()
+            .map(|page| Ok(image::open(directory.join(page))?.to_rgba()))
+            .collect::<Result<Vec<RgbaImage>, Error>>()?;
+        Ok(font)
+    }
+
+    /// Reads a descriptor in either format, returning the font without its pages and the
+    /// names of the page files.
+    pub fn parse(bytes: &[u8]) -> Result<(BitmapFont, Vec<String>), Error> {
+        if bytes.starts_with(BINARY_MAGIC) {
+            parse_binary(&bytes[BINARY_MAGIC.len()..])
+        } else if bytes.starts_with(b"BMF") {
+            Err(Error::BitmapFont(
+                "only version 3 binary descriptors are supported".to_string(),
+            ))
+        } else {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| Error::BitmapFont("descriptor isn't valid UTF-8".to_string()))?;
+            parse_text(text)
+        }
+    }
+
+    /// The kerning between two characters in texels.
+    pub fn pair_kerning(&self, first: char, second: char) -> f32 {
+        self.kerning.get(&(first, second)).cloned().unwrap_or(0.0)
+    }
+
+    fn empty() -> BitmapFont {
+        BitmapFont {
+            face: String::new(),
+            size: 0.0,
+            line_height: 0.0,
+            base: 0.0,
+            pages: vec![],
+            chars: HashMap::new(),
+            kerning: HashMap::new(),
+        }
+    }
+}
+
+/// Converts a character id from a descriptor, which is a Unicode code point.
+fn char_from_id(id: u32) -> Result<char, Error> {
+    std::char::from_u32(id)
+        .ok_or_else(|| Error::BitmapFont(format!("{} isn't a valid character", id)))
+}
+
+/// Reads the text format, which is a series of lines like
+/// `char id=65 x=10 y=0 width=8 height=12 ...`.
+fn parse_text(text: &str) -> Result<(BitmapFont, Vec<String>), Error> {
+    let mut font = BitmapFont::empty();
+    let mut pages: Vec<(usize, String)> = vec![];
+
+    for (number, line) in text.lines().enumerate() {
+        let mut tokens = Tokens { rest: line.trim() };
+        let tag = match tokens.next() {
+            Some((tag, None)) => tag,
+            Some(_) => {
+                return Err(Error::BitmapFont(format!(
+                    "expected a tag on line {}",
+                    number + 1
+                )))
+            }
+            None => continue,
+        };
+        let mut values = HashMap::new();
+        for (key, value) in tokens {
+            values.insert(key, value.unwrap_or(""));
+        }
+        let number = |key: &str| -> Result<f32, Error> {
+            match values.get(key) {
+                Some(value) => value.parse().map_err(|_| {
+                    Error::BitmapFont(format!("bad {} {} on line {}", key, value, number + 1))
+                }),
+                None => Err(Error::BitmapFont(format!(
+                    "missing {} on line {}",
+                    key,
+                    number + 1
+                ))),
+            }
+        };
+
+        match tag {
+            "info" => {
+                font.face = values.get("face").unwrap_or(&"").to_string();
+                // A negative size means the size was matched to the characters' height.
+                font.size = number("size")?.abs();
+            }
+            "common" => {
+                font.line_height = number("lineHeight")?;
+                font.base = number("base")?;
+            }
+            "page" => pages.push((
+                number("id")? as usize,
+                values.get("file").unwrap_or(&"").to_string(),
+            )),
+            "char" => {
+                font.chars.insert(
+                    char_from_id(number("id")? as u32)?,
+                    BitmapChar {
+                        page: number("page")? as usize,
+                        x: number("x")? as u32,
+                        y: number("y")? as u32,
+                        width: number("width")? as u32,
+                        height: number("height")? as u32,
+                        x_offset: number("xoffset")?,
+                        y_offset: number("yoffset")?,
+                        x_advance: number("xadvance")?,
+                    },
+                );
+            }
+            "kerning" => {
+                font.kerning.insert(
+                    (
+                        char_from_id(number("first")? as u32)?,
+                        char_from_id(number("second")? as u32)?,
+                    ),
+                    number("amount")?,
+                );
+            }
+            // `chars` and `kernings` only give counts, which aren't needed.
+            _ => {}
+        }
+    }
+
+    pages.sort_by_key(|(id, _)| *id);
+    Ok((font, pages.into_iter().map(|(_, file)| file).collect()))
+}
+
+/// Splits a line of the text format into `key=value` pairs, where values may be quoted.
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (&'a str, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rest = self.rest.trim_start();
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let key_end = self
+            .rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(self.rest.len());
+        let key = &self.rest[..key_end];
+        self.rest = &self.rest[key_end..];
+
+        let rest = match self.rest.strip_prefix('=') {
+            Some(rest) => rest,
+            None => return Some((key, None)),
+        };
+        let (value, rest) = match rest.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        self.rest = rest;
+        Some((key, Some(value)))
+    }
+}
+
+/// Reads the binary format, which is a series of blocks, each a type byte and a
+/// little-endian length followed by that many bytes.
+fn parse_binary(mut bytes: &[u8]) -> Result<(BitmapFont, Vec<String>), Error> {
+    let mut font = BitmapFont::empty();
+    let mut pages = vec![];
+
+    while !bytes.is_empty() {
+        let mut header = Reader { bytes };
+        let block_type = header.u8()?;
+        let length = header.u32()? as usize;
+        if header.bytes.len() < length {
+            return Err(Error::BitmapFont(format!(
+                "block {} is cut short",
+                block_type
+            )));
+        }
+        let mut block = Reader {
+            bytes: &header.bytes[..length],
+        };
+        bytes = &header.bytes[length..];
+
+        match block_type {
+            1 => {
+                font.size = f32::from(block.i16()?).abs();
+                // Style flags, character set, stretch, antialiasing, padding, spacing,
+                // and outline don't affect how the font is drawn.
+                block.skip(12)?;
+                font.face = block.string()?;
+            }
+            2 => {
+                font.line_height = f32::from(block.u16()?);
+                font.base = f32::from(block.u16()?);
+            }
+            3 => {
+                while !block.bytes.is_empty() {
+                    pages.push(block.string()?);
+                }
+            }
+            4 => {
+                while !block.bytes.is_empty() {
+                    let character = char_from_id(block.u32()?)?;
+                    let x = u32::from(block.u16()?);
+                    let y = u32::from(block.u16()?);
+                    let width = u32::from(block.u16()?);
+                    let height = u32::from(block.u16()?);
+                    let x_offset = f32::from(block.i16()?);
+                    let y_offset = f32::from(block.i16()?);
+                    let x_advance = f32::from(block.i16()?);
+                    let page = usize::from(block.u8()?);
+                    // The channel the character is drawn in.
+                    block.skip(1)?;
+                    font.chars.insert(
+                        character,
+                        BitmapChar {
+                            page,
+                            x,
+                            y,
+                            width,
+                            height,
+                            x_offset,
+                            y_offset,
+                            x_advance,
+                        },
+                    );
+                }
+            }
+            5 => {
+                while !block.bytes.is_empty() {
+                    let first = char_from_id(block.u32()?)?;
+                    let second = char_from_id(block.u32()?)?;
+                    font.kerning
+                        .insert((first, second), f32::from(block.i16()?));
+                }
+            }
+            _ => {
+                return Err(Error::BitmapFont(format!(
+                    "unknown block type {}",
+                    block_type
+                )))
+            }
+        }
+    }
+
+    Ok((font, pages))
+}
+
+/// Reads little-endian values from the front of a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < count {
+            return Err(Error::BitmapFont("descriptor is cut short".to_string()));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), Error> {
+        self.take(count).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, Error> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads a null-terminated string.
+    fn string(&mut self) -> Result<String, Error> {
+        let end = self
+            .bytes
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| Error::BitmapFont("unterminated string".to_string()))?;
+        let string = String::from_utf8_lossy(&self.bytes[..end]).into_owned();
+        self.bytes = &self.bytes[end + 1..];
+        Ok(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"info face="Pixel Sans" size=-12 bold=0 italic=0 charset="" unicode=1
+common lineHeight=14 base=11 scaleW=64 scaleH=64 pages=1 packed=0
+page id=0 file="pixel sans_0.png"
+chars count=2
+char id=65   x=0     y=0     width=7     height=9     xoffset=0     yoffset=2     xadvance=8     page=0  chnl=15
+char id=86   x=8     y=0     width=7     height=9     xoffset=0     yoffset=2     xadvance=8     page=0  chnl=15
+kernings count=1
+kerning first=65  second=86  amount=-1
+"#;
+
+    #[test]
+    fn text_descriptor() {
+        let (font, pages) = BitmapFont::parse(TEXT.as_bytes()).unwrap();
+        assert_eq!(font.face, "Pixel Sans");
+        assert_eq!((font.size, font.line_height, font.base), (12.0, 14.0, 11.0));
+        assert_eq!(pages, vec!["pixel sans_0.png".to_string()]);
+        assert_eq!(font.chars[&'V'].x, 8);
+        assert_eq!(font.pair_kerning('A', 'V'), -1.0);
+        assert_eq!(font.pair_kerning('V', 'A'), 0.0);
+    }
+
+    #[test]
+    fn binary_descriptor() {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        let mut block = |block_type: u8, contents: &[u8]| {
+            bytes.push(block_type);
+            bytes.extend(&(contents.len() as u32).to_le_bytes());
+            bytes.extend(contents);
+        };
+        let mut info = (-12i16).to_le_bytes().to_vec();
+        info.extend(&[0; 12]);
+        info.extend(b"Pixel Sans\0");
+        block(1, &info);
+        block(2, &[14, 0, 11, 0, 64, 0, 64, 0, 1, 0, 0, 0, 0, 0, 0]);
+        block(3, b"page_0.png\0");
+        let mut chars = 65u32.to_le_bytes().to_vec();
+        chars.extend(&[8, 0, 0, 0, 7, 0, 9, 0, 0, 0, 2, 0, 8, 0, 0, 15]);
+        block(4, &chars);
+        let mut kerning = 65u32.to_le_bytes().to_vec();
+        kerning.extend(&86u32.to_le_bytes());
+        kerning.extend(&(-1i16).to_le_bytes());
+        block(5, &kerning);
+
+        let (font, pages) = BitmapFont::parse(&bytes).unwrap();
+        assert_eq!(font.face, "Pixel Sans");
+        assert_eq!((font.size, font.line_height, font.base), (12.0, 14.0, 11.0));
+        assert_eq!(pages, vec!["page_0.png".to_string()]);
+        assert_eq!(
+            font.chars[&'A'],
+            BitmapChar {
+                page: 0,
+                x: 8,
+                y: 0,
+                width: 7,
+                height: 9,
+                x_offset: 0.0,
+                y_offset: 2.0,
+                x_advance: 8.0,
+            }
+        );
+        assert_eq!(font.pair_kerning('A', 'V'), -1.0);
+        assert!(BitmapFont::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -8,318 +8,944 @@
 //! to execute a desired behavior. For example, in order to draw a tree to the screen,
 //! the [`DrawingSystem`] needs to know how big the tree should be, where it should be
 //! drawn, what texture or part of a texture contains the picture we want to use, and
-//! so on. All of this information is bundled together into a [`Component`] with the
-//! and placed in the [`ComponentManager`]. When it comes time to
-//! draw a frame to the screen, the [`DrawingSystem`] will ask the [`ComponentManager`]
-//! for every [`Component`] of each drawable type and use that information to
-//! draw all of the currently existing drawable components to the screen.
+//! so on. All of this information is bundled together into a component and placed in
+//! the [`ComponentManager`]. When it comes time to draw a frame to the screen, the
+//! [`DrawingSystem`] will ask the [`ComponentManager`] for the [`Storage`] of each
+//! drawable type and use that information to draw all of the currently existing
+//! drawable components to the screen.
 //!
-//! [`Component`]: struct.Component.html
+//! Each type of component is kept in its own [`Storage`]. Any `'static` type that is
+//! `Send + Sync` can be a component. Systems that need several types at once, like
+//! everything on an entity with both a transform and a sprite, can use
+//! [`ComponentManager::query`].
+//!
+//! Games can define their own component types without touching the engine. A type that
+//! implements `Serialize` and `Deserialize` and is registered with
+//! [`ComponentManager::register_named`] can be written in `components.yaml` under its
+//! registered name, just like the built-in [`Animation2D`] and [`Quad`], and is included
+//! in saved games by [`ComponentManager::snapshot`].
+//!
+//! [`Animation2D`]: struct.Animation2D.html
 //! [`ComponentManager`]: struct.ComponentManager.html
+//! [`ComponentManager::query`]: struct.ComponentManager.html#method.query
+//! [`ComponentManager::register_named`]: struct.ComponentManager.html#method.register_named
+//! [`ComponentManager::snapshot`]: struct.ComponentManager.html#method.snapshot
+//! [`Quad`]: struct.Quad.html
 //! [`DrawingSystem`]: ../draw/struct.DrawingSystem.html
+//! [`Storage`]: ../storage/struct.Storage.html
 
-use nalgebra_glm as glm;
-use nalgebra_glm::Mat3;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_yaml;
-use std::{fs, time::Instant};
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    fs,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 use winit::VirtualKeyCode;
 
 use crate::{
-    draw::Animation,
+    animation::{Animation, AnimationController, Playback},
+    arena::{Arena, ArenaSnapshot, Handle},
+    draw::Spritesheet,
     entity::EntityManager,
     error::Error,
-    geometry::{Movement2D, Transform2D, TransformData},
-    serial::{Index, Position2D},
+    geometry::{Movement2D, Placement, TransformData},
+    serial::{Color, Index, Position2D},
+    storage::{AnyStorage, Query, Storage},
+    tween::{Pose, Tweener},
 };
 
-/// An `enum` for the different types of [`Component`]s.
-/// Rust provides `std::mem::discriminant` for looking at which variant an `enum` object
-/// belongs to, and it works for `enum` variants that have attached data. However, it's a little
-/// clunky and it's simpler to use a plain `enum` for variant identification and comparison.
-///
-/// [`Component`]: struct.Component.html
-#[derive(Deserialize, PartialEq, Serialize)]
-pub enum ComponentType {
-    /// A simple textured quad.
-    Quad = 0,
-    /// An animated quad.
-    Animation2D = 1,
-}
-
-/// Stores a [`Component`]'s data. Use `match` or `if let` constructs to access members of a
-/// particular `ComponentData` variant.
-///
-/// [`Component`]: struct.Component.html
+/// An animated quad.
 #[derive(Deserialize, Serialize)]
-pub enum ComponentData {
-    /// An animated quad.
-    Animation2D {
-        texture_index: Index,
-        /// Which [`Spritesheet`] this [`Component`] uses.
-        ///
-        /// [`Spritesheet`]: ../draw/struct.Spritesheet.html
-        /// [`Component`]: struct.Component.html
-        spritesheet_index: Index,
-        /// The [`Component`]'s z-distance. Lower layers have higher values (1 is further from the camera than 0, etc.).
-        ///
-        /// [`Component`]: struct.Component.html
-        layer: u16,
-        /// A list of this [`Component`]'s [`Animation`]s.
-        ///
-        /// [`Animation`]: ../draw/struct.Animation.html
-        /// [`Component`]: struct.Component.html
-        animations: Vec<Animation>,
-        /// An [`Index`] into the `animations` `Vec` representing the currently active animation.
-        ///
-        /// [`Index`]: ../serial/type.Index.html
-        current_animation: Index,
-        /// The `Component`'s position, scaling, and rotation values.
-        ///
-        /// [`Component`]: struct.Component.html
-        transform_data: TransformData,
-
-        #[serde(default = "std::time::Instant::now", skip)]
-        /// When the current animation began, used to calculate which frame in the current animation to use.
-        start_time: Instant,
-        movement: Movement2D,
-    },
-    /// A plain textured quad.
-    Quad {
-        texture_index: Index,
-        /// The [`Component`]'s position, scaling, and rotation values.
-        /// [`Component`]: struct.Component.html
-        transform_data: TransformData,
-        /// The (u, v) coordinates of the point on the `Quad`'s texture that should be attached to
-        /// the `Quad`'s top-left corner.
-        uv_offset: Position2D,
-        layer: u16,
-    },
+pub struct Animation2D {
+    pub texture_index: Index,
+    /// Which [`Spritesheet`] this component uses.
+    ///
+    /// [`Spritesheet`]: ../draw/struct.Spritesheet.html
+    pub spritesheet_index: Index,
+    /// The component's z-distance. Lower layers have higher values (1 is further from the camera than 0, etc.).
+    pub layer: u16,
+    /// A list of this component's [`Animation`]s.
+    ///
+    /// [`Animation`]: ../draw/struct.Animation.html
+    #[serde(default)]
+    pub animations: Vec<Animation>,
+    /// Names of animations that come with the spritesheet, like Aseprite tags, to add to
+    /// the end of `animations` once the component's loaded. See
+    /// [`add_spritesheet_animations`].
+    ///
+    /// [`add_spritesheet_animations`]: #method.add_spritesheet_animations
+    #[serde(default)]
+    pub spritesheet_animations: Vec<String>,
+    /// An [`Index`] into the `animations` `Vec` representing the currently active animation.
+    ///
+    /// [`Index`]: ../serial/type.Index.html
+    pub current_animation: Index,
+    /// The component's position, scaling, and rotation values.
+    pub transform_data: TransformData,
+    /// `transform_data` as it was before the last update, to draw in-between positions
+    /// from.
+    #[serde(skip)]
+    pub previous_transform: Option<TransformData>,
+    /// The color the texture is multiplied by, including how opaque it is.
+    #[serde(default)]
+    pub tint: Color,
+    /// How the sprite sits on `transform_data` and whether it's mirrored.
+    #[serde(default, flatten)]
+    pub placement: Placement,
+
+    /// How far along the current animation is.
+    #[serde(default, flatten)]
+    pub playback: Playback,
+    pub movement: Movement2D,
+    /// Whether the current animation has finished and [`AnimationFinished`] has been sent
+    /// for it.
+    ///
+    /// [`AnimationFinished`]: ../event/struct.AnimationFinished.html
+    #[serde(default)]
+    pub finished: bool,
+    /// How far into the current animation, in milliseconds, frame events have been sent
+    /// up to.
+    #[serde(default)]
+    pub events_until: u64,
+    /// Animations to play, in order, once the current one finishes or, if it repeats, gets
+    /// back to the start.
+    #[serde(default)]
+    pub queued: Vec<Index>,
 }
 
-impl Transform2D for ComponentData {
-    fn rotation_matrix(&self) -> Mat3 {
-        match self {
-            ComponentData::Animation2D { transform_data, .. }
-            | ComponentData::Quad { transform_data, .. } => {
-                glm::rotation2d(transform_data.rotation)
-            }
-        }
+impl Animation2D {
+    /// How long the current animation has been playing at game time `now`, in milliseconds.
+    pub fn elapsed(&self, now: u64) -> u64 {
+        self.playback.elapsed(now)
     }
 
-    fn scaling_matrix(&self) -> Mat3 {
-        match self {
-            ComponentData::Animation2D { transform_data, .. }
-            | ComponentData::Quad { transform_data, .. } => glm::scaling2d(&glm::vec2(
-                transform_data.scaling[0],
-                transform_data.scaling[1],
-            )),
+    /// Plays an animation from the start, replacing anything queued. Resumes playback if
+    /// it's paused.
+    pub fn play(&mut self, animation: Index) {
+        self.queued.clear();
+        self.start(animation);
+    }
+
+    /// Plays an animation once the current one and anything already queued are done.
+    pub fn queue(&mut self, animation: Index) {
+        self.queued.push(animation);
+    }
+
+    /// Switches to another animation as far along as the current one is.
+    pub fn switch(&mut self, animation: Index) {
+        self.current_animation = animation;
+        self.finished = false;
+    }
+
+    /// Starts the next queued animation, if there is one.
+    pub fn play_queued(&mut self) -> bool {
+        if self.queued.is_empty() {
+            return false;
         }
+        let next = self.queued.remove(0);
+        self.start(next);
+        true
+    }
+
+    fn start(&mut self, animation: Index) {
+        self.current_animation = animation;
+        self.playback.restart();
+        self.playback.resume();
+        self.finished = false;
+        self.events_until = 0;
     }
 
-    fn translation_matrix(&self) -> Mat3 {
-        match self {
-            ComponentData::Animation2D { transform_data, .. }
-            | ComponentData::Quad { transform_data, .. } => glm::translation2d(&glm::vec2(
-                transform_data.translation[0],
-                transform_data.translation[1],
-            )),
+    /// Moves the animations named in `spritesheet_animations` from the spritesheet to the
+    /// end of `animations`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Name`] if the spritesheet doesn't have one of them.
+    ///
+    /// [`Error::Name`]: ../error/enum.Error.html#variant.Name
+    pub fn add_spritesheet_animations(&mut self, spritesheet: &Spritesheet) -> Result<(), Error> {
+        for name in std::mem::take(&mut self.spritesheet_animations) {
+            match spritesheet.animations.get(&name) {
+                Some(animation) => self.animations.push(animation.clone()),
+                None => return Err(Error::Name(name)),
+            }
         }
+        Ok(())
+    }
+
+    /// The spritesheet frame to show at game time `now`, or `None` if nothing should be
+    /// shown.
+    pub fn current_frame(&self, now: u64) -> Option<u16> {
+        self.animations
+            .get(self.current_animation)?
+            .frame_at(self.elapsed(now))
+    }
+
+    /// How the current animation's keyframes change the sprite at game time `now`,
+    /// relative to its own transform and tint.
+    pub fn keyframe_pose(&self, now: u64) -> Pose {
+        self.animations
+            .get(self.current_animation)
+            .map(|animation| animation.pose_at(self.elapsed(now)))
+            .unwrap_or_default()
+    }
+
+    /// Whether the current animation has played through and stopped by game time `now`.
+    pub fn is_finished(&self, now: u64) -> bool {
+        self.animations
+            .get(self.current_animation)
+            .is_some_and(|animation| animation.is_finished(self.elapsed(now)))
     }
 }
 
-/// A single data object with no associated behavior.
+/// A plain textured quad.
 #[derive(Deserialize, Serialize)]
-pub struct Component {
-    /// This `Component`'s unique index in the list of all `Component`s.
-    pub id: Index,
-    /// The type of the `Component`.
-    pub component_type: ComponentType,
-    /// The data the `Component` carries.
-    pub component_data: ComponentData,
+pub struct Quad {
+    pub texture_index: Index,
+    /// The component's position, scaling, and rotation values.
+    pub transform_data: TransformData,
+    /// `transform_data` as it was before the last update, to draw in-between positions
+    /// from.
+    #[serde(skip)]
+    pub previous_transform: Option<TransformData>,
+    /// The (u, v) coordinates of the point on the `Quad`'s texture that should be attached to
+    /// the `Quad`'s top-left corner.
+    pub uv_offset: Position2D,
+    pub layer: u16,
+    /// The color the texture is multiplied by, including how opaque it is.
+    #[serde(default)]
+    pub tint: Color,
+    /// How the quad sits on `transform_data` and whether it's mirrored.
+    #[serde(default, flatten)]
+    pub placement: Placement,
 }
 
-/// The owner for all [`Component`]s. Controls creation, access, and deletion.
+/// One entry in `./data/components.yaml`.
 ///
-/// [`Component`]: struct.Component.html
-// Components are mostly handled via their indices so the borrow checker
-// doesn't get mad about a bunch of references being thrown around.
+/// `component_data` is a map with a single key, the name the component's type was
+/// registered under, holding the component's data, e.g. `Quad: { texture_index: 0, ... }`.
 #[derive(Deserialize)]
-pub struct ComponentManager {
-    /// The number of [`Component`]s that have been created. Used to assign new id numbers.
+struct ComponentRecord {
+    component_data: serde_yaml::Mapping,
+}
+
+/// The layout of `./data/components.yaml`.
+#[derive(Deserialize)]
+struct ComponentFile {
+    components: Vec<ComponentRecord>,
+}
+
+/// A component's data and what's known about it, as written in a saved game.
+#[derive(Deserialize, Serialize)]
+pub struct SavedComponent {
+    /// The name the component's type is registered under.
+    pub type_name: String,
+    pub entity: Option<Handle>,
+    pub data: serde_yaml::Value,
+}
+
+/// How to convert a type registered by name to and from YAML.
+#[derive(Clone, Copy)]
+struct NamedType {
+    type_id: TypeId,
+    /// Creates a new component from YAML data.
+    create: fn(&mut ComponentManager, serde_yaml::Value) -> Result<Handle, Error>,
+    /// Stores YAML data under an existing handle, for restoring saved games.
+    restore: fn(&mut ComponentManager, Handle, serde_yaml::Value) -> Result<Component, Error>,
+    /// Writes a component's data out as YAML.
+    save: fn(&ComponentManager, Handle) -> Result<serde_yaml::Value, Error>,
+}
+
+impl NamedType {
+    fn of<T: Serialize + DeserializeOwned + Any + Send + Sync>() -> NamedType {
+        NamedType {
+            type_id: TypeId::of::<T>(),
+            create: |manager, data| {
+                Ok(manager.create_component(serde_yaml::from_value::<T>(data)?))
+            },
+            restore: |manager, handle, data| {
+                let value = serde_yaml::from_value::<T>(data)?;
+                manager.register::<T>();
+                manager.storage_mut::<T>()?.insert(handle, value);
+                Ok(Component {
+                    type_id: TypeId::of::<T>(),
+                    type_name: std::any::type_name::<T>(),
+                    entity: None,
+                })
+            },
+            save: |manager, handle| {
+                let storage = manager.storage::<T>()?;
+                let value = storage.get(handle).ok_or(Error::Index())?;
+                Ok(serde_yaml::to_value(value)?)
+            },
+        }
+    }
+}
+
+/// What the [`ComponentManager`] knows about a component besides its data, which is kept
+/// in the [`Storage`] for its type.
+///
+/// [`ComponentManager`]: struct.ComponentManager.html
+/// [`Storage`]: ../storage/struct.Storage.html
+#[derive(Clone, Copy, Debug)]
+pub struct Component {
+    /// The type of the component's data.
+    pub type_id: TypeId,
+    /// The name of the component's type, for error messages.
+    pub type_name: &'static str,
+    /// The [`Entity`] the component has been added to, if any.
     ///
-    /// [`Component`]: struct.Component.html
-    pub counter: Index,
-    /// A list of all extant game [`Component`]s.
+    /// [`Entity`]: ../entity/struct.Entity.html
+    pub entity: Option<Handle>,
+}
+
+/// The owner for all components. Controls creation, access, and deletion.
+// Components are mostly handled via their handles so the borrow checker
+// doesn't get mad about a bunch of references being thrown around.
+pub struct ComponentManager {
+    /// All extant game components.
+    pub components: Arena<Component>,
+    /// The [`Storage`] for each type, each behind an `RwLock`.
     ///
-    /// [`Component`]: struct.Component.html
-    pub components: Vec<Component>,
+    /// [`Storage`]: ../storage/struct.Storage.html
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    /// How to convert each type registered by name to and from YAML.
+    names: HashMap<String, NamedType>,
+}
+
+impl Default for ComponentManager {
+    fn default() -> Self {
+        ComponentManager::new()
+    }
 }
 
 impl ComponentManager {
-    /// Loads all [`Component`] data from disk.
+    /// Creates a `ComponentManager` with no components, with the built-in types registered.
+    pub fn new() -> ComponentManager {
+        let mut manager = ComponentManager {
+            components: Arena::new(),
+            storages: HashMap::new(),
+            names: HashMap::new(),
+        };
+        manager.register_named::<Animation2D>("Animation2D");
+        manager.register_named::<AnimationController>("AnimationController");
+        manager.register_named::<Quad>("Quad");
+        manager.register_named::<Tweener>("Tweener");
+        manager
+    }
+
+    /// Loads all component data from disk, with only the built-in types available.
+    ///
+    /// See [`load`](#method.load).
     ///
     /// # Errors
     ///
-    /// The [`Component`] data is expected to be found in `./data/components.yaml` and an [`Error::Io`]
+    /// The component data is expected to be found in `./data/components.yaml` and an [`Error::Io`]
     /// will be returned if that file doesn't exist or can't be read for some reason.
     ///
-    /// An [`Error::SerdeYaml`] will be returned if the YAML is malformed or if its data can't be matched
-    /// to a valid [`Component`] structure.
-    ///
-    /// [`Component`]: struct.Component.html
     /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
-    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
     pub fn load_components() -> Result<ComponentManager, Error> {
+        let mut manager = ComponentManager::new();
+        manager.load(&fs::read_to_string("./data/components.yaml")?)?;
+        Ok(manager)
+    }
+
+    /// Creates components from YAML laid out like `./data/components.yaml`. Register any
+    /// types the YAML uses with [`register_named`](#method.register_named) first.
+    ///
+    /// Components are created in the order they're listed, so in a fresh `ComponentManager`
+    /// the `n`th one listed can be referred to with the handle `n` in other files.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ComponentType`] if a component's type isn't registered.
+    ///
+    /// An [`Error::SerdeYaml`] will be returned if the YAML is malformed or if a component's
+    /// data can't be matched to its type's structure.
+    ///
+    /// [`Error::ComponentType`]: ../error/enum.Error.html#variant.ComponentType
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn load(&mut self, yaml: &str) -> Result<Vec<Handle>, Error> {
         use log::debug;
         debug!("Loading components...");
-        Ok(serde_yaml::from_str(&fs::read_to_string(
-            "./data/components.yaml",
-        )?)?)
+        let file: ComponentFile = serde_yaml::from_str(yaml)?;
+
+        let mut handles = Vec::with_capacity(file.components.len());
+        for record in file.components {
+            let mut entries = record.component_data.into_iter();
+            let (name, data) = match (entries.next(), entries.next()) {
+                (Some((serde_yaml::Value::String(name), data)), None) => (name, data),
+                _ => {
+                    return Err(Error::ComponentType(
+                        "component_data should have exactly one type name as its key".to_string(),
+                    ))
+                }
+            };
+            handles.push(self.create_named(&name, data)?);
+        }
+        Ok(handles)
     }
 
-    /// Creates a [`Component`] with the given type and data, then returns the new [`Component`]'s `id`.
+    /// Makes a type usable as a component, and lets it be created from YAML data and saved
+    /// under the given name. Registering a name again replaces the type it refers to.
     ///
-    /// [`Component`]: struct.Component.html
-    pub fn create_component(
+    /// ```ignore
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Health { current: u32, maximum: u32 }
+    ///
+    /// component_manager.register_named::<Health>("Health");
+    /// ```
+    pub fn register_named<T: Serialize + DeserializeOwned + Any + Send + Sync>(
         &mut self,
-        component_type: ComponentType,
-        data: ComponentData,
-    ) -> Index {
-        let new_component = Component {
-            id: self.counter,
-            component_type: component_type,
-            component_data: data,
+        name: &str,
+    ) {
+        self.register::<T>();
+        self.names.insert(name.to_string(), NamedType::of::<T>());
+    }
+
+    /// Creates a component of the type registered under `name` from YAML data.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ComponentType`] if no type is registered under `name`, or
+    /// [`Error::SerdeYaml`] if the data doesn't match the type.
+    ///
+    /// [`Error::ComponentType`]: ../error/enum.Error.html#variant.ComponentType
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn create_named(&mut self, name: &str, data: serde_yaml::Value) -> Result<Handle, Error> {
+        match self.names.get(name) {
+            Some(named) => (named.create)(self, data),
+            None => Err(Error::ComponentType(name.to_string())),
+        }
+    }
+
+    /// The type registered under `name`, if there is one.
+    pub fn named_type(&self, name: &str) -> Option<TypeId> {
+        self.names.get(name).map(|named| named.type_id)
+    }
+
+    /// Writes out every component, keeping their handles, for a saved game.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ComponentType`] if a component's type wasn't registered by name,
+    /// [`Error::Borrow`] if a storage is locked for writing, or [`Error::SerdeYaml`] if a
+    /// component's data can't be serialized.
+    ///
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Error::ComponentType`]: ../error/enum.Error.html#variant.ComponentType
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn snapshot(&self) -> Result<ArenaSnapshot<SavedComponent>, Error> {
+        let type_names = self
+            .names
+            .iter()
+            .map(|(name, named)| (named.type_id, name.as_str()))
+            .collect::<HashMap<TypeId, &str>>();
+        self.components.snapshot(|handle, component| {
+            let type_name = type_names
+                .get(&component.type_id)
+                .ok_or_else(|| Error::ComponentType(component.type_name.to_string()))?;
+            Ok(SavedComponent {
+                type_name: type_name.to_string(),
+                entity: component.entity,
+                data: (self.names[*type_name].save)(self, handle)?,
+            })
+        })
+    }
+
+    /// Creates a `ComponentManager` holding the components from a [`snapshot`], with the
+    /// same types registered as this one. Handles saved with the snapshot refer to the same
+    /// components in the new manager.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Snapshot`] if the snapshot's free list is broken,
+    /// [`Error::ComponentType`] if a component's type isn't registered by name, or
+    /// [`Error::SerdeYaml`] if its data doesn't match the type.
+    ///
+    /// [`snapshot`]: #method.snapshot
+    /// [`Error::ComponentType`]: ../error/enum.Error.html#variant.ComponentType
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    /// [`Error::Snapshot`]: ../error/enum.Error.html#variant.Snapshot
+    pub fn restored(
+        &self,
+        snapshot: ArenaSnapshot<SavedComponent>,
+    ) -> Result<ComponentManager, Error> {
+        let mut manager = ComponentManager {
+            components: Arena::new(),
+            storages: self
+                .storages
+                .iter()
+                .map(|(type_id, storage)| (*type_id, storage.empty()))
+                .collect(),
+            names: self.names.clone(),
         };
-        self.components.push(new_component);
-        self.counter += 1;
-        (self.components.len() - 1) as Index
+        manager.components = snapshot.restore(|handle, saved| {
+            let named = *manager
+                .names
+                .get(&saved.type_name)
+                .ok_or_else(|| Error::ComponentType(saved.type_name.clone()))?;
+            let mut component = (named.restore)(&mut manager, handle, saved.data)?;
+            component.entity = saved.entity;
+            Ok::<Component, Error>(component)
+        })?;
+        Ok(manager)
+    }
+
+    /// Makes a type usable as a component. Registering a type more than once does nothing.
+    pub fn register<T: Any + Send + Sync>(&mut self) {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RwLock::new(Storage::<T>::default())));
+    }
+
+    /// Records which [`Entity`] each component belongs to, for components loaded before
+    /// their entities.
+    ///
+    /// [`Entity`]: ../entity/struct.Entity.html
+    pub fn link_entities(&mut self, entity_manager: &EntityManager) {
+        for (entity, data) in entity_manager.entities.iter() {
+            for component in &data.components {
+                if let Some(component) = self.components.get_mut(*component) {
+                    component.entity = Some(entity);
+                }
+            }
+        }
     }
 
-    /// Adds a [`Component`] to the specified [`Entity`].
+    /// Creates a component holding `data`, then returns the new component's handle.
+    /// The type is registered if it hasn't been already.
+    pub fn create_component<T: Any + Send + Sync>(&mut self, data: T) -> Handle {
+        self.register::<T>();
+        let handle = self.components.insert(Component {
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            entity: None,
+        });
+        self.storage_lock::<T>()
+            .expect("storage was just registered")
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(handle, data);
+        handle
+    }
+
+    /// Destroys a component, removing it from the [`Entity`] it belongs to.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the component has already been destroyed.
     ///
-    /// [`Component`]: struct.Component.html
     /// [`Entity`]: ../entity/struct.Entity.html
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn destroy_component(
+        &mut self,
+        component: Handle,
+        entity_manager: &mut EntityManager,
+    ) -> Result<(), Error> {
+        let removed = self.components.remove(component).ok_or(Error::Index())?;
+        if let Some(storage) = self.storages.get_mut(&removed.type_id) {
+            storage.remove(component);
+        }
+        if let Some(entity) = removed
+            .entity
+            .and_then(|entity| entity_manager.get_entity_mut(entity))
+        {
+            entity.components.retain(|owned| *owned != component);
+        }
+        Ok(())
+    }
+
+    /// Adds a component to the specified [`Entity`]. A component that belongs to another
+    /// entity is moved, so destroying that entity leaves it alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if either the [`Entity`] or the component has been destroyed.
+    ///
+    /// [`Entity`]: ../entity/struct.Entity.html
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
     pub fn add_entity_component(
         &mut self,
-        entity: Index,
+        entity: Handle,
         entity_manager: &mut EntityManager,
-        index: Index,
-    ) {
-        entity_manager.get_entity_mut(entity).components.push(index);
+        component: Handle,
+    ) -> Result<(), Error> {
+        let header = self.components.get_mut(component).ok_or(Error::Index())?;
+        if header.entity == Some(entity) {
+            return Ok(());
+        }
+        entity_manager
+            .get_entity_mut(entity)
+            .ok_or(Error::Index())?
+            .components
+            .push(component);
+        if let Some(previous) = header
+            .entity
+            .and_then(|previous| entity_manager.get_entity_mut(previous))
+        {
+            previous.components.retain(|owned| *owned != component);
+        }
+        header.entity = Some(entity);
+        Ok(())
+    }
+
+    /// Moves the animations an [`Animation2D`] names in `spritesheet_animations` from its
+    /// spritesheet into its own list. Does nothing for other kinds of components.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the spritesheet doesn't exist, [`Error::Borrow`] if the
+    /// storage is locked, or the errors from [`Animation2D::add_spritesheet_animations`].
+    ///
+    /// [`Animation2D`]: struct.Animation2D.html
+    /// [`Animation2D::add_spritesheet_animations`]: struct.Animation2D.html#method.add_spritesheet_animations
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn add_spritesheet_animations(
+        &self,
+        component: Handle,
+        spritesheets: &[Spritesheet],
+    ) -> Result<(), Error> {
+        if !self.is_of_type(component, TypeId::of::<Animation2D>()) {
+            return Ok(());
+        }
+        let mut storage = self.storage_mut::<Animation2D>()?;
+        let animation = storage.get_mut(component).ok_or(Error::Index())?;
+        let spritesheet = spritesheets
+            .get(animation.spritesheet_index)
+            .ok_or(Error::Index())?;
+        animation.add_spritesheet_animations(spritesheet)
+    }
+
+    /// Remembers the transform of every [`Animation2D`] and [`Quad`] as it is now, so they
+    /// can be drawn partway between there and wherever the next update moves them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Borrow`] if either storage is locked.
+    ///
+    /// [`Animation2D`]: struct.Animation2D.html
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Quad`]: struct.Quad.html
+    pub fn record_transforms(&self) -> Result<(), Error> {
+        for (_, animation) in self.storage_mut::<Animation2D>()?.iter_mut() {
+            animation.previous_transform = Some(animation.transform_data.clone());
+        }
+        for (_, quad) in self.storage_mut::<Quad>()?.iter_mut() {
+            quad.previous_transform = Some(quad.transform_data.clone());
+        }
+        Ok(())
+    }
+
+    /// Retrieves what's known about the component besides its data, or `None` if it's been
+    /// destroyed.
+    pub fn get_component(&self, component: Handle) -> Option<&Component> {
+        self.components.get(component)
     }
 
-    /// Retrieves a reference to the [`Component`] with the given `id`.
+    /// Locks the [`Storage`] for components of type `T` for reading.
     ///
-    /// [`Component`]: struct.Component.html
-    pub fn get_component(&self, id: Index) -> &Component {
-        &self.components[id as usize]
+    /// # Errors
+    ///
+    /// Returns [`Error::ComponentType`] if `T` was never registered, or [`Error::Borrow`] if
+    /// the storage is locked for writing.
+    ///
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Error::ComponentType`]: ../error/enum.Error.html#variant.ComponentType
+    /// [`Storage`]: ../storage/struct.Storage.html
+    pub fn storage<T: Any + Send + Sync>(&self) -> Result<RwLockReadGuard<'_, Storage<T>>, Error> {
+        self.storage_lock::<T>()?
+            .try_read()
+            .map_err(|_| Error::Borrow(std::any::type_name::<T>()))
     }
 
-    /// Retrieves a mutable reference to the `Component` with the given `id`.
+    /// Locks the [`Storage`] for components of type `T` for writing.
     ///
-    /// [`Component`]: struct.Component.html
-    pub fn get_component_mut(&mut self, index: Index) -> &mut Component {
-        &mut self.components[index as usize]
+    /// # Errors
+    ///
+    /// Returns [`Error::ComponentType`] if `T` was never registered, or [`Error::Borrow`] if
+    /// the storage is already locked.
+    ///
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Error::ComponentType`]: ../error/enum.Error.html#variant.ComponentType
+    /// [`Storage`]: ../storage/struct.Storage.html
+    pub fn storage_mut<T: Any + Send + Sync>(
+        &self,
+    ) -> Result<RwLockWriteGuard<'_, Storage<T>>, Error> {
+        self.storage_lock::<T>()?
+            .try_write()
+            .map_err(|_| Error::Borrow(std::any::type_name::<T>()))
     }
 
-    /// Get `id`s for all [`Component`]s belonging to the given [`Entity`] that are of the specified type.
+    fn storage_lock<T: Any + Send + Sync>(&self) -> Result<&RwLock<Storage<T>>, Error> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref::<RwLock<Storage<T>>>())
+            .ok_or_else(|| Error::ComponentType(std::any::type_name::<T>().to_string()))
+    }
+
+    /// Get handles for all components belonging to the given [`Entity`] that are of type `T`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the [`Entity`] has been destroyed.
     ///
-    /// [`Component`]: struct.Component.html
     /// [`Entity`]: ../entity/struct.Entity.html
-    pub fn get_entity_components_of_type(
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn get_entity_components_of_type<T: Any>(
         &self,
-        entity_index: Index,
+        entity: Handle,
         entity_manager: &EntityManager,
-        component_type: &ComponentType,
-    ) -> Vec<Index> {
-        let entity = entity_manager.get_entity(entity_index);
-        entity
+    ) -> Result<Vec<Handle>, Error> {
+        let entity = entity_manager.get_entity(entity).ok_or(Error::Index())?;
+        Ok(entity
             .components
             .iter()
-            .filter(|index| self.components[**index as usize].component_type == *component_type)
+            .filter(|component| self.is_of_type(**component, TypeId::of::<T>()))
             .cloned()
-            .collect()
+            .collect())
     }
 
-    /// Get `id`s for all existing [`Component`]s of the given type.
+    /// Get handles for all existing components of type `T`.
     ///
-    /// [`Component`]: struct.Component.html
-    pub fn get_components_of_type(&self, component_type: ComponentType) -> Vec<Index> {
-        self.components
-            .iter()
-            .filter(|component| component.component_type == component_type)
-            .map(|component| component.id)
-            .collect()
+    /// # Errors
+    ///
+    /// See [`storage`](#method.storage).
+    pub fn get_components_of_type<T: Any + Send + Sync>(&self) -> Result<Vec<Handle>, Error> {
+        Ok(self.storage::<T>()?.handles().to_vec())
     }
 
-    /// Removes a [`Component`] from an [`Entity`].
+    /// Calls `each` for every [`Entity`] that has a component of every type in the query,
+    /// with the entity's handle and its components. If an [`Entity`] has more than one
+    /// component of a type, the first one added is used.
+    ///
+    /// ```ignore
+    /// component_manager.query::<(Write<Animation2D>, Read<Quad>), _>(&entities, |entity, (animation, quad)| {
+    ///     animation.layer = quad.layer;
+    /// })?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// See [`Query::lock`].
+    ///
+    /// [`Entity`]: ../entity/struct.Entity.html
+    /// [`Query::lock`]: ../storage/trait.Query.html#tymethod.lock
+    pub fn query<Q, F>(&self, entity_manager: &EntityManager, mut each: F) -> Result<(), Error>
+    where
+        Q: Query,
+        F: for<'l> FnMut(Handle, Q::Item<'l>),
+    {
+        let types = Q::types();
+        let first = match types.first() {
+            Some(first) => *first,
+            None => return Ok(()),
+        };
+
+        // Only entities with a component of the first type can match, so start from those.
+        // Entities with several components of that type are still only visited once.
+        let mut seen = HashSet::new();
+        let candidates = self
+            .storages
+            .get(&first)
+            .map(|storage| storage.handles())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|component| self.get_component(component)?.entity)
+            .filter(|entity| seen.insert(*entity))
+            .collect::<Vec<Handle>>();
+
+        let mut locks = Q::lock(self)?;
+        let mut matched = Vec::with_capacity(types.len());
+        for entity in candidates {
+            let owned = match entity_manager.get_entity(entity) {
+                Some(data) => &data.components,
+                None => continue,
+            };
+
+            matched.clear();
+            for type_id in &types {
+                match owned.iter().find(|component| self.is_of_type(**component, *type_id)) {
+                    Some(component) => matched.push(*component),
+                    None => break,
+                }
+            }
+            if matched.len() < types.len() {
+                continue;
+            }
+
+            if let Some(item) = Q::fetch(&mut locks, &mut matched.iter().cloned()) {
+                each(entity, item);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_of_type(&self, component: Handle, type_id: TypeId) -> bool {
+        match self.get_component(component) {
+            Some(component) => component.type_id == type_id,
+            None => false,
+        }
+    }
+
+    /// Removes a component from an [`Entity`]. The component itself isn't destroyed.
     ///
-    /// [`Component`]: struct.Component.html
     /// [`Entity`]: ../entity/struct.Entity.html
     pub fn remove_entity_component(
-        &self,
+        &mut self,
         entity_manager: &mut EntityManager,
-        entity_index: Index,
-        component_index: Index,
+        entity: Handle,
+        component: Handle,
     ) -> Result<(), Error> {
-        let mut index = 0;
-        let components: &mut Vec<Index> =
-            &mut entity_manager.get_entity_mut(entity_index).components;
-        while index < components.len() {
-            if components[index] == component_index {
+        let components = &mut entity_manager
+            .get_entity_mut(entity)
+            .ok_or(Error::Index())?
+            .components;
+        match components.iter().position(|owned| *owned == component) {
+            Some(index) => {
                 components.remove(index);
-                return Ok(());
+                if let Some(header) = self.components.get_mut(component) {
+                    header.entity = None;
+                }
+                Ok(())
             }
-            index += 1;
+            None => Err(Error::Index()),
         }
-        Err(Error::Index())
     }
 }
 
 pub trait ReceiveInput {
-    fn keyboard_response(&mut self, keycode: VirtualKeyCode) -> Result<(), Error>;
+    /// Responds to a key having been held down for `seconds` of game time.
+    fn keyboard_response(&mut self, keycode: VirtualKeyCode, seconds: f32) -> Result<(), Error>;
 }
 
-impl ReceiveInput for Component {
-    fn keyboard_response(&mut self, keycode: VirtualKeyCode) -> Result<(), Error> {
-        if let ComponentData::Animation2D {
+impl ReceiveInput for Animation2D {
+    fn keyboard_response(&mut self, keycode: VirtualKeyCode, seconds: f32) -> Result<(), Error> {
+        let Animation2D {
             movement,
             transform_data,
             ..
-        } = &mut self.component_data
-        {
-            match keycode {
-                VirtualKeyCode::Right => {
-                    transform_data.translation[0] += movement.delta_translate[0];
-                    transform_data.translation[1] += movement.delta_translate[1];
-                    transform_data.rotation += movement.delta_rotation;
-                    transform_data.scaling[0] += movement.delta_scale[0];
-                    transform_data.scaling[1] += movement.delta_scale[1];
-                    return Ok(());
-                }
-                VirtualKeyCode::Left => {
-                    transform_data.translation[0] -= movement.delta_translate[0];
-                    transform_data.translation[1] -= movement.delta_translate[1];
-                    transform_data.rotation -= movement.delta_rotation;
-                    transform_data.scaling[0] -= movement.delta_scale[0];
-                    transform_data.scaling[1] -= movement.delta_scale[1];
-                    return Ok(());
-                }
-                _ => {
-                    return Ok(());
-                }
-            }
-        }
+        } = self;
+        let direction = match keycode {
+            VirtualKeyCode::Right => 1.0,
+            VirtualKeyCode::Left => -1.0,
+            _ => return Ok(()),
+        };
+        let amount = direction * seconds;
+        transform_data.translation[0] += movement.delta_translate[0] * amount;
+        transform_data.translation[1] += movement.delta_translate[1] * amount;
+        transform_data.rotation += movement.delta_rotation * amount;
+        transform_data.scaling[0] += movement.delta_scale[0] * amount;
+        transform_data.scaling[1] += movement.delta_scale[1] * amount;
         Ok(())
     }
 }
 
 impl ReceiveInput for ComponentManager {
-    fn keyboard_response(&mut self, keycode: VirtualKeyCode) -> Result<(), Error> {
-        for component in &mut self.components {
-            component.keyboard_response(keycode).unwrap();
+    fn keyboard_response(&mut self, keycode: VirtualKeyCode, seconds: f32) -> Result<(), Error> {
+        for (_, animation) in self.storage_mut::<Animation2D>()?.iter_mut() {
+            animation.keyboard_response(keycode, seconds)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Read, Write};
+
+    #[test]
+    fn query_visits_each_matching_entity_once() {
+        let mut components = ComponentManager::new();
+        let mut entities = EntityManager::new();
+
+        // Two `u8`s and a `u16`: matches, with the first `u8`.
+        let both = entities.create_entity();
+        let first = components.create_component(1u8);
+        let second = components.create_component(2u8);
+        let speed = components.create_component(10u16);
+        // Missing the `u16`.
+        let only_u8 = entities.create_entity();
+        let lone_u8 = components.create_component(3u8);
+        // Missing the `u8`.
+        let only_u16 = entities.create_entity();
+        let lone_u16 = components.create_component(20u16);
+        // Not attached to anything.
+        components.create_component(4u8);
+
+        for (entity, component) in [
+            (both, first),
+            (both, second),
+            (both, speed),
+            (only_u8, lone_u8),
+            (only_u16, lone_u16),
+        ] {
+            components
+                .add_entity_component(entity, &mut entities, component)
+                .unwrap();
+        }
+
+        let mut visited = vec![];
+        components
+            .query::<(Write<u8>, Read<u16>), _>(&entities, |entity, (value, speed)| {
+                *value += *speed as u8;
+                visited.push(entity);
+            })
+            .unwrap();
+        assert_eq!(visited, vec![both]);
+
+        let values = components.storage::<u8>().unwrap();
+        assert_eq!(values.get(first), Some(&11));
+        assert_eq!(values.get(second), Some(&2));
+    }
+
+    #[test]
+    fn conflicting_queries_fail_to_lock() {
+        let components = ComponentManager::new();
+        let entities = EntityManager::new();
+        let result = components.query::<(Write<Quad>, Read<Quad>), _>(&entities, |_, _| {});
+        assert!(matches!(result, Err(Error::Borrow(_))));
+    }
+
+    #[test]
+    fn moved_components_outlive_their_old_owner() {
+        let mut components = ComponentManager::new();
+        let mut entities = EntityManager::new();
+        let old_owner = entities.create_entity();
+        let new_owner = entities.create_entity();
+        let component = components.create_component(1u8);
+
+        components
+            .add_entity_component(old_owner, &mut entities, component)
+            .unwrap();
+        components
+            .add_entity_component(new_owner, &mut entities, component)
+            .unwrap();
+        // Adding it again changes nothing.
+        components
+            .add_entity_component(new_owner, &mut entities, component)
+            .unwrap();
+        assert!(entities
+            .get_entity(old_owner)
+            .unwrap()
+            .components
+            .is_empty());
+        assert_eq!(
+            entities.get_entity(new_owner).unwrap().components,
+            vec![component]
+        );
+
+        entities.destroy_entity(old_owner, &mut components).unwrap();
+        assert_eq!(
+            components
+                .get_component(component)
+                .and_then(|header| header.entity),
+            Some(new_owner)
+        );
+        assert_eq!(components.storage::<u8>().unwrap().get(component), Some(&1));
+    }
+}
@@ -13,6 +13,8 @@ use crate::serial::Size;
 /// Global game configuration.
 pub struct Configuration {
     pub graphics: Graphics,
+    #[serde(default)]
+    pub simulation: Simulation,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -27,6 +29,23 @@ pub struct Window {
     pub size: Size,
 }
 
+#[derive(Deserialize, Serialize)]
+/// How often the game is updated, independently of how often it's drawn.
+pub struct Simulation {
+    pub updates_per_second: u32,
+    /// The most updates to run before drawing a frame, when the game has fallen behind.
+    pub max_updates_per_frame: u32,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self {
+            updates_per_second: 60,
+            max_updates_per_frame: 5,
+        }
+    }
+}
+
 impl Configuration {
     /// Reads game configuration data from `./config.yaml`.
     pub fn new() -> Result<Self, Error> {
@@ -45,6 +64,7 @@ impl Default for Configuration {
                     },
                 },
             },
+            simulation: Simulation::default(),
         }
     }
 }
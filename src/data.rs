@@ -8,17 +8,29 @@
 //!
 //! [`ResourceManager`]: struct.ResourceManager.html
 
-use log::debug;
+use log::{debug, error, warn};
 use serde::Deserialize;
 use std::fs;
 
 use crate::{
+    arena::Handle,
     component::ComponentManager,
+    dialogue::{Dialogue, DialogueData},
     draw::{Background, Spritesheet},
     entity::EntityManager,
     error::Error,
+    event::{
+        AnimationEvent, AnimationFinished, CollisionBegan, DialogueClosed, KeyPressed,
+        MapChanged, TweenFinished,
+    },
+    geometry::Camera,
+    prefab::{PrefabInstance, Prefabs},
     resource::ResourceManager,
+    save::SaveGame,
     serial::{Index, Size},
+    system::{Input, Resources, World},
+    time::GameClock,
+    validate::{validate, Sources},
 };
 
 // YAML is used for (de-)serialization because the `serde-toml` crate seemed to have
@@ -48,7 +60,20 @@ pub struct GameMap {
     pub index: Index,
     pub name: String,
     pub size: Size,
-    pub entities: Vec<Index>,
+    /// The map's entities, including the ones created from `prefabs` once they've been
+    /// created.
+    pub entities: Vec<Handle>,
+    /// Entities to create from [`Prefab`]s when the map is loaded.
+    ///
+    /// [`Prefab`]: ../prefab/struct.Prefab.html
+    #[serde(default)]
+    pub prefabs: Vec<PrefabInstance>,
+    /// (Optional) The index of a [`DialogueScript`] to show when the player arrives on the
+    /// map, or when the game starts if it's the first map.
+    ///
+    /// [`DialogueScript`]: ../dialogue/struct.DialogueScript.html
+    #[serde(default)]
+    pub dialogue: Option<Index>,
 }
 
 #[derive(Deserialize)]
@@ -84,9 +109,27 @@ pub struct DataManager<'a> {
     pub resource_manager: ResourceManager<'a>,
     pub component_manager: ComponentManager,
     pub entity_manager: EntityManager,
+    pub prefabs: Prefabs,
+    /// Data for [`System`]s that isn't attached to any entity. Starts out with [`Input`],
+    /// the [`Camera`], and [`Events`] queues for [`KeyPressed`], [`DialogueClosed`] and
+    /// [`MapChanged`].
+    ///
+    /// [`Camera`]: ../geometry/struct.Camera.html
+    /// [`DialogueClosed`]: ../event/struct.DialogueClosed.html
+    /// [`Events`]: ../event/struct.Events.html
+    /// [`Input`]: ../system/struct.Input.html
+    /// [`KeyPressed`]: ../event/struct.KeyPressed.html
+    /// [`MapChanged`]: ../event/struct.MapChanged.html
+    /// [`System`]: ../system/trait.System.html
+    pub resources: Resources,
     pub backgrounds: Vec<Background>,
     pub maps: Vec<GameMap>,
+    /// The index of the map the player is on. Starts out as the first map.
+    pub current_map: Index,
     pub spritesheets: Vec<Spritesheet>,
+    pub dialogue: DialogueData,
+    /// The dialogue currently on screen, if any.
+    pub active_dialogue: Option<Dialogue>,
 }
 
 impl<'a> DataManager<'a> {
@@ -95,7 +138,9 @@ impl<'a> DataManager<'a> {
     /// Data is loaded from the following files:
     ///
     /// + [`Background`]s: `./data/backgrounds.yaml`
+    /// + Dialogue windows and scripts ([`DialogueData`]): `./data/dialogue.yaml`
     /// + [`GameMap`]s: `./data/maps.yaml`
+    /// + [`Prefab`]s: `./data/prefabs.yaml`
     /// + [`Spritesheet`]s: `./data/spritesheets.yaml`
     ///
     /// See the individual structs to see what fields they have and whether they're optional.
@@ -107,22 +152,264 @@ impl<'a> DataManager<'a> {
     ///
     /// If any of the expected files are absent or malformed, [`Error::SerdeYaml`] will be returned.
     ///
+    /// Once everything is loaded, it's checked for references to things that don't exist
+    /// and the like by [`validate`]. If anything's wrong, [`Error::Validation`] is returned
+    /// with a line for each problem.
+    ///
+    /// Maps' prefab instances that still can't be created return the error from
+    /// [`EntityManager::spawn_prefab`].
+    ///
     /// [`Background`]: ../draw/struct.Background.html
+    /// [`DialogueData`]: ../dialogue/struct.DialogueData.html
+    /// [`EntityManager::spawn_prefab`]: ../entity/struct.EntityManager.html#method.spawn_prefab
     /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    /// [`Error::Validation`]: ../error/enum.Error.html#variant.Validation
     /// [`GameMap`]: struct.GameMap.html
+    /// [`Prefab`]: ../prefab/struct.Prefab.html
     /// [`Spritesheet`]: ../draw/struct.Spritesheet.html
+    /// [`validate`]: ../validate/fn.validate.html
     pub fn new() -> Result<DataManager<'a>, Error> {
+        DataManager::with_component_types(|_| {})
+    }
+
+    /// Creates a new `DataManager` like [`new`](#method.new), but calls `register` before
+    /// loading components so it can register the game's own component types.
+    ///
+    /// ```ignore
+    /// let data = DataManager::with_component_types(|components| {
+    ///     components.register_named::<Health>("Health");
+    /// })?;
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// See [`new`](#method.new).
+    pub fn with_component_types<F: FnOnce(&mut ComponentManager)>(
+        register: F,
+    ) -> Result<DataManager<'a>, Error> {
+        let mut entity_manager = EntityManager::load_entities()?;
+        let mut component_manager = ComponentManager::new();
+        register(&mut component_manager);
+        let component_handles =
+            component_manager.load(&fs::read_to_string("./data/components.yaml")?)?;
+        component_manager.link_entities(&entity_manager);
+
+        let resource_manager = ResourceManager::load_resources()?;
         debug!("Loading backgrounds, maps, and spritesheets...");
-        let new_data = DataManager {
+        let backgrounds: Vec<Background> =
+            serde_yaml::from_str(&fs::read_to_string("./data/backgrounds.yaml")?)?;
+        let spritesheets = Spritesheet::load_spritesheets()?;
+        let mut maps: Vec<GameMap> =
+            serde_yaml::from_str(&fs::read_to_string("./data/maps.yaml")?)?;
+        let prefabs = Prefabs::load()?;
+        let dialogue = DialogueData::load()?;
+
+        debug!("Validating data...");
+        let problems = validate(&Sources {
+            entities: &entity_manager,
+            components: &component_manager,
+            component_handles: &component_handles,
+            textures: &resource_manager.textures,
+            spritesheets: &spritesheets,
+            backgrounds: &backgrounds,
+            maps: &maps,
+            prefabs: &prefabs,
+            dialogue: &dialogue,
+        });
+        if !problems.is_empty() {
+            for problem in &problems {
+                error!("{}", problem);
+            }
+            return Err(Error::Validation(
+                problems.iter().map(|problem| problem.to_string()).collect(),
+            ));
+        }
+
+        for handle in &component_handles {
+            component_manager.add_spritesheet_animations(*handle, &spritesheets)?;
+        }
+        for map in &mut maps {
+            for instance in &map.prefabs {
+                let entity = entity_manager.spawn_prefab(
+                    &prefabs,
+                    instance,
+                    &mut component_manager,
+                    &spritesheets,
+                )?;
+                map.entities.push(entity);
+            }
+        }
+
+        let mut resources = Resources::new();
+        resources.insert(Input::default());
+        resources.insert(Camera::default());
+        resources.insert(GameClock::new());
+        resources.add_events::<AnimationEvent>();
+        resources.add_events::<AnimationFinished>();
+        resources.add_events::<CollisionBegan>();
+        resources.add_events::<KeyPressed>();
+        resources.add_events::<DialogueClosed>();
+        resources.add_events::<MapChanged>();
+        resources.add_events::<TweenFinished>();
+
+        let mut new_data = DataManager {
             game_data: GameData::new()?,
-            resource_manager: ResourceManager::load_resources()?,
-            component_manager: ComponentManager::load_components()?,
-            entity_manager: EntityManager::load_entities()?,
-            backgrounds: serde_yaml::from_str(&fs::read_to_string("./data/backgrounds.yaml")?)?,
-            maps: serde_yaml::from_str(&fs::read_to_string("./data/maps.yaml")?)?,
-            spritesheets: serde_yaml::from_str(&fs::read_to_string("./data/spritesheets.yaml")?)?,
+            resource_manager,
+            component_manager,
+            entity_manager,
+            prefabs,
+            resources,
+            backgrounds,
+            maps,
+            current_map: 0,
+            spritesheets,
+            dialogue,
+            active_dialogue: None,
         };
+        // A dialogue that can't be shown, e.g. because its font is missing, shouldn't stop
+        // the game from starting.
+        if let Err(err) = new_data.start_map_dialogue() {
+            warn!("Couldn't start the first map's dialogue: {}", err);
+        }
 
         Ok(new_data)
     }
+
+    /// The parts of the game data that [`System`]s can see.
+    ///
+    /// [`System`]: ../system/trait.System.html
+    pub fn world(&self) -> World<'_> {
+        World {
+            components: &self.component_manager,
+            entities: &self.entity_manager,
+            resources: &self.resources,
+        }
+    }
+
+    /// Takes a snapshot of the game world.
+    ///
+    /// # Errors
+    ///
+    /// See [`ComponentManager::snapshot`]. Also returns [`Error::Resource`] if the
+    /// [`Camera`] or [`GameClock`] has been removed from the resources, or [`Error::Borrow`]
+    /// if either is locked for writing.
+    ///
+    /// [`Camera`]: ../geometry/struct.Camera.html
+    /// [`GameClock`]: ../time/struct.GameClock.html
+    /// [`ComponentManager::snapshot`]: ../component/struct.ComponentManager.html#method.snapshot
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Error::Resource`]: ../error/enum.Error.html#variant.Resource
+    pub fn save_game(&self) -> Result<SaveGame, Error> {
+        Ok(SaveGame {
+            current_map: self.current_map,
+            camera: self.resources.get::<Camera>()?.clone(),
+            clock: self.resources.get::<GameClock>()?.clone(),
+            entities: self.entity_manager.snapshot(),
+            components: self.component_manager.snapshot()?,
+        })
+    }
+
+    /// Replaces the game world with a snapshot from [`save_game`]. If this fails, the world
+    /// is left as it was.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the saved map doesn't exist, and the errors from
+    /// [`ComponentManager::restored`] and [`EntityManager::restore`] if the components or
+    /// entities can't be restored. If the world
+    /// is restored but the saved map is a different one, see [`change_map`].
+    ///
+    /// [`change_map`]: #method.change_map
+    /// [`save_game`]: #method.save_game
+    /// [`ComponentManager::restored`]: ../component/struct.ComponentManager.html#method.restored
+    /// [`EntityManager::restore`]: ../entity/struct.EntityManager.html#method.restore
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn load_game(&mut self, save: SaveGame) -> Result<(), Error> {
+        if save.current_map >= self.maps.len() {
+            return Err(Error::Index());
+        }
+        let component_manager = self.component_manager.restored(save.components)?;
+        self.entity_manager.restore(save.entities)?;
+        self.component_manager = component_manager;
+        self.resources.insert(save.camera);
+        self.resources.insert(save.clock);
+        self.change_map(save.current_map)
+    }
+
+    /// Moves the player to another map, sending [`MapChanged`] and starting the map's
+    /// dialogue if it isn't the one they're already on.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the map doesn't exist, the errors from
+    /// [`start_dialogue`] if the map's dialogue can't be shown, or the errors from
+    /// [`Resources::send`] if the event can't be sent. The map is changed either way in the
+    /// last two cases.
+    ///
+    /// [`start_dialogue`]: #method.start_dialogue
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    /// [`MapChanged`]: ../event/struct.MapChanged.html
+    /// [`Resources::send`]: ../system/struct.Resources.html#method.send
+    pub fn change_map(&mut self, map: Index) -> Result<(), Error> {
+        if map >= self.maps.len() {
+            return Err(Error::Index());
+        }
+        let from = self.current_map;
+        self.current_map = map;
+        if from != map {
+            self.resources.send(MapChanged { from, to: map })?;
+            self.start_map_dialogue()?;
+        }
+        Ok(())
+    }
+
+    /// Opens the current map's dialogue, if it has one.
+    fn start_map_dialogue(&mut self) -> Result<(), Error> {
+        match self.maps[self.current_map].dialogue {
+            Some(script) => self.start_dialogue(script),
+            None => Ok(()),
+        }
+    }
+
+    /// Opens a dialogue window showing the given script, replacing any dialogue already open.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the script, its window, or the window's font family
+    /// doesn't exist.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn start_dialogue(&mut self, script: Index) -> Result<(), Error> {
+        let window = self
+            .dialogue
+            .scripts
+            .get(script)
+            .and_then(|script| self.dialogue.windows.get(script.window))
+            .ok_or(Error::Index())?;
+        let fonts = self.resource_manager.font_stack(&window.font)?;
+
+        self.active_dialogue = Some(Dialogue::new(script, &self.dialogue, fonts)?);
+        Ok(())
+    }
+
+    /// Advances the open dialogue, if there is one, closing it after its last page and
+    /// sending [`DialogueClosed`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors from [`Resources::send`] if the event can't be sent. The dialogue
+    /// is closed either way.
+    ///
+    /// [`DialogueClosed`]: ../event/struct.DialogueClosed.html
+    /// [`Resources::send`]: ../system/struct.Resources.html#method.send
+    pub fn advance_dialogue(&mut self) -> Result<(), Error> {
+        if let Some(dialogue) = &mut self.active_dialogue {
+            if !dialogue.advance() {
+                let script = dialogue.script;
+                self.active_dialogue = None;
+                self.resources.send(DialogueClosed { script })?;
+            }
+        }
+        Ok(())
+    }
 }
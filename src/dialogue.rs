@@ -0,0 +1,943 @@
+// dialogue.rs
+// Dialogue boxes with typewriter-style text.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Dialogue windows that reveal their text a character at a time.
+//!
+//! A [`DialogueScript`] is a list of lines, each of which may name a speaker and show a
+//! portrait. Scripts are shown in a [`DialogueWindow`], which describes the window's frame,
+//! name plate, and text settings. While a script is being shown, its progress is tracked
+//! by a [`Dialogue`].
+//!
+//! Lines that don't fit in the window are split into pages. Each page is revealed at the
+//! window's `characters_per_second`, stopping for any `[pause]` tags in the text.
+//!
+//! [`Dialogue`]: struct.Dialogue.html
+//! [`DialogueScript`]: struct.DialogueScript.html
+//! [`DialogueWindow`]: struct.DialogueWindow.html
+
+use image::{Rgba, RgbaImage};
+use log::debug;
+use serde::Deserialize;
+use std::{fs, ops::Range};
+
+use crate::{
+    draw::Spritesheet,
+    error::Error,
+    font::{FaceGlyph, FontStack},
+    geometry::Rect,
+    markup::{RichText, TextRun, TextStyle},
+    sdf::SdfAtlas,
+    serial::{Color, Index, Size},
+    text::{self, DistanceFieldText, IconSheet, Reveal, SdfQuad, TextLayout, TextRendering},
+    texture::Texture,
+};
+
+/// The widths of the edges of a [`NineSlice`] in texels.
+///
+/// [`NineSlice`]: struct.NineSlice.html
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Border {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+/// A region of a texture that can be stretched to any size without distorting its edges.
+///
+/// The region is cut into a 3x3 grid by its [`Border`]. The corners are drawn at their
+/// original size, the edges are stretched along their length, and the center is stretched
+/// to fill whatever space is left.
+///
+/// [`Border`]: struct.Border.html
+#[derive(Deserialize)]
+pub struct NineSlice {
+    pub texture_index: Index,
+    /// The part of the texture containing the frame, in texels.
+    pub region: Rect,
+    pub border: Border,
+}
+
+impl NineSlice {
+    /// Pairs each piece of the source region with the part of `target` it should cover,
+    /// as (source, destination).
+    pub fn slices(&self, target: Rect) -> Vec<(Rect, Rect)> {
+        let source = self.region;
+        let border = self.border;
+
+        // If the target is too small for the borders, shrink them evenly.
+        let left = border.left.min(target.size.x / 2.0);
+        let right = border.right.min(target.size.x / 2.0);
+        let top = border.top.min(target.size.y / 2.0);
+        let bottom = border.bottom.min(target.size.y / 2.0);
+
+        // (source start, source length, destination start, destination length)
+        let columns = [
+            (source.position.x, border.left, target.position.x, left),
+            (
+                source.position.x + border.left,
+                source.size.x - border.left - border.right,
+                target.position.x + left,
+                target.size.x - left - right,
+            ),
+            (
+                source.position.x + source.size.x - border.right,
+                border.right,
+                target.position.x + target.size.x - right,
+                right,
+            ),
+        ];
+        let rows = [
+            (source.position.y, border.top, target.position.y, top),
+            (
+                source.position.y + border.top,
+                source.size.y - border.top - border.bottom,
+                target.position.y + top,
+                target.size.y - top - bottom,
+            ),
+            (
+                source.position.y + source.size.y - border.bottom,
+                border.bottom,
+                target.position.y + target.size.y - bottom,
+                bottom,
+            ),
+        ];
+
+        let mut slices = vec![];
+        for row in &rows {
+            for column in &columns {
+                if column.1 <= 0.0 || row.1 <= 0.0 || column.3 <= 0.0 || row.3 <= 0.0 {
+                    continue;
+                }
+                slices.push((
+                    Rect::new(column.0, row.0, column.1, row.1),
+                    Rect::new(column.2, row.2, column.3, row.3),
+                ));
+            }
+        }
+        slices
+    }
+
+    /// Draws the frame from `source` so that it fills `target` on the canvas.
+    pub fn draw(&self, source: &RgbaImage, canvas: &mut RgbaImage, target: Rect) {
+        for (from, to) in self.slices(target) {
+            blit(source, from, canvas, to);
+        }
+    }
+}
+
+/// The box above a [`DialogueWindow`] showing who is speaking.
+///
+/// [`DialogueWindow`]: struct.DialogueWindow.html
+#[derive(Deserialize)]
+pub struct NamePlate {
+    pub frame: NineSlice,
+    /// The distance from the left edge of the window to the plate, in pixels.
+    pub offset: f32,
+    /// The plate's height in pixels. The plate overlaps the top half of the window's frame.
+    pub height: f32,
+    /// The space between the plate's edges and the name, in pixels.
+    pub padding: f32,
+}
+
+impl NamePlate {
+    /// The part of the window the plate covers, given the size of the name on it.
+    fn area(&self, text_size: Size) -> Rect {
+        Rect::new(
+            self.offset,
+            0.0,
+            text_size.x + self.padding * 2.0,
+            self.height,
+        )
+    }
+
+    /// Where the name goes, given its size.
+    fn text_area(&self, text_size: Size) -> Rect {
+        Rect::new(
+            self.offset + self.padding,
+            (self.height - text_size.y) / 2.0,
+            text_size.x,
+            text_size.y,
+        )
+    }
+}
+
+/// Where to find the pictures for `[icon]` tags.
+#[derive(Deserialize)]
+pub struct IconSource {
+    pub texture_index: Index,
+    pub spritesheet_index: Index,
+}
+
+/// The appearance and behavior of a dialogue box.
+#[derive(Deserialize)]
+pub struct DialogueWindow {
+    pub index: Index,
+    /// Where the window appears on screen, in pixels from the top-left corner.
+    pub bounds: Rect,
+    /// The blank [`Texture`] the window is drawn into. It must be the same size as `bounds`.
+    ///
+    /// [`Texture`]: ../texture/struct.Texture.html
+    pub texture_index: Index,
+    pub frame: NineSlice,
+    pub name_plate: NamePlate,
+    /// The space between the frame's edges and its contents, in pixels.
+    pub padding: f32,
+    /// The name of the [`FontFamily`] the text is drawn in.
+    ///
+    /// [`FontFamily`]: ../font/struct.FontFamily.html
+    pub font: String,
+    /// The height of the text in pixels.
+    pub text_height: f32,
+    /// The color of text that isn't colored by markup.
+    pub text_color: Color,
+    /// How quickly text is revealed.
+    pub characters_per_second: f32,
+    /// (Optional) How the text is drawn. Defaults to coverage.
+    #[serde(default)]
+    pub rendering: TextRendering,
+    /// (Optional) The blank [`Texture`] the distance-field atlas is uploaded to, so that
+    /// distance-field text is drawn by the `sdf.frag` shader. Without one, distance-field
+    /// text is drawn into the window on the CPU.
+    ///
+    /// [`Texture`]: ../texture/struct.Texture.html
+    #[serde(default)]
+    pub atlas_texture_index: Option<Index>,
+    /// (Optional) Where to find the pictures for `[icon]` tags.
+    #[serde(default)]
+    pub icons: Option<IconSource>,
+}
+
+impl DialogueWindow {
+    /// The part of the window covered by the frame. The name plate sits above it.
+    fn frame_area(&self) -> Rect {
+        let top = self.name_plate.height / 2.0;
+        Rect::new(0.0, top, self.bounds.size.x, self.bounds.size.y - top)
+    }
+
+    /// The inside of the frame.
+    fn content_area(&self) -> Rect {
+        let frame = self.frame_area();
+        Rect::new(
+            frame.position.x + self.padding,
+            frame.position.y + self.padding,
+            frame.size.x - self.padding * 2.0,
+            frame.size.y - self.padding * 2.0,
+        )
+    }
+
+    /// The square at the left of the frame where a portrait is drawn.
+    fn portrait_area(&self) -> Rect {
+        let content = self.content_area();
+        Rect::new(
+            content.position.x,
+            content.position.y,
+            content.size.y,
+            content.size.y,
+        )
+    }
+
+    /// The part of the window that text is drawn in, which is narrower if there's a portrait.
+    pub fn text_area(&self, has_portrait: bool) -> Rect {
+        let content = self.content_area();
+        if has_portrait {
+            let indent = content.size.y + self.padding;
+            Rect::new(
+                content.position.x + indent,
+                content.position.y,
+                content.size.x - indent,
+                content.size.y,
+            )
+        } else {
+            content
+        }
+    }
+}
+
+/// A single frame of a spritesheet shown beside a line of dialogue.
+#[derive(Deserialize)]
+pub struct Portrait {
+    pub texture_index: Index,
+    pub spritesheet_index: Index,
+    pub frame: u16,
+}
+
+/// One line of a [`DialogueScript`].
+///
+/// [`DialogueScript`]: struct.DialogueScript.html
+#[derive(Deserialize)]
+pub struct DialogueLine {
+    /// (Optional) The name shown on the name plate.
+    #[serde(default)]
+    pub speaker: Option<String>,
+    /// (Optional) The picture shown beside the text.
+    #[serde(default)]
+    pub portrait: Option<Portrait>,
+    /// The text to show, which may contain [markup].
+    ///
+    /// [markup]: ../markup/index.html
+    pub text: String,
+}
+
+/// A conversation to be shown in a [`DialogueWindow`].
+///
+/// [`DialogueWindow`]: struct.DialogueWindow.html
+#[derive(Deserialize)]
+pub struct DialogueScript {
+    pub index: Index,
+    pub name: String,
+    /// The index of the [`DialogueWindow`] to show the script in.
+    ///
+    /// [`DialogueWindow`]: struct.DialogueWindow.html
+    pub window: Index,
+    pub lines: Vec<DialogueLine>,
+}
+
+/// All dialogue windows and scripts.
+#[derive(Deserialize)]
+pub struct DialogueData {
+    pub windows: Vec<DialogueWindow>,
+    pub scripts: Vec<DialogueScript>,
+}
+
+impl DialogueData {
+    /// Loads dialogue windows and scripts from `./data/dialogue.yaml`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file can't be read or [`Error::SerdeYaml`] if it's malformed.
+    ///
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn load() -> Result<DialogueData, Error> {
+        debug!("Loading dialogue...");
+        Ok(serde_yaml::from_str(&fs::read_to_string(
+            "./data/dialogue.yaml",
+        )?)?)
+    }
+}
+
+/// A group of lines of text that fit in the window together.
+struct Page {
+    lines: Range<usize>,
+    reveal: Reveal,
+    /// Whether any of the page's text moves by itself.
+    animated: bool,
+}
+
+/// What a [`Dialogue`]'s window shows at some moment. Moments with the same appearance look
+/// exactly alike, so the window only has to be drawn again when its appearance changes.
+///
+/// [`Dialogue`]: struct.Dialogue.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Appearance {
+    pub script: Index,
+    pub line: usize,
+    pub page: usize,
+    /// How many characters and icons of the page are showing, or `None` if all of them are.
+    pub revealed: Option<usize>,
+    /// How long the page has been showing, if any of its text moves by itself.
+    pub effect_time: Option<u64>,
+}
+
+/// The progress of a [`DialogueScript`] being shown.
+///
+/// [`DialogueScript`]: struct.DialogueScript.html
+pub struct Dialogue {
+    /// The index of the [`DialogueScript`] being shown.
+    ///
+    /// [`DialogueScript`]: struct.DialogueScript.html
+    pub script: Index,
+    /// The parsed text of each line of the script.
+    text: Vec<RichText>,
+    /// The pages each line of the script is split into.
+    pages: Vec<Vec<Page>>,
+    /// Distance fields for every glyph in the script, if the window draws text with them.
+    atlas: Option<SdfAtlas>,
+    line: usize,
+    page: usize,
+    /// Milliseconds since the current page started being revealed.
+    elapsed: u64,
+    /// Whether the reader skipped to the end of the current page.
+    skipped: bool,
+}
+
+impl Dialogue {
+    /// Starts showing the given script from its first line.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the script or its window doesn't exist or the script has
+    /// no lines, or [`Error::Markup`] if any line's markup is malformed.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    /// [`Error::Markup`]: ../error/enum.Error.html#variant.Markup
+    pub fn new(script: Index, data: &DialogueData, fonts: &FontStack) -> Result<Dialogue, Error> {
+        let (script_data, window) = Dialogue::script_and_window(script, data)?;
+        if script_data.lines.is_empty() {
+            return Err(Error::Index());
+        }
+
+        let mut text = vec![];
+        let mut pages = vec![];
+        let mut layouts = vec![];
+        for line in &script_data.lines {
+            let rich_text = RichText::parse(&line.text, window.text_color)?;
+            let area = window.text_area(line.portrait.is_some());
+            let layout =
+                text::layout_rich_text(&rich_text, fonts, window.text_height, Some(area.size.x));
+
+            let lines_per_page = ((area.size.y / layout.line_height) as usize).max(1);
+            pages.push(
+                (0..layout.lines)
+                    .step_by(lines_per_page)
+                    .map(|start| {
+                        let lines = start..(start + lines_per_page).min(layout.lines);
+                        let page = layout.page(lines.clone());
+                        Page {
+                            reveal: Reveal::new(&page, window.characters_per_second),
+                            animated: page.is_animated(),
+                            lines,
+                        }
+                    })
+                    .collect(),
+            );
+            text.push(rich_text);
+            layouts.push(layout);
+            if let Some(speaker) = &line.speaker {
+                layouts.push(text::layout_rich_text(
+                    &speaker_text(speaker, window),
+                    fonts,
+                    window.text_height,
+                    None,
+                ));
+            }
+        }
+
+        let atlas = match &window.rendering {
+            TextRendering::Coverage => None,
+            // The fields have to reach as far as the effects do.
+            TextRendering::DistanceField(effects) => Some(SdfAtlas::from_layouts(
+                &layouts,
+                effects.extent().ceil() + 1.0,
+            )),
+        };
+
+        Ok(Dialogue {
+            script,
+            text,
+            pages,
+            atlas,
+            line: 0,
+            page: 0,
+            elapsed: 0,
+            skipped: false,
+        })
+    }
+
+    fn script_and_window(
+        script: Index,
+        data: &DialogueData,
+    ) -> Result<(&DialogueScript, &DialogueWindow), Error> {
+        let script = data.scripts.get(script).ok_or(Error::Index())?;
+        let window = data.windows.get(script.window).ok_or(Error::Index())?;
+        Ok((script, window))
+    }
+
+    /// The index of the line currently being shown.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The page of the current line being shown.
+    pub fn page(&self) -> usize {
+        self.page
+    }
+
+    /// Moves the reveal forward by `delta` milliseconds.
+    pub fn update(&mut self, delta: u64) {
+        self.elapsed += delta;
+    }
+
+    /// Whether all of the current page's text is showing.
+    pub fn is_page_revealed(&self) -> bool {
+        self.skipped || self.elapsed >= self.pages[self.line][self.page].reveal.duration()
+    }
+
+    /// What the window shows at the moment.
+    pub fn appearance(&self) -> Appearance {
+        let page = &self.pages[self.line][self.page];
+        Appearance {
+            script: self.script,
+            line: self.line,
+            page: self.page,
+            revealed: if self.is_page_revealed() {
+                None
+            } else {
+                Some(page.reveal.revealed_length(self.elapsed))
+            },
+            effect_time: if page.animated {
+                Some(self.elapsed)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Shows the rest of the current page immediately.
+    pub fn skip(&mut self) {
+        self.skipped = true;
+    }
+
+    /// Responds to the reader pressing the advance key. If the current page is still being
+    /// revealed, the rest of it is shown; otherwise, the next page or line begins.
+    ///
+    /// Returns `false` once the last page of the last line has been dismissed.
+    pub fn advance(&mut self) -> bool {
+        if !self.is_page_revealed() {
+            self.skip();
+            return true;
+        }
+
+        if self.page + 1 < self.pages[self.line].len() {
+            self.page += 1;
+        } else if self.line + 1 < self.pages.len() {
+            self.line += 1;
+            self.page = 0;
+        } else {
+            return false;
+        }
+        self.elapsed = 0;
+        self.skipped = false;
+        true
+    }
+
+    /// The current page laid out in the window, and where in the window it goes.
+    fn page_layout<'a>(
+        &self,
+        window: &DialogueWindow,
+        line: &DialogueLine,
+        fonts: &FontStack<'a>,
+    ) -> (TextLayout<'a>, Rect) {
+        let area = window.text_area(line.portrait.is_some());
+        let layout = text::layout_rich_text(
+            &self.text[self.line],
+            fonts,
+            window.text_height,
+            Some(area.size.x),
+        )
+        .page(self.pages[self.line][self.page].lines.clone());
+        (layout, area)
+    }
+
+    /// The current speaker's name laid out on the name plate, and where in the window it
+    /// goes, if the current line has a speaker.
+    fn speaker_layout<'a>(
+        window: &DialogueWindow,
+        line: &DialogueLine,
+        fonts: &FontStack<'a>,
+    ) -> Option<(TextLayout<'a>, Rect)> {
+        let speaker = line.speaker.as_ref()?;
+        let layout = text::layout_rich_text(
+            &speaker_text(speaker, window),
+            fonts,
+            window.text_height,
+            None,
+        );
+        let area = window.name_plate.text_area(layout.size);
+        Some((layout, area))
+    }
+
+    /// The texture the window's distance-field atlas is uploaded to, if it has one and
+    /// draws text from distance fields.
+    pub fn atlas_texture(&self, window: &DialogueWindow) -> Option<Index> {
+        match (&window.rendering, &self.atlas) {
+            (TextRendering::DistanceField(_), Some(_)) => window.atlas_texture_index,
+            _ => None,
+        }
+    }
+
+    /// Distance fields for every glyph in the script, if the window draws text with them.
+    pub fn atlas(&self) -> Option<&SdfAtlas> {
+        self.atlas.as_ref()
+    }
+
+    /// Places the glyphs that the shader draws from the window's [`atlas_texture`], as
+    /// much of the current page as has been revealed and the speaker's name, in pixels from
+    /// the window's top-left corner. There are none if the window has no atlas texture.
+    ///
+    /// [`atlas_texture`]: #method.atlas_texture
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the script or its window doesn't exist.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn distance_field_quads(
+        &self,
+        data: &DialogueData,
+        fonts: &FontStack,
+    ) -> Result<Vec<SdfQuad>, Error> {
+        let (script, window) = Dialogue::script_and_window(self.script, data)?;
+        let line = &script.lines[self.line];
+        let atlas = match (self.atlas_texture(window), &self.atlas) {
+            (Some(_), Some(atlas)) => atlas,
+            _ => return Ok(vec![]),
+        };
+
+        let (layout, area) = self.page_layout(window, line, fonts);
+        let visible = self.appearance().revealed;
+        let mut quads = placed(
+            text::distance_field_quads(&layout, atlas, self.elapsed, visible),
+            area,
+        );
+        if let Some((layout, area)) = Dialogue::speaker_layout(window, line, fonts) {
+            quads.extend(placed(
+                text::distance_field_quads(&layout, atlas, self.elapsed, None),
+                area,
+            ));
+        }
+        Ok(quads)
+    }
+
+    /// Draws the window with the current page as much of it has been revealed. The picture
+    /// only changes when the [`appearance`] does.
+    ///
+    /// [`appearance`]: #method.appearance
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the window refers to textures or spritesheets that don't
+    /// exist, or [`Error::None`] if their image data hasn't been loaded.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    /// [`Error::None`]: ../error/enum.Error.html#variant.None
+    pub fn compose<'a>(
+        &self,
+        data: &DialogueData,
+        fonts: &FontStack<'a>,
+        textures: &[Texture],
+        spritesheets: &[Spritesheet],
+    ) -> Result<RgbaImage, Error> {
+        let (script, window) = Dialogue::script_and_window(self.script, data)?;
+        let line = &script.lines[self.line];
+        let texture_data =
+            |index: Index| textures.get(index).ok_or(Error::Index())?.get_data();
+
+        let mut canvas = RgbaImage::new(window.bounds.size.x as u32, window.bounds.size.y as u32);
+        window.frame.draw(
+            texture_data(window.frame.texture_index)?,
+            &mut canvas,
+            window.frame_area(),
+        );
+
+        if let Some(portrait) = &line.portrait {
+            let spritesheet = spritesheets
+                .get(portrait.spritesheet_index)
+                .ok_or(Error::Index())?;
+            blit(
+                texture_data(portrait.texture_index)?,
+                spritesheet.frame_rect(portrait.frame),
+                &mut canvas,
+                window.portrait_area(),
+            );
+        }
+
+        // Text drawn by the shader is left out of the window's picture.
+        let on_gpu = self.atlas_texture(window).is_some();
+        let distance_field = match (&window.rendering, &self.atlas) {
+            (TextRendering::DistanceField(effects), Some(atlas)) if !on_gpu => {
+                Some(DistanceFieldText { atlas, effects })
+            }
+            _ => None,
+        };
+        let prepare = |mut layout: TextLayout<'a>| {
+            if on_gpu {
+                layout.glyphs.retain(|styled| match styled.glyph {
+                    FaceGlyph::Outline(_) => false,
+                    FaceGlyph::Bitmap { .. } => true,
+                });
+            }
+            layout
+        };
+
+        // Reveal the page a character at a time.
+        let (layout, area) = self.page_layout(window, line, fonts);
+        let layout = prepare(layout);
+        let visible = self.appearance().revealed;
+        let icons = match &window.icons {
+            Some(icons) => Some(IconSheet {
+                image: texture_data(icons.texture_index)?,
+                spritesheet: spritesheets
+                    .get(icons.spritesheet_index)
+                    .ok_or(Error::Index())?,
+            }),
+            None => None,
+        };
+        draw_text(
+            &mut canvas,
+            &layout,
+            distance_field.as_ref(),
+            text::render_rich_text(
+                &layout,
+                icons.as_ref(),
+                distance_field.as_ref(),
+                self.elapsed,
+                visible,
+            )?,
+            area,
+        );
+
+        if let Some((layout, area)) = Dialogue::speaker_layout(window, line, fonts) {
+            let plate = &window.name_plate;
+            let layout = prepare(layout);
+            plate.frame.draw(
+                texture_data(plate.frame.texture_index)?,
+                &mut canvas,
+                plate.area(layout.size),
+            );
+            draw_text(
+                &mut canvas,
+                &layout,
+                distance_field.as_ref(),
+                text::render_rich_text(
+                    &layout,
+                    None,
+                    distance_field.as_ref(),
+                    self.elapsed,
+                    None,
+                )?,
+                area,
+            );
+        }
+
+        Ok(canvas)
+    }
+}
+
+/// Moves quads placed relative to the corner of `area` to be relative to the window.
+fn placed(mut quads: Vec<SdfQuad>, area: Rect) -> Vec<SdfQuad> {
+    for quad in &mut quads {
+        for corner in &mut quad.corners {
+            corner.x += area.position.x;
+            corner.y += area.position.y;
+        }
+    }
+    quads
+}
+
+/// The text shown on a speaker's name plate.
+fn speaker_text(speaker: &str, window: &DialogueWindow) -> RichText {
+    RichText {
+        runs: vec![TextRun::Text {
+            text: speaker.to_string(),
+            style: TextStyle::new(window.text_color),
+        }],
+    }
+}
+
+/// Draws rendered text onto the canvas so that the text itself, not counting the margin
+/// [`text::render_rich_text`] adds, starts at the corner of `area`.
+///
+/// [`text::render_rich_text`]: ../text/fn.render_rich_text.html
+fn draw_text(
+    canvas: &mut RgbaImage,
+    layout: &TextLayout,
+    distance_field: Option<&DistanceFieldText>,
+    rendered: RgbaImage,
+    area: Rect,
+) {
+    let margin = text::text_margin(
+        layout.line_height,
+        distance_field.map(|field| field.effects),
+    );
+    let size = (rendered.width() as f32, rendered.height() as f32);
+    blit(
+        &rendered,
+        Rect::new(0.0, 0.0, size.0, size.1),
+        canvas,
+        Rect::new(
+            area.position.x - margin,
+            area.position.y - margin,
+            size.0,
+            size.1,
+        ),
+    );
+}
+
+/// Copies the `from` region of `source` onto the `to` region of `target`, scaling it with
+/// nearest-neighbor sampling and blending it over whatever is already there.
+fn blit(source: &RgbaImage, from: Rect, target: &mut RgbaImage, to: Rect) {
+    let x_range = to.position.x.max(0.0) as u32
+        ..((to.position.x + to.size.x).max(0.0) as u32).min(target.width());
+    let y_range = to.position.y.max(0.0) as u32
+        ..((to.position.y + to.size.y).max(0.0) as u32).min(target.height());
+
+    for y in y_range {
+        for x in x_range.clone() {
+            let u = from.position.x
+                + ((x as f32 + 0.5 - to.position.x) / to.size.x * from.size.x).floor();
+            let v = from.position.y
+                + ((y as f32 + 0.5 - to.position.y) / to.size.y * from.size.y).floor();
+            if u < 0.0 || v < 0.0 || u as u32 >= source.width() || v as u32 >= source.height() {
+                continue;
+            }
+
+            let over = source.get_pixel(u as u32, v as u32).data;
+            let under = target.get_pixel(x, y).data;
+            let alpha = f32::from(over[3]) / 255.0;
+            let under_alpha = f32::from(under[3]) / 255.0;
+            let out_alpha = alpha + under_alpha * (1.0 - alpha);
+            if out_alpha <= 0.0 {
+                continue;
+            }
+
+            let mut blended = [0; 4];
+            for channel in 0..3 {
+                blended[channel] = ((f32::from(over[channel]) * alpha
+                    + f32::from(under[channel]) * under_alpha * (1.0 - alpha))
+                    / out_alpha) as u8;
+            }
+            blended[3] = (out_alpha * 255.0) as u8;
+            target.put_pixel(x, y, Rgba { data: blended });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        font::{FontFamily, GameFont},
+        sdf::SdfEffects,
+    };
+
+    const DIALOGUE: &str = "
+windows:
+  - index: 0
+    bounds: { position: { x: 0, y: 0 }, size: { x: 400, y: 100 } }
+    texture_index: 1
+    frame: &frame
+      texture_index: 0
+      region: { position: { x: 0, y: 0 }, size: { x: 16, y: 16 } }
+      border: { left: 4, right: 4, top: 4, bottom: 4 }
+    name_plate: { frame: *frame, offset: 16, height: 20, padding: 4 }
+    padding: 10
+    font: Mono
+    text_height: 20
+    text_color: { r: 1.0, g: 1.0, b: 1.0, a: 1.0 }
+    characters_per_second: 30
+scripts:
+  - index: 0
+    name: Test
+    window: 0
+    lines:
+      - speaker: Narrator
+        text: \"This line is far too long to fit in the window all at once, so it has to be
+          split into pages, each of which is revealed a character at a time and has to be
+          dismissed before the next one starts.\"
+      - text: Short.
+";
+
+    /// The font the windows use, as (families, fonts).
+    fn mono() -> (Vec<FontFamily>, Vec<GameFont<'static>>) {
+        let mut font: GameFont =
+            serde_yaml::from_str("{ index: 0, file: ./data/resources/fonts/DejaVuSansMono.ttf }")
+                .unwrap();
+        font.initialize().unwrap();
+        let families = serde_yaml::from_str("[{ name: Mono, regular: 0 }]").unwrap();
+        (families, vec![font])
+    }
+
+    /// The texture the window frames are cut from.
+    fn frame_texture() -> Texture {
+        let mut frame: Texture =
+            serde_yaml::from_str("{ index: 0, size: { x: 16, y: 16 } }").unwrap();
+        let color = Rgba {
+            data: [40, 40, 80, 255],
+        };
+        frame.data = Some(RgbaImage::from_pixel(16, 16, color));
+        frame
+    }
+
+    #[test]
+    fn pages_are_revealed_and_advanced() {
+        let data: DialogueData = serde_yaml::from_str(DIALOGUE).unwrap();
+        let (families, fonts) = mono();
+        let fonts = FontStack::new("Mono", &families, &fonts).unwrap();
+        let textures = [frame_texture()];
+        let compose =
+            |dialogue: &Dialogue| dialogue.compose(&data, &fonts, &textures, &[]).unwrap();
+
+        let mut dialogue = Dialogue::new(0, &data, &fonts).unwrap();
+        let unrevealed = compose(&dialogue);
+        assert_eq!(unrevealed.dimensions(), (400, 100));
+        dialogue.update(100);
+        let partly_revealed = compose(&dialogue);
+        assert_ne!(*partly_revealed, *unrevealed);
+
+        // The first press shows the rest of the page instead of moving on.
+        assert_eq!(dialogue.appearance().revealed, Some(3));
+        assert!(dialogue.advance());
+        assert!(dialogue.is_page_revealed());
+        assert_eq!((dialogue.line(), dialogue.page()), (0, 0));
+        // Nothing moves once the page is showing, so there's no need to draw it again.
+        let appearance = dialogue.appearance();
+        dialogue.update(1000);
+        assert_eq!(dialogue.appearance(), appearance);
+        assert_ne!(*compose(&dialogue), *partly_revealed);
+
+        let mut shown = vec![(0, 0)];
+        while dialogue.advance() {
+            assert!(!dialogue.is_page_revealed());
+            shown.push((dialogue.line(), dialogue.page()));
+            dialogue.skip();
+        }
+        // The first line needs more than one page.
+        assert!(shown.len() > 2);
+        assert_eq!(shown[1], (0, 1));
+        assert_eq!(shown.last(), Some(&(1, 0)));
+    }
+
+    #[test]
+    fn distance_field_text_is_left_to_the_shader() {
+        let mut data: DialogueData = serde_yaml::from_str(DIALOGUE).unwrap();
+        data.windows[0].rendering = TextRendering::DistanceField(SdfEffects::default());
+        let (families, fonts) = mono();
+        let fonts = FontStack::new("Mono", &families, &fonts).unwrap();
+        let textures = [frame_texture()];
+        let compose = |dialogue: &Dialogue, data: &DialogueData| {
+            dialogue.compose(data, &fonts, &textures, &[]).unwrap()
+        };
+
+        // Without an atlas texture, the text is drawn into the window.
+        let mut dialogue = Dialogue::new(0, &data, &fonts).unwrap();
+        let unrevealed = compose(&dialogue, &data);
+        dialogue.update(100);
+        assert_ne!(*compose(&dialogue, &data), *unrevealed);
+        assert!(dialogue
+            .distance_field_quads(&data, &fonts)
+            .unwrap()
+            .is_empty());
+
+        data.windows[0].atlas_texture_index = Some(2);
+        let mut dialogue = Dialogue::new(0, &data, &fonts).unwrap();
+        let unrevealed = compose(&dialogue, &data);
+        let speaker = dialogue.distance_field_quads(&data, &fonts).unwrap();
+        assert_eq!(speaker.len(), "Narrator".len());
+        dialogue.update(100);
+        assert_eq!(*compose(&dialogue, &data), *unrevealed);
+        let quads = dialogue.distance_field_quads(&data, &fonts).unwrap();
+        assert_eq!(quads.len(), speaker.len() + "Thi".len());
+
+        // Every quad samples its own glyph's field, and the atlas fits its texture.
+        let atlas = dialogue.atlas().unwrap();
+        for quad in &quads {
+            let corners = &quad.corners;
+            assert!(corners[0].x < corners[3].x && corners[0].y < corners[1].y);
+            assert!(quad.texels.position.x + quad.texels.size.x <= atlas.image.width() as f32);
+        }
+        assert!(atlas.padded_image(512, 512).is_some());
+    }
+}
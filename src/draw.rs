@@ -4,45 +4,305 @@
 
 use nalgebra_glm as glm;
 
-use nalgebra_glm::{Mat4, Vec2, Vec3};
-use serde::{Deserialize, Serialize};
+use log::warn;
+use nalgebra_glm::{Mat3, Mat4, Vec2};
+use serde::Deserialize;
+use std::{collections::HashMap, fs};
 use winit::Window;
 
 use crate::{
-    component::{ComponentData, ComponentType},
+    animation::Animation,
+    aseprite::AsepriteSheet,
+    component::{Animation2D, Quad},
     data::DataManager,
+    dialogue::Appearance,
     error::Error,
-    geometry::{self, Transform2D, VertexData},
-    render::{FormattedVertexData, Renderer},
+    geometry::{self, Camera, Rect, Transform2D, TransformData, VertexData},
+    render::{DrawBatch, FormattedVertexData, Renderer},
     resource::ResourceManager,
+    sdf::{SdfEffects, SdfUniforms},
     serial::{Color, Index, Position2D, Position3D, Size},
-    time,
+    text::{SdfQuad, TextRendering},
+    time::GameClock,
+    tween::Pose,
 };
 
 /// A trait required for any `Component` that needs to be drawn on the screen.
 pub trait Draw2D {
+    /// `parent_transform` is the world transform of the [`Entity`] the component belongs
+    /// to, which the component's own transform is relative to, with the [`Camera`]'s view
+    /// already applied.
+    ///
+    /// `alpha` is how far the frame being drawn is between the last two updates, from 0 to
+    /// 1, for moving things smoothly between where they were and where they are.
+    ///
+    /// Returns no vertices if there's nothing to draw right now.
+    ///
+    /// [`Camera`]: ../geometry/struct.Camera.html
+    /// [`Entity`]: ../entity/struct.Entity.html
     fn vertex_data(
         &self,
         projection_matrix: Mat4,
+        parent_transform: Mat3,
         data_manager: &DataManager,
+        alpha: f32,
     ) -> Result<Vec<VertexData>, Error>;
 }
 
 /// A partitioned texture (or contiguous region of an atlas) containing animations frames
 /// for a sprite.
 ///
-/// Currently, every frame in a spritesheet must be of uniform size. If an atlas contains multiple
-/// spritesheets, individual spritesheets can have different frame sizes.
+/// Frames are either laid out in a grid of `frame_size` cells, `pitch` to a row, or listed
+/// one by one in `frames`, as packed by a tool like TexturePacker. Packed frames can be any
+/// size, and can have had empty space trimmed off, in which case each one says where its
+/// texels go in the untrimmed frame. If an atlas contains multiple spritesheets, individual
+/// spritesheets can be laid out differently.
+///
+/// ```yaml
+/// - index: 1
+///   position: { x: 0, y: 64 }
+///   frames:
+///     - { name: idle, rect: { position: { x: 0, y: 0 }, size: { x: 16, y: 24 } } }
+///     - name: jump
+///       rect: { position: { x: 16, y: 0 }, size: { x: 14, y: 20 } }
+///       source_size: { x: 16, y: 24 }
+///       offset: { x: 1, y: 0 }
+///       pivot: { x: 0.5, y: 1.0 }
+/// ```
+///
+/// In `spritesheets.yaml`, a spritesheet can also be imported from an Aseprite export by
+/// giving the path to its JSON, and optionally where its image starts in the texture:
+///
+/// ```yaml
+/// - index: 1
+///   aseprite: ./data/resources/art/hero.json
+///   position: { x: 0, y: 16 }
+/// ```
 #[derive(Deserialize)]
 pub struct Spritesheet {
     pub index: Index,
 
-    /// The number of frames in each row.
+    /// The number of frames in each row of the grid.
+    #[serde(default)]
     pub pitch: u16,
 
     pub position: Position2D,
+    /// The size of the grid.
+    #[serde(default)]
     pub size: Size,
+    /// The size of each cell of the grid.
+    #[serde(default)]
     pub frame_size: Size,
+    /// The frames, in order, if they aren't in a grid.
+    #[serde(default)]
+    pub frames: Vec<SpriteFrame>,
+
+    /// Animations that go with the spritesheet, by name, for [`Animation2D`]s to use. These
+    /// are the tags of an imported Aseprite file.
+    ///
+    /// [`Animation2D`]: ../component/struct.Animation2D.html
+    #[serde(default)]
+    pub animations: HashMap<String, Animation>,
+    /// Named regions of frames, like hitboxes.
+    #[serde(default)]
+    pub slices: HashMap<String, Slice>,
+}
+
+/// How a [`Spritesheet`] can be written in `spritesheets.yaml`.
+///
+/// [`Spritesheet`]: struct.Spritesheet.html
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SpritesheetData {
+    Aseprite {
+        index: Index,
+        aseprite: String,
+        #[serde(default)]
+        position: Option<Position2D>,
+    },
+    Full(Spritesheet),
+}
+
+impl Spritesheet {
+    /// Loads every spritesheet listed in `./data/spritesheets.yaml`, importing any that
+    /// come from Aseprite.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SerdeYaml`] if the list is malformed, or any error from
+    /// [`AsepriteSheet::load`] or [`AsepriteSheet::spritesheet`].
+    ///
+    /// [`AsepriteSheet::load`]: ../aseprite/struct.AsepriteSheet.html#method.load
+    /// [`AsepriteSheet::spritesheet`]: ../aseprite/struct.AsepriteSheet.html#method.spritesheet
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn load_spritesheets() -> Result<Vec<Spritesheet>, Error> {
+        let data: Vec<SpritesheetData> =
+            serde_yaml::from_str(&fs::read_to_string("./data/spritesheets.yaml")?)?;
+        data.into_iter()
+            .map(|spritesheet| match spritesheet {
+                SpritesheetData::Aseprite {
+                    index,
+                    aseprite,
+                    position,
+                } => AsepriteSheet::load(&aseprite)?.spritesheet(
+                    index,
+                    position.unwrap_or(Position2D { x: 0.0, y: 0.0 }),
+                ),
+                SpritesheetData::Full(spritesheet) => Ok(spritesheet),
+            })
+            .collect()
+    }
+
+    /// The number of frames in the spritesheet.
+    pub fn frame_count(&self) -> usize {
+        if !self.frames.is_empty() {
+            return self.frames.len();
+        }
+        if self.frame_size.y <= 0.0 {
+            return 0;
+        }
+        let rows = (self.size.y / self.frame_size.y) as usize;
+        usize::from(self.pitch) * rows
+    }
+
+    /// The given frame, or `None` if there isn't one.
+    pub fn frame(&self, frame: u16) -> Option<SpriteFrame> {
+        if !self.frames.is_empty() {
+            return self.frames.get(usize::from(frame)).cloned();
+        }
+        if usize::from(frame) >= self.frame_count() {
+            return None;
+        }
+        Some(SpriteFrame::untrimmed(Rect::new(
+            f32::from(frame % self.pitch) * self.frame_size.x,
+            f32::from(frame / self.pitch) * self.frame_size.y,
+            self.frame_size.x,
+            self.frame_size.y,
+        )))
+    }
+
+    /// The number of the frame with the given name, if there is one.
+    pub fn frame_index(&self, name: &str) -> Option<u16> {
+        self.frames
+            .iter()
+            .position(|frame| frame.name.as_ref().is_some_and(|frame_name| frame_name == name))
+            .map(|position| position as u16)
+    }
+
+    /// Returns the region of the texture, in texels, containing the given frame. A frame
+    /// that doesn't exist has no size.
+    pub fn frame_rect(&self, frame: u16) -> Rect {
+        let rect = self
+            .frame(frame)
+            .map_or(Rect::new(0.0, 0.0, 0.0, 0.0), |frame| frame.rect);
+        Rect::new(
+            self.position.x + rect.position.x,
+            self.position.y + rect.position.y,
+            rect.size.x,
+            rect.size.y,
+        )
+    }
+
+    /// The texture coordinates of the corners of the given frame, in the order of
+    /// [`QUAD_UVS`], for a texture with the given normalization matrix.
+    ///
+    /// [`QUAD_UVS`]: ../geometry/constant.QUAD_UVS.html
+    pub fn uv_coordinates(&self, frame: u16, normalization_matrix: &Mat3) -> Vec<Vec2> {
+        let rect = self.frame_rect(frame);
+        let frame_matrix = glm::translation2d(&glm::vec2(rect.position.x, rect.position.y))
+            * glm::scaling2d(&glm::vec2(rect.size.x, rect.size.y));
+        geometry::QUAD_UVS
+            .iter()
+            .map(|uv| (normalization_matrix * frame_matrix * glm::vec3(uv[0], uv[1], uv[2])).xy())
+            .collect()
+    }
+}
+
+/// One frame of a [`Spritesheet`].
+///
+/// [`Spritesheet`]: struct.Spritesheet.html
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpriteFrame {
+    /// A name to look the frame up by, like `walk_01.png`.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The frame's region of the spritesheet, measured from the spritesheet's `position`.
+    pub rect: Rect,
+    /// The size of the frame before it was trimmed, if it was.
+    #[serde(default)]
+    pub source_size: Option<Size>,
+    /// Where the top-left corner of `rect` goes in the untrimmed frame.
+    #[serde(default)]
+    pub offset: Option<Position2D>,
+    /// The point of the untrimmed frame that's put at the sprite's position, from (0, 0)
+    /// at the top-left corner to (1, 1) at the bottom-right. The center if not given.
+    #[serde(default)]
+    pub pivot: Option<Position2D>,
+}
+
+impl SpriteFrame {
+    /// A frame that covers `rect` and wasn't trimmed.
+    pub fn untrimmed(rect: Rect) -> SpriteFrame {
+        SpriteFrame {
+            name: None,
+            rect,
+            source_size: None,
+            offset: None,
+            pivot: None,
+        }
+    }
+
+    /// Moves and scales the unit quad to where the frame's texels go in a sprite one unit
+    /// across, so trimmed frames don't change size or jump around and the pivot ends up at
+    /// the origin. `origin`, if given, is used instead of the frame's own pivot.
+    pub fn quad_matrix(&self, origin: Option<Position2D>) -> Mat3 {
+        let size = self.rect.size;
+        let source = self.source_size.unwrap_or(size);
+        let offset = self.offset.unwrap_or(Position2D { x: 0.0, y: 0.0 });
+        let pivot = origin
+            .or(self.pivot)
+            .unwrap_or(Position2D { x: 0.5, y: 0.5 });
+        if source.x <= 0.0 || source.y <= 0.0 {
+            return glm::scaling2d(&glm::vec2(0.0, 0.0));
+        }
+
+        // Frames are measured with y pointing down, and the quad with y pointing up.
+        let center = glm::vec2(
+            (offset.x + size.x / 2.0) / source.x - pivot.x,
+            pivot.y - (offset.y + size.y / 2.0) / source.y,
+        );
+        glm::translation2d(&center)
+            * glm::scaling2d(&glm::vec2(size.x / source.x, size.y / source.y))
+    }
+}
+
+/// A named region of a spritesheet's frames, like a hitbox, which can change from frame to
+/// frame.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Slice {
+    /// The slice's bounds, each taking effect from its frame on, in frame order.
+    pub keys: Vec<SliceKey>,
+}
+
+/// Where a [`Slice`] is from one frame on.
+///
+/// [`Slice`]: struct.Slice.html
+#[derive(Clone, Debug, Deserialize)]
+pub struct SliceKey {
+    pub frame: u16,
+    /// Measured from the top-left corner of the frame, in texels.
+    pub bounds: Rect,
+    /// A point in `bounds`, like a character's feet, measured from the top-left of the bounds.
+    #[serde(default)]
+    pub pivot: Option<Position2D>,
+}
+
+impl Slice {
+    /// Where the slice is on spritesheet frame `frame`, or `None` if it doesn't start
+    /// until a later frame.
+    pub fn at(&self, frame: u16) -> Option<&SliceKey> {
+        self.keys.iter().rev().find(|key| key.frame <= frame)
+    }
 }
 
 /// A static image to display beneath all other images.
@@ -53,9 +313,15 @@ pub struct Background {
     pub size: Size,
 }
 
+/// A quad's vertices, along with the effects it's drawn with if it's a glyph from a
+/// distance-field atlas.
+type ShadedQuad = (Vec<VertexData>, Option<SdfUniforms>);
+
 /// Performs all drawing operations.
 pub struct DrawingSystem {
     renderer: Renderer,
+    /// How the dialogue window looked when it was last drawn into its texture.
+    dialogue_shown: Option<Appearance>,
 }
 
 impl DrawingSystem {
@@ -65,53 +331,197 @@ impl DrawingSystem {
         resource_manager: &mut ResourceManager,
     ) -> Result<Self, Error> {
         let renderer = Renderer::new(name, window, resource_manager)?;
-        Ok(DrawingSystem { renderer })
+        Ok(DrawingSystem {
+            renderer,
+            dialogue_shown: None,
+        })
     }
 
-    /// Collects all Components of the given type and returns a `Vec` of `VertexData`
+    /// Collects all components of type `T` and returns a `Vec` of `VertexData`
     /// giving all the vertex information needed to render the components.
-    fn get_vertex_data_for_type(
+    fn get_vertex_data_for_type<T: Draw2D + Send + Sync + 'static>(
         &self,
-        component_type: ComponentType,
         data_manager: &DataManager,
-    ) -> Vec<Vec<VertexData>> {
-        data_manager
-            .component_manager
-            .get_components_of_type(component_type)
+        alpha: f32,
+    ) -> Result<Vec<Vec<VertexData>>, Error> {
+        let projection_matrix = geometry::projection_matrix(glm::vec2(
+            self.renderer.physical_size.width as f32,
+            self.renderer.physical_size.height as f32,
+        ));
+        let view_matrix = data_manager.resources.get::<Camera>()?.view_matrix();
+        let component_manager = &data_manager.component_manager;
+        component_manager
+            .storage::<T>()?
             .iter()
-            .map(|component| {
-                data_manager
-                    .component_manager
-                    .get_component(*component)
-                    .component_data
-                    .vertex_data(
-                        geometry::projection_matrix(glm::vec2(
-                            self.renderer.physical_size.width as f32,
-                            self.renderer.physical_size.height as f32,
-                        )),
-                        data_manager,
-                    )
-                    .unwrap()
+            .map(|(handle, component)| {
+                let entity = component_manager
+                    .get_component(handle)
+                    .and_then(|header| header.entity);
+                component.vertex_data(
+                    projection_matrix,
+                    view_matrix
+                        * data_manager
+                            .entity_manager
+                            .interpolated_world_transform(entity, alpha),
+                    data_manager,
+                    alpha,
+                )
             })
-            .collect::<Vec<Vec<VertexData>>>()
+            .collect()
+    }
+
+    /// Draws the open dialogue window, if there is one, into its texture if it's changed
+    /// since it was last drawn, and returns the quads to draw it with: one covering the
+    /// window's place on the screen, then any glyphs drawn from the window's distance-field
+    /// atlas, each with the effects it's drawn with.
+    fn dialogue_vertex_data(
+        &mut self,
+        data_manager: &DataManager,
+    ) -> Result<Vec<ShadedQuad>, Error> {
+        let dialogue = match &data_manager.active_dialogue {
+            Some(dialogue) => dialogue,
+            None => {
+                self.dialogue_shown = None;
+                return Ok(vec![]);
+            }
+        };
+        let dialogue_data = &data_manager.dialogue;
+        let window = &dialogue_data.windows[dialogue_data.scripts[dialogue.script].window];
+        let textures = &data_manager.resource_manager.textures;
+        let fonts = data_manager.resource_manager.font_stack(&window.font)?;
+        let atlas = match (dialogue.atlas_texture(window), dialogue.atlas()) {
+            (Some(index), Some(atlas)) => Some((&textures[index], atlas)),
+            _ => None,
+        };
+
+        let appearance = dialogue.appearance();
+        if self.dialogue_shown != Some(appearance) {
+            // The atlas holds every glyph in the script, so it only changes with the script.
+            if let Some((texture, atlas)) = atlas {
+                if self.dialogue_shown.map(|shown| shown.script) != Some(appearance.script) {
+                    let image = atlas
+                        .padded_image(texture.size.x as u32, texture.size.y as u32)
+                        .ok_or(Error::TextureSize())?;
+                    self.renderer.update_texture(texture, &image)?;
+                }
+            }
+            let image =
+                dialogue.compose(dialogue_data, fonts, textures, &data_manager.spritesheets)?;
+            self.renderer
+                .update_texture(&textures[window.texture_index], &image)?;
+            self.dialogue_shown = Some(appearance);
+        }
+
+        // Window bounds are measured from the top-left corner of the screen, but the
+        // projection has its origin in the center with y pointing up.
+        let screen = glm::vec2(
+            self.renderer.physical_size.width as f32,
+            self.renderer.physical_size.height as f32,
+        );
+        let bounds = window.bounds;
+        let projection_matrix = geometry::projection_matrix(screen);
+        let position = |x: f32, y: f32| {
+            Position3D::from(
+                (projection_matrix
+                    * glm::vec4(
+                        bounds.position.x + x - screen.x / 2.0,
+                        screen.y / 2.0 - bounds.position.y - y,
+                        1.0,
+                        1.0,
+                    ))
+                .xyz(),
+            )
+        };
+
+        let mut quads = vec![(
+            geometry::QUAD_UVS
+                .iter()
+                .map(|uv| VertexData {
+                    position: position(uv[0] * bounds.size.x, uv[1] * bounds.size.y),
+                    uv: Position2D { x: uv[0], y: uv[1] },
+                    color: Color::from(glm::vec4(1.0, 1.0, 1.0, 1.0)),
+                    texture_index: window.texture_index,
+                })
+                .collect(),
+            None,
+        )];
+
+        if let (Some((texture, atlas)), TextRendering::DistanceField(effects)) =
+            (atlas, &window.rendering)
+        {
+            let glyphs = dialogue.distance_field_quads(dialogue_data, fonts)?;
+            let vertices = |glyph: &SdfQuad, color: Color| -> Vec<VertexData> {
+                let texels = glyph.texels;
+                glyph
+                    .corners
+                    .iter()
+                    .zip(geometry::QUAD_UVS.iter())
+                    .map(|(corner, uv)| VertexData {
+                        position: position(corner.x, corner.y),
+                        uv: Position2D {
+                            x: (texels.position.x + uv[0] * texels.size.x) / texture.size.x,
+                            y: (texels.position.y + uv[1] * texels.size.y) / texture.size.y,
+                        },
+                        color,
+                        texture_index: texture.index,
+                    })
+                    .collect()
+            };
+
+            // The atlas was made at the size the text is shown. The effects of every glyph
+            // are drawn before any of the glyphs themselves, so that one glyph's outline,
+            // shadow or glow never covers its neighbor.
+            if !effects.is_empty() {
+                let uniforms = SdfUniforms::new(effects, atlas, texture.size, 1.0);
+                quads.extend(glyphs.iter().map(|glyph| {
+                    let clear = Color {
+                        a: 0.0,
+                        ..glyph.color
+                    };
+                    (vertices(glyph, clear), Some(uniforms))
+                }));
+            }
+            let uniforms = SdfUniforms::new(&SdfEffects::default(), atlas, texture.size, 1.0);
+            quads.extend(
+                glyphs
+                    .iter()
+                    .map(|glyph| (vertices(glyph, glyph.color), Some(uniforms))),
+            );
+        }
+
+        Ok(quads)
     }
 
-    /// Collects all drawable [`Component`]s and sends them to the [`Renderer`] to be drawn.
-    pub fn draw_frame(&mut self, data_manager: &DataManager) {
-        let types = vec![ComponentType::Quad, ComponentType::Animation2D];
-        let mut quad_vertices = vec![];
-        let mut quad_counts = vec![];
-
-        // Collect all vertices into a single Vec and count the quads of each type.
-        for component_type in types {
-            let new_vertices = self.get_vertex_data_for_type(component_type, data_manager);
-            quad_counts.push(new_vertices.len());
-            quad_vertices.extend(new_vertices);
+    /// Collects all drawable [`Component`]s and sends them to the [`Renderer`] to be drawn,
+    /// `alpha` of the way between the last two updates.
+    pub fn draw_frame(&mut self, data_manager: &DataManager, alpha: f32) {
+        let mut quads = vec![];
+
+        // Collect all vertices into a single Vec.
+        for vertices in [
+            self.get_vertex_data_for_type::<Quad>(data_manager, alpha),
+            self.get_vertex_data_for_type::<Animation2D>(data_manager, alpha),
+        ] {
+            match vertices {
+                Ok(vertices) => quads.extend(
+                    vertices
+                        .into_iter()
+                        .filter(|quad| !quad.is_empty())
+                        .map(|quad| (quad, None)),
+                ),
+                Err(err) => warn!("Couldn't draw components: {}", err),
+            }
         }
 
-        let vertex_data = quad_vertices
+        // The dialogue window goes on top of everything else.
+        match self.dialogue_vertex_data(data_manager) {
+            Ok(vertices) => quads.extend(vertices),
+            Err(err) => warn!("Couldn't draw dialogue window: {}", err),
+        }
+
+        let vertex_data = quads
             .iter()
-            .flat_map(|quad| {
+            .flat_map(|(quad, _)| {
                 quad.iter().map(|vertex| FormattedVertexData {
                     position: vertex.position,
                     color: vertex.color,
@@ -120,15 +530,36 @@ impl DrawingSystem {
             })
             .collect::<Vec<FormattedVertexData>>();
 
-        let mut index_ranges = vec![];
-        let mut sum: u32 = 0;
-        for count in quad_counts {
-            index_ranges.push(sum..sum + (count * geometry::QUAD_INDICES.len()) as u32);
-            sum += index_ranges.last().unwrap().end;
+        // Group consecutive quads that use the same texture and shading so they can be
+        // drawn together.
+        let mut batches: Vec<DrawBatch> = vec![];
+        for (quad, (vertices, distance_field)) in quads.iter().enumerate() {
+            let texture_index = vertices[0].texture_index;
+            let start = (quad * geometry::QUAD_INDICES.len()) as u32;
+            let end = start + geometry::QUAD_INDICES.len() as u32;
+            match batches.last_mut() {
+                Some(batch)
+                    if batch.texture_index == texture_index
+                        && batch.distance_field == *distance_field =>
+                {
+                    batch.indices.end = end
+                }
+                _ => batches.push(DrawBatch {
+                    texture_index,
+                    indices: start..end,
+                    distance_field: *distance_field,
+                }),
+            }
         }
 
         // Draw everything.
-        self.renderer.render_frame(vertex_data, index_ranges);
+        if let Err(err) = self.renderer.render_frame(
+            vertex_data,
+            batches,
+            &data_manager.resource_manager.textures,
+        ) {
+            warn!("Couldn't draw frame: {}", err);
+        }
     }
 
     pub fn clean_up(self) -> Result<(), Error> {
@@ -136,138 +567,125 @@ impl DrawingSystem {
     }
 }
 
-/// A single animation sequence for a sprite.
-///
-/// Animations only specify the length of each frame, how to repeat, and which frame indices
-/// of a spritesheet to use. They aren't tied to specific spritesheets.
-///
-/// Currently, all frames have to be of equal duration. Repeat an index to hold on a particular
-/// frame for more than one "tick".
-#[derive(Deserialize, Serialize)]
-pub struct Animation {
-    /// The sequence of frames to use from the spritesheet.
-    pub frames: Vec<u16>,
-    /// How the animation progresses through the frames.
-    pub animation_type: AnimationType,
-    /// The length of each frame in milliseconds.
-    pub frame_length: u32,
-}
+impl Draw2D for Animation2D {
+    fn vertex_data(
+        &self,
+        projection_matrix: Mat4,
+        parent_transform: Mat3,
+        data_manager: &DataManager,
+        alpha: f32,
+    ) -> Result<Vec<VertexData>, Error> {
+        let now = data_manager.resources.get::<GameClock>()?.millis();
+        let zoom = data_manager.resources.get::<Camera>()?.zoom;
+        let pose = Pose {
+            transform: interpolated(&self.previous_transform, &self.transform_data, alpha),
+            tint: self.tint,
+        }
+        .offset_by(&self.keyframe_pose(now));
+        let current_frame = match self.current_frame(now) {
+            Some(frame) => frame,
+            None => return Ok(vec![]),
+        };
+        let spritesheet = &data_manager.spritesheets[self.spritesheet_index];
+        let frame = match spritesheet.frame(current_frame) {
+            Some(frame) => frame,
+            None => return Ok(vec![]),
+        };
+        let uvs = spritesheet.uv_coordinates(
+            current_frame,
+            &data_manager.resource_manager.textures[self.texture_index].normalization_matrix,
+        );
 
-/// Whether and how the animation repeats.
-#[derive(Deserialize, PartialEq, Serialize)]
-pub enum AnimationType {
-    /// Repeat by going through the sequence over and over: 1 2 3 1 2 3
-    Loop,
-    /// Repeat by going back and forth through the sequence: 1 2 3 2 1 2 3
-    Bounce,
-    /// Play once, then stop.
-    Once,
+        // The frame's already been moved so its pivot, or the placement's origin, is at
+        // (0, 0), so all that's left is mirroring it.
+        let transform = self.placement.snap(
+            parent_transform * pose.transform.transformation_matrix(),
+            zoom,
+        ) * self.placement.scaling_matrix()
+            * frame.quad_matrix(self.placement.origin);
+
+        Ok(quad_vertex_data(
+            transform,
+            projection_matrix,
+            &uvs,
+            self.texture_index,
+            pose.tint,
+        ))
+    }
 }
 
-impl Draw2D for ComponentData {
-    /// Collect vertex data according to the Component's type.
+impl Draw2D for Quad {
     fn vertex_data(
         &self,
         projection_matrix: Mat4,
+        parent_transform: Mat3,
         data_manager: &DataManager,
+        alpha: f32,
     ) -> Result<Vec<VertexData>, Error> {
-        match self {
-            ComponentData::Animation2D { texture_index, .. }
-            | ComponentData::Quad { texture_index, .. } => {
-                let vertices = self.vertices(projection_matrix)?;
-                let uvs = self.uv_coordinates(data_manager)?;
-                Ok(vertices
-                    .iter()
-                    .enumerate()
-                    .map(|(index, _)| VertexData {
-                        position: Position3D::from(vertices[index]),
-                        uv: Position2D::from(uvs[index]),
-                        color: Color::from(glm::vec4(1.0, 1.0, 1.0, 1.0)),
-                        texture_index: *texture_index,
-                    })
-                    .collect::<Vec<VertexData>>())
-            }
-        }
+        let transform_data = interpolated(&self.previous_transform, &self.transform_data, alpha);
+        let zoom = data_manager.resources.get::<Camera>()?.zoom;
+        let uvs = geometry::QUAD_UVS
+            .iter()
+            .map(|uv| {
+                (data_manager.resource_manager.textures[self.texture_index].normalization_matrix
+                    * glm::translation2d(&glm::vec2(self.uv_offset.x, self.uv_offset.y))
+                    * transform_data.scaling_matrix()
+                    * glm::vec3(uv[0], uv[1], uv[2]))
+                .xy()
+            })
+            .collect::<Vec<Vec2>>();
+
+        let transform = self
+            .placement
+            .snap(parent_transform * transform_data.transformation_matrix(), zoom)
+            * self.placement.transformation_matrix();
+
+        Ok(quad_vertex_data(
+            transform,
+            projection_matrix,
+            &uvs,
+            self.texture_index,
+            self.tint,
+        ))
     }
 }
 
-impl ComponentData {
-    /// Calculates and returns the position data for the associated [`Component`]'s vertices.
-    fn vertices(&self, projection_matrix: Mat4) -> Result<Vec<Vec3>, Error> {
-        match self {
-            ComponentData::Animation2D { .. } | ComponentData::Quad { .. } => {
-                Ok(geometry::QUAD_VERTICES
-                    .iter()
-                    .map(|vertex| {
-                        // TODO: Attempting to do any layer/depth stuff here causes weirdness.
-                        let transformed =
-                            self.transformation_matrix() * glm::vec3(vertex[0], vertex[1], 1.0);
-                        (projection_matrix * glm::vec4(transformed.x, transformed.y, 1.0, 1.0))
-                            .xyz()
-                    })
-                    .collect())
-            }
-        }
+/// Where a transform is `alpha` of the way from where it was at the last update, if it's
+/// been recorded, to where it is now.
+fn interpolated(
+    previous: &Option<TransformData>,
+    current: &TransformData,
+    alpha: f32,
+) -> TransformData {
+    match previous {
+        Some(previous) => previous.interpolate(current, alpha),
+        None => current.clone(),
     }
+}
 
-    /// Calculates and returns the uv data for the associated [`Component`]'s vertices.
-    fn uv_coordinates(&self, data_manager: &DataManager) -> Result<Vec<Vec2>, Error> {
-        match self {
-            ComponentData::Animation2D {
+/// Transforms the unit quad and pairs its corners with the given uv coordinates, tinted
+/// `tint`.
+fn quad_vertex_data(
+    transform: Mat3,
+    projection_matrix: Mat4,
+    uvs: &[Vec2],
+    texture_index: Index,
+    tint: Color,
+) -> Vec<VertexData> {
+    geometry::QUAD_VERTICES
+        .iter()
+        .zip(uvs.iter())
+        .map(|(vertex, uv)| {
+            // TODO: Attempting to do any layer/depth stuff here causes weirdness.
+            let transformed = transform * glm::vec3(vertex[0], vertex[1], 1.0);
+            VertexData {
+                position: Position3D::from(
+                    (projection_matrix * glm::vec4(transformed.x, transformed.y, 1.0, 1.0)).xyz(),
+                ),
+                uv: Position2D::from(*uv),
+                color: tint,
                 texture_index,
-                spritesheet_index,
-                animations,
-                current_animation,
-                start_time,
-                ..
-            } => {
-                let spritesheet = &data_manager.spritesheets[*spritesheet_index];
-                let frame_size = spritesheet.frame_size;
-                let animation = &animations[*current_animation];
-
-                let current_frame = animation.frames[time::calculate_frame(
-                    *start_time,
-                    animation.frames.len(),
-                    animation.frame_length,
-                )];
-                let frame_uv = glm::vec2(
-                    f32::from((current_frame % spritesheet.pitch) * frame_size.x as u16),
-                    f32::from((current_frame / spritesheet.pitch) * frame_size.y as u16),
-                );
-                let uv_offset = glm::translation2d(&frame_uv)
-                    * glm::translation2d(&glm::vec2(
-                        spritesheet.position.x as f32,
-                        spritesheet.position.y as f32,
-                    ));
-                Ok(geometry::QUAD_UVS
-                    .iter()
-                    .map(|uv| {
-                        (data_manager.resource_manager.textures[*texture_index]
-                            .normalization_matrix
-                            * uv_offset
-                            * glm::scaling2d(&glm::vec2(frame_size.x, frame_size.y))
-                            * glm::vec3(uv[0], uv[1], uv[2]))
-                        .xy()
-                    })
-                    .collect())
             }
-            ComponentData::Quad {
-                texture_index,
-                uv_offset,
-                ..
-            } => Ok(geometry::QUAD_UVS
-                .iter()
-                .map(|uv| {
-                    (data_manager.resource_manager.textures[*texture_index].normalization_matrix
-                        * glm::translation2d(&glm::vec2(uv_offset.x, uv_offset.y))
-                        * self.scaling_matrix()
-                        * glm::vec3(uv[0], uv[1], uv[2]))
-                    .xy()
-                })
-                .collect()),
-            // _ => Err(Error::WrongType(
-            //     "uv_coordinates() expected ComponentData::Animation2D variant",
-            // )),
-        }
-    }
+        })
+        .collect()
 }
@@ -2,79 +2,871 @@
 // Managing `entities`, which are just containers for `components`, individual bits of game functionality.
 // (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
 
+//! Entities and the hierarchy they form.
+//!
+//! An [`Entity`] can have a unique name, like `hero`, and any number of tags, like `npc`
+//! or `sprite`, which say what kind of thing it is. [`EntityManager::find_by_name`] and
+//! [`EntityManager::with_tag`] look them up without searching through every entity.
+//!
+//! An [`Entity`] can have a parent, in which case its transform is relative to its parent's,
+//! and so are the transforms of its components. Moving a character moves the weapon
+//! attached to it and the name floating over its head.
+//!
+//! World transforms are cached. Changing an entity's transform or parent marks it and
+//! everything below it dirty, and [`EntityManager::update_transforms`] recomputes the dirty
+//! entities, once per update. The transforms from before the update are kept too, so
+//! entities can be drawn partway between the two when frames fall between updates.
+//!
+//! [`Entity`]: struct.Entity.html
+//! [`EntityManager::find_by_name`]: struct.EntityManager.html#method.find_by_name
+//! [`EntityManager::with_tag`]: struct.EntityManager.html#method.with_tag
+//! [`EntityManager::update_transforms`]: struct.EntityManager.html#method.update_transforms
+
 use log::debug;
+use nalgebra_glm as glm;
+use nalgebra_glm::Mat3;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+};
 
-use crate::{error::Error, serial::Index};
+use crate::{
+    arena::{Arena, ArenaSnapshot, Handle},
+    component::ComponentManager,
+    draw::Spritesheet,
+    error::Error,
+    geometry::{self, Transform2D, TransformData},
+    prefab::{Prefab, PrefabInstance, Prefabs},
+};
 
 /// An entity is just a bundle of components.
 #[derive(Clone, Deserialize, Serialize)]
 pub struct Entity {
-    pub id: Index,
-    pub entity_type: EntityType,
-    pub components: Vec<Index>,
-}
-
-#[derive(Clone, PartialEq, Deserialize, Eq, Hash, Serialize)]
-pub enum EntityType {
-    Background = 0,
-    Sprite = 1,
+    /// A name no other entity has, for finding this one.
+    #[serde(default)]
+    name: Option<String>,
+    /// What kind of thing the entity is, e.g. `sprite` or `npc`.
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    pub components: Vec<Handle>,
+    /// The entity this one's transform is relative to.
+    #[serde(default)]
+    parent: Option<Handle>,
+    /// Entities whose parent is this one. Rebuilt from `parent` when loading.
+    #[serde(skip)]
+    children: Vec<Handle>,
+    /// Position, scaling, and rotation relative to the parent, or to the world if there's
+    /// no parent. Components' transforms are relative to this.
+    #[serde(default)]
+    transform: TransformData,
+    /// The cached transform relative to the world, or `None` if it needs recomputing.
+    #[serde(skip)]
+    world_transform: Option<Mat3>,
+    /// The world transform before the last update, to draw in-between positions from.
+    #[serde(skip)]
+    previous_world_transform: Option<Mat3>,
 }
 
 impl Entity {
-    pub fn new(id: Index, entity_type: EntityType) -> Self {
+    pub fn new() -> Self {
         Entity {
-            id,
-            entity_type: entity_type,
+            name: None,
+            tags: BTreeSet::new(),
             components: Vec::new(),
+            parent: None,
+            children: Vec::new(),
+            transform: TransformData::default(),
+            world_transform: None,
+            previous_world_transform: None,
         }
     }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(String::as_str)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn parent(&self) -> Option<Handle> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[Handle] {
+        &self.children
+    }
+
+    /// The entity's transform relative to its parent.
+    pub fn transform(&self) -> &TransformData {
+        &self.transform
+    }
+}
+
+impl Default for Entity {
+    fn default() -> Self {
+        Entity::new()
+    }
 }
 
 /// The owner for all entities.
 /// Handles creation, access, and deletion.
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct EntityManager {
-    pub counter: Index,
-    pub entities: Vec<Entity>,
+    pub entities: Arena<Entity>,
+    /// Each named entity, by name.
+    #[serde(skip)]
+    names: HashMap<String, Handle>,
+    /// The entities with each tag, in the order they are in `entities`.
+    #[serde(skip)]
+    tagged: HashMap<String, BTreeSet<Handle>>,
 }
 
 impl EntityManager {
+    pub fn new() -> EntityManager {
+        EntityManager::default()
+    }
+
+    /// Loads entities from `./data/entities.yaml`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file can't be read, [`Error::SerdeYaml`] if it's
+    /// malformed, or [`Error::Name`] if two entities have the same name.
+    ///
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    /// [`Error::Name`]: ../error/enum.Error.html#variant.Name
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
     pub fn load_entities() -> Result<EntityManager, Error> {
         debug!("Loading entities...");
-        Ok(serde_yaml::from_str(&fs::read_to_string(
+        let mut entity_manager: EntityManager = serde_yaml::from_str(&fs::read_to_string(
             "./data/entities.yaml",
-        )?)?)
+        )?)?;
+        entity_manager.link_children();
+        entity_manager.index()?;
+        Ok(entity_manager)
     }
 
-    pub fn create_entity(&mut self, entity_type: EntityType) -> Index {
-        let new_entity = Entity {
-            id: self.counter,
-            entity_type,
-            components: Vec::new(),
+    /// Rebuilds the lookups by name and tag from the entities.
+    fn index(&mut self) -> Result<(), Error> {
+        self.names.clear();
+        self.tagged.clear();
+        for (handle, entity) in self.entities.iter() {
+            if let Some(name) = &entity.name {
+                if self.names.insert(name.clone(), handle).is_some() {
+                    return Err(Error::Name(format!(
+                        "more than one entity is named {}",
+                        name
+                    )));
+                }
+            }
+            for tag in &entity.tags {
+                self.tagged.entry(tag.clone()).or_default().insert(handle);
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills in every entity's list of children from their parents.
+    fn link_children(&mut self) {
+        let links = self
+            .entities
+            .iter()
+            .filter_map(|(child, entity)| entity.parent.map(|parent| (parent, child)))
+            .collect::<Vec<(Handle, Handle)>>();
+        for (parent, child) in links {
+            if let Some(parent) = self.entities.get_mut(parent) {
+                parent.children.push(child);
+            }
+        }
+    }
+
+    /// Records every entity, keeping their handles, for a saved game.
+    pub fn snapshot(&self) -> ArenaSnapshot<Entity> {
+        ArenaSnapshot::from(&self.entities)
+    }
+
+    /// Replaces every entity with the ones from a [`snapshot`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Snapshot`] if the snapshot's free list is broken or [`Error::Name`]
+    /// if two entities in the snapshot have the same name, in which case the entities are
+    /// left as they were.
+    ///
+    /// [`snapshot`]: #method.snapshot
+    /// [`Error::Name`]: ../error/enum.Error.html#variant.Name
+    /// [`Error::Snapshot`]: ../error/enum.Error.html#variant.Snapshot
+    pub fn restore(&mut self, snapshot: ArenaSnapshot<Entity>) -> Result<(), Error> {
+        let mut restored = EntityManager {
+            entities: snapshot.restore(|_, entity| Ok::<Entity, Error>(entity))?,
+            ..EntityManager::default()
         };
+        for (_, entity) in restored.entities.iter_mut() {
+            entity.children.clear();
+            entity.world_transform = None;
+        }
+        restored.link_children();
+        restored.index()?;
+        *self = restored;
+        Ok(())
+    }
 
-        // len() is the number of elements in the vec, not the allocated size
-        // thus push() always places items into vec[vec.len() - 1]
-        self.entities.push(new_entity);
-        self.counter += 1;
-        (self.entities.len() - 1) as Index
+    pub fn create_entity(&mut self) -> Handle {
+        self.entities.insert(Entity::new())
     }
 
-    pub fn get_entities_of_type(&self, requested_type: EntityType) -> Vec<Index> {
-        self.entities
+    /// The entity with the given name, if there is one.
+    pub fn find_by_name(&self, name: &str) -> Option<Handle> {
+        self.names.get(name).cloned()
+    }
+
+    /// Every entity with the given tag, in the order they were created in, apart from
+    /// reused slots.
+    pub fn with_tag(&self, tag: &str) -> Vec<Handle> {
+        self.tagged
+            .get(tag)
+            .map(|tagged| tagged.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Names an entity, or takes its name away if `name` is `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the entity has been destroyed, or [`Error::Name`] if
+    /// another entity already has the name.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    /// [`Error::Name`]: ../error/enum.Error.html#variant.Name
+    pub fn set_name(&mut self, entity: Handle, name: Option<&str>) -> Result<(), Error> {
+        if !self.entities.contains(entity) {
+            return Err(Error::Index());
+        }
+        if let Some(name) = name {
+            match self.names.get(name) {
+                Some(named) if *named != entity => {
+                    return Err(Error::Name(format!("{} is already taken", name)))
+                }
+                _ => {}
+            }
+        }
+
+        let data = self.entities.get_mut(entity).ok_or(Error::Index())?;
+        if let Some(old) = data.name.take() {
+            self.names.remove(&old);
+        }
+        if let Some(name) = name {
+            data.name = Some(name.to_string());
+            self.names.insert(name.to_string(), entity);
+        }
+        Ok(())
+    }
+
+    /// Tags an entity. Tagging it with a tag it already has does nothing.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the entity has been destroyed.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn add_tag(&mut self, entity: Handle, tag: &str) -> Result<(), Error> {
+        let data = self.entities.get_mut(entity).ok_or(Error::Index())?;
+        data.tags.insert(tag.to_string());
+        self.tagged
+            .entry(tag.to_string())
+            .or_default()
+            .insert(entity);
+        Ok(())
+    }
+
+    /// Takes a tag off an entity, if it has it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the entity has been destroyed.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn remove_tag(&mut self, entity: Handle, tag: &str) -> Result<(), Error> {
+        let data = self.entities.get_mut(entity).ok_or(Error::Index())?;
+        data.tags.remove(tag);
+        self.untag(entity, tag);
+        Ok(())
+    }
+
+    /// Takes the entity out of the lookup for a tag.
+    fn untag(&mut self, entity: Handle, tag: &str) {
+        if let Some(tagged) = self.tagged.get_mut(tag) {
+            tagged.remove(&entity);
+            if tagged.is_empty() {
+                self.tagged.remove(tag);
+            }
+        }
+    }
+
+    /// Creates an entity, its components, and its children from a prefab, and returns the
+    /// new entity's handle. Animations the components take from `spritesheets` are filled
+    /// in as they're created.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors from [`Prefabs::resolve`], [`ComponentManager::create_named`] and
+    /// [`ComponentManager::add_spritesheet_animations`]. Anything created before the error
+    /// is destroyed again.
+    ///
+    /// [`ComponentManager::add_spritesheet_animations`]: ../component/struct.ComponentManager.html#method.add_spritesheet_animations
+    /// [`ComponentManager::create_named`]: ../component/struct.ComponentManager.html#method.create_named
+    /// [`Prefabs::resolve`]: ../prefab/struct.Prefabs.html#method.resolve
+    pub fn spawn_prefab(
+        &mut self,
+        prefabs: &Prefabs,
+        instance: &PrefabInstance,
+        component_manager: &mut ComponentManager,
+        spritesheets: &[Spritesheet],
+    ) -> Result<Handle, Error> {
+        let Prefab {
+            name,
+            tags,
+            transform,
+            components,
+            children,
+        } = prefabs.resolve(instance)?;
+        let entity = self.create_entity();
+        self.set_transform(entity, transform)?;
+
+        let result = (|| {
+            self.set_name(entity, name.as_deref())?;
+            for tag in &tags {
+                self.add_tag(entity, tag)?;
+            }
+            for (name, data) in components {
+                let name = name.as_str().ok_or_else(|| {
+                    Error::Prefab("component names should be strings".to_string())
+                })?;
+                let component = component_manager.create_named(name, data)?;
+                component_manager.add_entity_component(entity, self, component)?;
+                component_manager.add_spritesheet_animations(component, spritesheets)?;
+            }
+            for child in &children {
+                let child = self.spawn_prefab(prefabs, child, component_manager, spritesheets)?;
+                self.set_parent(child, Some(entity), false)?;
+            }
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => Ok(entity),
+            Err(err) => {
+                self.destroy_entity(entity, component_manager)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Destroys an entity along with all of its components and all of its descendants.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the entity has already been destroyed.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn destroy_entity(
+        &mut self,
+        entity: Handle,
+        component_manager: &mut ComponentManager,
+    ) -> Result<Entity, Error> {
+        let removed = self.entities.remove(entity).ok_or(Error::Index())?;
+        if let Some(name) = &removed.name {
+            self.names.remove(name);
+        }
+        for tag in &removed.tags {
+            self.untag(entity, tag);
+        }
+        if let Some(parent) = removed.parent.and_then(|parent| self.entities.get_mut(parent)) {
+            parent.children.retain(|child| *child != entity);
+        }
+        for component in &removed.components {
+            // Components destroyed separately are already gone, which is fine.
+            let _ = component_manager.destroy_component(*component, self);
+        }
+        for child in &removed.children {
+            let _ = self.destroy_entity(*child, component_manager);
+        }
+        Ok(removed)
+    }
+
+    /// Returns `None` if the entity has been destroyed.
+    pub fn get_entity(&self, entity: Handle) -> Option<&Entity> {
+        self.entities.get(entity)
+    }
+
+    /// Returns `None` if the entity has been destroyed.
+    pub fn get_entity_mut(&mut self, entity: Handle) -> Option<&mut Entity> {
+        self.entities.get_mut(entity)
+    }
+
+    /// Replaces an entity's transform relative to its parent.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if the entity has been destroyed.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn set_transform(&mut self, entity: Handle, transform: TransformData) -> Result<(), Error> {
+        let data = self.entities.get_mut(entity).ok_or(Error::Index())?;
+        data.transform = transform;
+        self.invalidate_world_transforms(entity);
+        Ok(())
+    }
+
+    /// Moves an entity under a new parent, or to the top of the hierarchy if `parent` is
+    /// `None`. With `keep_world_transform`, the entity's transform is adjusted so it stays
+    /// where it is on screen; otherwise it keeps its transform relative to its parent and
+    /// moves with the change of parent. See [`TransformData::from_matrix`] for when the
+    /// adjustment can't be exact.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if either entity has been destroyed, or
+    /// [`Error::Hierarchy`] if `parent` is the entity itself or one of its descendants.
+    ///
+    /// [`Error::Hierarchy`]: ../error/enum.Error.html#variant.Hierarchy
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    /// [`TransformData::from_matrix`]: ../geometry/struct.TransformData.html#method.from_matrix
+    pub fn set_parent(
+        &mut self,
+        entity: Handle,
+        parent: Option<Handle>,
+        keep_world_transform: bool,
+    ) -> Result<(), Error> {
+        let old_parent = self.get_entity(entity).ok_or(Error::Index())?.parent;
+        if let Some(parent) = parent {
+            if !self.entities.contains(parent) {
+                return Err(Error::Index());
+            }
+            if parent == entity || self.ancestors(parent).any(|ancestor| ancestor == entity) {
+                return Err(Error::Hierarchy(
+                    "an entity can't be its own ancestor".to_string(),
+                ));
+            }
+        }
+
+        if keep_world_transform {
+            let world = self.compute_world_transform(Some(entity));
+            let parent_world = self.compute_world_transform(parent);
+            let local = glm::inverse(&parent_world) * world;
+            self.set_transform(entity, TransformData::from_matrix(&local))?;
+        }
+
+        if let Some(old_parent) = old_parent.and_then(|old| self.entities.get_mut(old)) {
+            old_parent.children.retain(|child| *child != entity);
+        }
+        if let Some(parent) = parent.and_then(|parent| self.entities.get_mut(parent)) {
+            parent.children.push(entity);
+        }
+        self.entities.get_mut(entity).ok_or(Error::Index())?.parent = parent;
+        self.invalidate_world_transforms(entity);
+        Ok(())
+    }
+
+    /// Throws away the cached world transforms of an entity and everything below it.
+    fn invalidate_world_transforms(&mut self, entity: Handle) {
+        let mut stack = vec![entity];
+        // As in `ancestors`, a cycle in badly written data mustn't loop forever.
+        let mut remaining = self.entities.len();
+        while let Some(handle) = stack.pop() {
+            if remaining == 0 {
+                break;
+            }
+            remaining -= 1;
+            if let Some(entity) = self.entities.get_mut(handle) {
+                entity.world_transform = None;
+                stack.extend(entity.children.iter().cloned());
+            }
+        }
+    }
+
+    /// The entity's parent, its parent's parent, and so on.
+    pub fn ancestors(&self, entity: Handle) -> impl Iterator<Item = Handle> + '_ {
+        // Counting steps keeps a cycle in badly written data from looping forever.
+        let mut current = self.get_entity(entity).and_then(|entity| entity.parent);
+        (0..self.entities.len()).map_while(move |_| {
+            let ancestor = current?;
+            current = self.get_entity(ancestor).and_then(|entity| entity.parent);
+            Some(ancestor)
+        })
+    }
+
+    /// The entity's cached transform relative to the world. Returns the identity for
+    /// `None`, so components that don't belong to an entity are drawn as they are.
+    ///
+    /// If the cache is out of date because [`update_transforms`] hasn't been called since
+    /// something changed, the transform is worked out from scratch instead.
+    ///
+    /// [`update_transforms`]: #method.update_transforms
+    pub fn world_transform(&self, entity: Option<Handle>) -> Mat3 {
+        match entity
+            .and_then(|entity| self.get_entity(entity))
+            .and_then(|entity| entity.world_transform)
+        {
+            Some(world_transform) => world_transform,
+            None => self.compute_world_transform(entity),
+        }
+    }
+
+    /// The entity's world transform `alpha` of the way from where it was when
+    /// [`record_transforms`] was last called to where it is now. Entities that haven't been
+    /// recorded yet are where they are now.
+    ///
+    /// [`record_transforms`]: #method.record_transforms
+    pub fn interpolated_world_transform(&self, entity: Option<Handle>, alpha: f32) -> Mat3 {
+        let current = self.world_transform(entity);
+        match entity
+            .and_then(|entity| self.get_entity(entity))
+            .and_then(|entity| entity.previous_world_transform)
+        {
+            Some(previous) => geometry::interpolate_matrix(&previous, &current, alpha),
+            None => current,
+        }
+    }
+
+    /// Remembers every entity's world transform as it is now, for
+    /// [`interpolated_world_transform`]. Called before each update.
+    ///
+    /// [`interpolated_world_transform`]: #method.interpolated_world_transform
+    pub fn record_transforms(&mut self) {
+        let recorded = self
+            .entities
+            .iter()
+            .map(|(handle, _)| (handle, self.world_transform(Some(handle))))
+            .collect::<Vec<(Handle, Mat3)>>();
+        for (handle, world_transform) in recorded {
+            if let Some(entity) = self.entities.get_mut(handle) {
+                entity.previous_world_transform = Some(world_transform);
+            }
+        }
+    }
+
+    /// Works out a world transform by walking up the hierarchy, ignoring the cache.
+    fn compute_world_transform(&self, entity: Option<Handle>) -> Mat3 {
+        let entity = match entity {
+            Some(entity) => entity,
+            None => return glm::identity(),
+        };
+        std::iter::once(entity)
+            .chain(self.ancestors(entity))
+            .filter_map(|entity| self.get_entity(entity))
+            .fold(glm::identity(), |world: Mat3, entity| {
+                entity.transform.transformation_matrix() * world
+            })
+    }
+
+    /// Recomputes the world transforms of entities that have changed, and of everything
+    /// below them.
+    pub fn update_transforms(&mut self) {
+        let roots = self
+            .entities
             .iter()
-            .filter(|entity| entity.entity_type == requested_type)
-            .map(|entity| entity.id)
-            .collect()
+            .filter(|(_, entity)| entity.parent.is_none())
+            .map(|(handle, _)| handle)
+            .collect::<Vec<Handle>>();
+
+        let mut stack = roots
+            .into_iter()
+            .map(|root| (root, glm::identity(), false))
+            .collect::<Vec<(Handle, Mat3, bool)>>();
+        while let Some((handle, parent_world, parent_changed)) = stack.pop() {
+            let entity = match self.entities.get_mut(handle) {
+                Some(entity) => entity,
+                None => continue,
+            };
+
+            let changed = parent_changed || entity.world_transform.is_none();
+            let world = match entity.world_transform {
+                Some(world) if !changed => world,
+                _ => {
+                    let world = parent_world * entity.transform.transformation_matrix();
+                    entity.world_transform = Some(world);
+                    world
+                }
+            };
+            stack.extend(
+                entity
+                    .children
+                    .iter()
+                    .map(|child| (*child, world, changed)),
+            );
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::Animation2D;
+
+    fn transform(x: f32, y: f32, scale: f32, rotation: f32) -> TransformData {
+        TransformData::new(glm::vec2(x, y), glm::vec2(scale, scale), rotation)
+    }
+
+    fn assert_close(left: Mat3, right: Mat3) {
+        assert!((left - right).amax() < 1e-4, "{} != {}", left, right);
+    }
+
+    /// A parent, its child and its grandchild.
+    fn family(entities: &mut EntityManager) -> (Handle, Handle, Handle) {
+        let parent = entities.create_entity();
+        let child = entities.create_entity();
+        let grandchild = entities.create_entity();
+        entities
+            .set_transform(parent, transform(10.0, 0.0, 2.0, 0.5))
+            .unwrap();
+        entities
+            .set_transform(child, transform(3.0, 4.0, 1.0, 0.0))
+            .unwrap();
+        entities
+            .set_transform(grandchild, transform(0.0, 1.0, 1.0, 0.0))
+            .unwrap();
+        entities.set_parent(child, Some(parent), false).unwrap();
+        entities.set_parent(grandchild, Some(child), false).unwrap();
+        (parent, child, grandchild)
+    }
+
+    #[test]
+    fn names_and_tags_are_looked_up_and_cleaned_up() {
+        let mut entities = EntityManager::new();
+        let mut components = ComponentManager::new();
+        let hero = entities.create_entity();
+        let guard = entities.create_entity();
+        let chest = entities.create_entity();
+        entities.set_name(hero, Some("hero")).unwrap();
+        entities.add_tag(hero, "npc").unwrap();
+        entities.add_tag(guard, "npc").unwrap();
+        entities.add_tag(guard, "npc").unwrap();
+        entities.add_tag(chest, "item").unwrap();
+        assert_eq!(entities.find_by_name("hero"), Some(hero));
+        assert_eq!(entities.with_tag("npc"), vec![hero, guard]);
+        assert_eq!(entities.with_tag("nobody"), vec![]);
+
+        // Names are unique, but an entity can be given its own name again.
+        assert!(matches!(
+            entities.set_name(guard, Some("hero")),
+            Err(Error::Name(_))
+        ));
+        assert_eq!(entities.get_entity(guard).unwrap().name(), None);
+        entities.set_name(hero, Some("hero")).unwrap();
+
+        // Renaming frees the old name.
+        entities.set_name(hero, Some("player")).unwrap();
+        assert_eq!(entities.find_by_name("hero"), None);
+        assert_eq!(entities.find_by_name("player"), Some(hero));
+        entities.set_name(guard, Some("hero")).unwrap();
+        assert_eq!(entities.find_by_name("hero"), Some(guard));
+        entities.set_name(guard, None).unwrap();
+        assert_eq!(entities.find_by_name("hero"), None);
+
+        entities.remove_tag(guard, "npc").unwrap();
+        assert_eq!(entities.with_tag("npc"), vec![hero]);
+
+        // Destroying an entity takes it out of every lookup, and a new entity in its slot
+        // doesn't inherit anything.
+        entities.destroy_entity(hero, &mut components).unwrap();
+        entities.destroy_entity(chest, &mut components).unwrap();
+        assert_eq!(entities.find_by_name("player"), None);
+        assert_eq!(entities.with_tag("npc"), vec![]);
+        assert_eq!(entities.with_tag("item"), vec![]);
+        let newcomer = entities.create_entity();
+        entities.set_name(newcomer, Some("player")).unwrap();
+        assert_eq!(entities.find_by_name("player"), Some(newcomer));
+        assert!(entities.set_name(hero, Some("ghost")).is_err());
+        assert!(entities.add_tag(hero, "npc").is_err());
+        assert_eq!(entities.with_tag("npc"), vec![]);
+    }
+
+    #[test]
+    fn reparenting_can_keep_the_world_transform() {
+        let mut entities = EntityManager::new();
+        let (parent, child, _) = family(&mut entities);
+        let other = entities.create_entity();
+        entities
+            .set_transform(other, transform(-5.0, 2.0, 0.5, 1.0))
+            .unwrap();
+        entities.update_transforms();
+
+        let before = entities.world_transform(Some(child));
+        entities.set_parent(child, Some(other), true).unwrap();
+        entities.update_transforms();
+        assert_close(entities.world_transform(Some(child)), before);
+        assert_eq!(
+            entities.get_entity(parent).unwrap().children(),
+            &[] as &[Handle]
+        );
+        assert_eq!(entities.get_entity(other).unwrap().children(), &[child]);
 
-    pub fn get_entity(&self, index: Index) -> &Entity {
-        &self.entities[index as usize]
+        // Without keeping it, the local transform stays and the world one moves.
+        let local = entities.get_entity(child).unwrap().transform().clone();
+        entities.set_parent(child, None, false).unwrap();
+        entities.update_transforms();
+        assert_close(
+            entities.world_transform(Some(child)),
+            local.transformation_matrix(),
+        );
     }
 
-    pub fn get_entity_mut(&mut self, index: Index) -> &mut Entity {
-        &mut self.entities[index as usize]
+    #[test]
+    fn changes_reach_descendants() {
+        let mut entities = EntityManager::new();
+        let (parent, child, grandchild) = family(&mut entities);
+        entities.update_transforms();
+
+        let expected = |entities: &EntityManager| {
+            [parent, child, grandchild]
+                .iter()
+                .map(|entity| {
+                    entities
+                        .get_entity(*entity)
+                        .unwrap()
+                        .transform()
+                        .transformation_matrix()
+                })
+                .fold(glm::identity(), |world: Mat3, local| world * local)
+        };
+        assert_close(
+            entities.world_transform(Some(grandchild)),
+            expected(&entities),
+        );
+
+        // Before the update, the stale cache isn't used; after it, the cache is right.
+        entities
+            .set_transform(parent, transform(0.0, -7.0, 3.0, 2.0))
+            .unwrap();
+        assert_close(
+            entities.world_transform(Some(grandchild)),
+            expected(&entities),
+        );
+        entities.update_transforms();
+        assert!(entities
+            .get_entity(grandchild)
+            .unwrap()
+            .world_transform
+            .is_some());
+        assert_close(
+            entities.world_transform(Some(grandchild)),
+            expected(&entities),
+        );
+
+        let root = entities.create_entity();
+        entities
+            .set_transform(root, transform(1.0, 1.0, 1.0, 1.0))
+            .unwrap();
+        entities.update_transforms();
+        entities.set_parent(parent, Some(root), false).unwrap();
+        assert_close(
+            entities.world_transform(Some(grandchild)),
+            entities
+                .get_entity(root)
+                .unwrap()
+                .transform()
+                .transformation_matrix()
+                * expected(&entities),
+        );
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut entities = EntityManager::new();
+        let (parent, child, grandchild) = family(&mut entities);
+        for (entity, new_parent) in [(parent, grandchild), (parent, child), (child, child)] {
+            assert!(matches!(
+                entities.set_parent(entity, Some(new_parent), false),
+                Err(Error::Hierarchy(_))
+            ));
+        }
+        assert_eq!(entities.get_entity(parent).unwrap().parent(), None);
+        assert_eq!(entities.get_entity(child).unwrap().parent(), Some(parent));
+    }
+
+    #[test]
+    fn destroying_an_entity_destroys_its_descendants() {
+        let mut entities = EntityManager::new();
+        let mut components = ComponentManager::new();
+        let (parent, child, grandchild) = family(&mut entities);
+        let sibling = entities.create_entity();
+        entities.set_parent(sibling, Some(parent), false).unwrap();
+        let component = components.create_component(0u8);
+        components
+            .add_entity_component(grandchild, &mut entities, component)
+            .unwrap();
+
+        entities.destroy_entity(sibling, &mut components).unwrap();
+        assert_eq!(entities.get_entity(parent).unwrap().children(), &[child]);
+
+        entities.destroy_entity(parent, &mut components).unwrap();
+        for entity in [parent, child, grandchild] {
+            assert!(entities.get_entity(entity).is_none());
+        }
+        assert!(components.get_component(component).is_none());
+        assert!(matches!(
+            entities.destroy_entity(parent, &mut components),
+            Err(Error::Index())
+        ));
+    }
+
+    #[test]
+    fn spawned_prefabs_get_spritesheet_animations() {
+        let prefabs: Prefabs = serde_yaml::from_str(
+            "prefabs:
+               walker:
+                 components:
+                   Animation2D:
+                     texture_index: 0
+                     spritesheet_index: 0
+                     layer: 0
+                     current_animation: 0
+                     spritesheet_animations: [walk]
+                     transform_data: { translation: [0, 0], scaling: [1, 1], rotation: 0 }
+                     movement: { delta_rotation: 0, delta_scale: [0, 0], delta_translate: [0, 0] }
+               lost:
+                 base: walker
+                 components: { Animation2D: { spritesheet_animations: [run] } }",
+        )
+        .unwrap();
+        let spritesheets: Vec<Spritesheet> = serde_yaml::from_str(
+            "- { index: 0, position: { x: 0, y: 0 }, animations: { walk: {
+                 frames: [3, 4], animation_type: Loop, frame_length: 100 } } }",
+        )
+        .unwrap();
+        let mut entities = EntityManager::new();
+        let mut components = ComponentManager::new();
+
+        let walker = entities
+            .spawn_prefab(
+                &prefabs,
+                &PrefabInstance::new("walker"),
+                &mut components,
+                &spritesheets,
+            )
+            .unwrap();
+        let animation = entities.get_entity(walker).unwrap().components[0];
+        let storage = components.storage::<Animation2D>().unwrap();
+        let animation = storage.get(animation).unwrap();
+        assert!(animation.spritesheet_animations.is_empty());
+        assert_eq!(animation.current_frame(0), Some(3));
+        drop(storage);
+
+        // A name the spritesheet doesn't have fails, and nothing's left behind.
+        let lost = entities.spawn_prefab(
+            &prefabs,
+            &PrefabInstance::new("lost"),
+            &mut components,
+            &spritesheets,
+        );
+        assert!(matches!(lost, Err(Error::Name(name)) if name == "run"));
+        assert_eq!(components.storage::<Animation2D>().unwrap().len(), 1);
     }
 }
@@ -8,8 +8,14 @@ use std::{fmt, io};
 
 #[derive(Debug)]
 pub enum Error {
+    Aseprite(String),
     Bind(gfx_hal::device::BindError),
+    BitmapFont(String),
+    Borrow(&'static str),
+    ComponentType(String),
     DescriptorAllocation(gfx_hal::pso::AllocationError),
+    Font(rusttype::Error),
+    Hierarchy(String),
     HostExecution(gfx_hal::error::HostExecutionError),
     Image(image::ImageError),
     ImageCreation(gfx_hal::image::CreationError),
@@ -17,13 +23,21 @@ pub enum Error {
     Index(),
     Io(io::Error),
     Mapping(gfx_hal::mapping::Error),
+    Markup(String),
     MemoryAllocation(gfx_hal::device::AllocationError),
+    Name(String),
     None(),
     NoSuitableMemory(),
     OutOfMemory(gfx_hal::device::OutOfMemory),
     OutOfMemoryOrDeviceLost(gfx_hal::device::OomOrDeviceLost),
+    Prefab(String),
+    Resource(&'static str),
     SerdeYaml(serde_yaml::Error),
     Shader(gfx_hal::device::ShaderError),
+    Snapshot(String),
+    Systems(Vec<Error>),
+    TextureSize(),
+    Validation(Vec<String>),
     WindowCreation(winit::CreationError),
     WrongType(&'static str),
 }
@@ -104,6 +118,12 @@ impl From<image::ImageError> for Error {
     }
 }
 
+impl From<rusttype::Error> for Error {
+    fn from(err: rusttype::Error) -> Error {
+        Error::Font(err)
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
         Error::Io(err)
@@ -0,0 +1,218 @@
+// event.rs
+// Typed event queues for systems to talk to each other through.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Events: messages from one part of the game to whatever else cares.
+//!
+//! Each type of event has its own [`Events`] queue, kept in the [`Resources`]. Anything
+//! that can lock the queue for writing can send an event, without knowing who, if anyone,
+//! is listening. Systems that want to hear about events keep a [`Subscription`] and read
+//! through it every frame.
+//!
+//! Queues are double-buffered. An event can be read for the rest of the frame it's sent in
+//! and all of the next one, so it doesn't matter whether the reader runs before or after
+//! the sender. After that it's dropped. [`Resources::update_events`] moves every queue on
+//! to the next frame, and should be called once at the start of each one, before any of
+//! its update steps, rather than once per step: otherwise a frame that catches up with
+//! several steps would drop events before systems that run once per frame could see them.
+//! A frame with no update steps can skip the call, so that events are still there for
+//! the next step to see.
+//!
+//! Reading through a [`Subscription`] hands out each event once, however many frames it
+//! stays in the queue. Reading with [`Events::iter`] hands out everything still there.
+//!
+//! [`Events`]: struct.Events.html
+//! [`Events::iter`]: struct.Events.html#method.iter
+//! [`Resources`]: ../system/struct.Resources.html
+//! [`Resources::update_events`]: ../system/struct.Resources.html#method.update_events
+//! [`Subscription`]: struct.Subscription.html
+
+use log::warn;
+use std::marker::PhantomData;
+use winit::VirtualKeyCode;
+
+use crate::{arena::Handle, serial::Index};
+
+/// A queue of events of type `T`.
+pub struct Events<T> {
+    /// Events sent during the last frame.
+    previous: Vec<T>,
+    /// Events sent during this frame.
+    current: Vec<T>,
+    /// The number of events ever sent before the first one in `previous`.
+    previous_start: u64,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Events {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Events<T> {
+        Events::default()
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// Drops last frame's events and starts a new frame. Called for every queue by
+    /// [`Resources::update_events`].
+    ///
+    /// [`Resources::update_events`]: ../system/struct.Resources.html#method.update_events
+    pub fn update(&mut self) {
+        self.previous_start += self.previous.len() as u64;
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    /// Every event sent during this frame or the last one, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(self.current.iter())
+    }
+
+    /// The events the subscription hasn't seen yet, oldest first. They're marked as seen
+    /// whether or not the iterator is used up.
+    pub fn read<'e>(&'e self, subscription: &mut Subscription<T>) -> impl Iterator<Item = &'e T> {
+        let sent = self.previous_start + (self.previous.len() + self.current.len()) as u64;
+        if subscription.next < self.previous_start {
+            warn!(
+                "{} {} events were dropped before they could be read",
+                self.previous_start - subscription.next,
+                std::any::type_name::<T>()
+            );
+        }
+        let start = subscription.next.max(self.previous_start);
+        subscription.next = sent;
+        self.iter().skip((start - self.previous_start) as usize)
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Keeps track of which events of type `T` a reader has already seen.
+///
+/// A new subscription starts with the oldest events still in the queue.
+pub struct Subscription<T> {
+    /// The number of events sent before the first one this subscription hasn't seen.
+    next: u64,
+    // `fn() -> T` keeps the subscription `Send` and `Sync` whatever `T` is.
+    event_type: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for Subscription<T> {
+    fn default() -> Self {
+        Subscription {
+            next: 0,
+            event_type: PhantomData,
+        }
+    }
+}
+
+impl<T> Subscription<T> {
+    pub fn new() -> Subscription<T> {
+        Subscription::default()
+    }
+}
+
+/// An entity's animation reached a frame with an event named on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationEvent {
+    pub entity: Handle,
+    /// The index of the animation in the entity's [`Animation2D`].
+    ///
+    /// [`Animation2D`]: ../component/struct.Animation2D.html
+    pub animation: Index,
+    /// The position of the frame in the animation's `frames`.
+    pub frame: Index,
+    /// The name given to the event in the animation's data.
+    pub name: String,
+}
+
+/// An entity's animation played through to the end and stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationFinished {
+    pub entity: Handle,
+    /// The index of the animation in the entity's [`Animation2D`].
+    ///
+    /// [`Animation2D`]: ../component/struct.Animation2D.html
+    pub animation: Index,
+}
+
+/// An entity's [`Tweener`] played its tween through.
+///
+/// [`Tweener`]: ../tween/struct.Tweener.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct TweenFinished {
+    pub entity: Handle,
+    /// The tween's `name`, if it has one.
+    pub name: Option<String>,
+}
+
+/// Two entities started touching. There's no collision detection built in, so this is
+/// sent by whatever game code checks for collisions, once when the contact starts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CollisionBegan {
+    pub entity: Handle,
+    pub other: Handle,
+}
+
+/// A key was pressed. Held keys repeat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyPressed {
+    pub keycode: VirtualKeyCode,
+}
+
+/// A dialogue window was closed after its last page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DialogueClosed {
+    /// The index of the script the window was showing.
+    pub script: Index,
+}
+
+/// The player moved from one map to another.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapChanged {
+    pub from: Index,
+    pub to: Index,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscribers_see_each_event_once() {
+        let mut events = Events::new();
+        let mut early = Subscription::new();
+        let mut late = Subscription::new();
+
+        events.send(1);
+        assert_eq!(events.read(&mut early).collect::<Vec<_>>(), vec![&1]);
+        events.send(2);
+        events.update();
+        events.send(3);
+        assert_eq!(events.read(&mut early).collect::<Vec<_>>(), vec![&2, &3]);
+        assert_eq!(events.read(&mut late).collect::<Vec<_>>(), vec![&1, &2, &3]);
+        assert_eq!(events.read(&mut late).count(), 0);
+
+        // Two updates without reading drop events the subscription never saw.
+        events.send(4);
+        events.update();
+        events.update();
+        events.send(5);
+        assert_eq!(events.read(&mut early).collect::<Vec<_>>(), vec![&5]);
+        assert_eq!(events.iter().collect::<Vec<_>>(), vec![&5]);
+    }
+}
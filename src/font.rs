@@ -0,0 +1,381 @@
+// font.rs
+// Loading fonts and choosing which font draws each character.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Fonts, font families, and fallback chains.
+//!
+//! A [`FontFamily`] groups the regular, bold, and italic faces of a typeface. A family can
+//! name other families as fallbacks, which supply any characters the family doesn't have.
+//! This is how text can mix, say, Latin and Cherokee or CJK characters without every font
+//! having to cover every script.
+//!
+//! Text is laid out with a [`FontStack`], which is a family with all of its fallbacks
+//! resolved.
+//!
+//! Fonts can be TrueType/OpenType outlines or pre-rendered [`BitmapFont`]s, and the two
+//! kinds can be mixed freely within a family or a fallback chain.
+//!
+//! [`BitmapFont`]: ../bitmap_font/struct.BitmapFont.html
+//! [`FontFamily`]: struct.FontFamily.html
+//! [`FontStack`]: struct.FontStack.html
+
+use rusttype::{Font, GlyphId, Point, PositionedGlyph, Scale, VMetrics};
+use serde::Deserialize;
+use std::{fs, sync::Arc};
+
+use crate::{
+    bitmap_font::{BitmapChar, BitmapFont},
+    error::Error,
+    serial::{Filename, Index},
+};
+
+/// A single font file.
+///
+/// Files ending in `.fnt` are loaded as [`BitmapFont`]s, and anything else as an outline font.
+///
+/// [`BitmapFont`]: ../bitmap_font/struct.BitmapFont.html
+#[derive(Deserialize)]
+pub struct GameFont<'a> {
+    pub index: Index,
+    pub file: Filename,
+    #[serde(skip)]
+    pub data: Option<FontFace<'a>>,
+}
+
+impl<'a> GameFont<'a> {
+    /// Reads and parses the font file, along with the pages of a bitmap font.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file can't be read, [`Error::Font`] if it isn't a
+    /// font `rusttype` understands, or [`Error::BitmapFont`] if it's a malformed bitmap font.
+    ///
+    /// [`Error::BitmapFont`]: ../error/enum.Error.html#variant.BitmapFont
+    /// [`Error::Font`]: ../error/enum.Error.html#variant.Font
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    pub fn initialize(&mut self) -> Result<(), Error> {
+        self.data = Some(if self.file.ends_with(".fnt") {
+            FontFace::Bitmap(Arc::new(BitmapFont::load(&self.file)?))
+        } else {
+            FontFace::Outline(Font::from_bytes(fs::read(&self.file)?)?)
+        });
+        Ok(())
+    }
+
+    /// A method for getting the `data` field because `unwrap()` unhelpfully moves instead
+    /// of borrowing.
+    pub fn get_data(&self) -> Result<&FontFace<'a>, Error> {
+        match &self.data {
+            Some(data) => Ok(data),
+            None => Err(Error::None()),
+        }
+    }
+}
+
+/// The style variations of a typeface.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FontStyle {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl FontStyle {
+    pub fn new(bold: bool, italic: bool) -> FontStyle {
+        match (bold, italic) {
+            (false, false) => FontStyle::Regular,
+            (true, false) => FontStyle::Bold,
+            (false, true) => FontStyle::Italic,
+            (true, true) => FontStyle::BoldItalic,
+        }
+    }
+
+    pub fn is_bold(self) -> bool {
+        self == FontStyle::Bold || self == FontStyle::BoldItalic
+    }
+
+    pub fn is_italic(self) -> bool {
+        self == FontStyle::Italic || self == FontStyle::BoldItalic
+    }
+}
+
+/// A named group of fonts that are styles of the same typeface.
+///
+/// Only the regular face is required. Styles without a face of their own are imitated
+/// by thickening or slanting a face that does exist.
+#[derive(Deserialize)]
+pub struct FontFamily {
+    pub name: String,
+    /// The index of each face's [`GameFont`].
+    ///
+    /// [`GameFont`]: struct.GameFont.html
+    pub regular: Index,
+    #[serde(default)]
+    pub bold: Option<Index>,
+    #[serde(default)]
+    pub italic: Option<Index>,
+    #[serde(default)]
+    pub bold_italic: Option<Index>,
+    /// The names of families to take characters from if this family doesn't have them,
+    /// in order of preference.
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+}
+
+/// A loaded font of either kind.
+#[derive(Clone)]
+pub enum FontFace<'a> {
+    Outline(Font<'a>),
+    Bitmap(Arc<BitmapFont>),
+}
+
+impl<'a> FontFace<'a> {
+    /// Whether the font has a glyph for the character.
+    pub fn has_glyph(&self, character: char) -> bool {
+        match self {
+            FontFace::Outline(font) => font.glyph(character).id() != GlyphId(0),
+            FontFace::Bitmap(font) => font.chars.contains_key(&character),
+        }
+    }
+
+    /// The font's vertical metrics with glyphs `pixel_height` pixels tall.
+    pub fn v_metrics(&self, pixel_height: f32) -> VMetrics {
+        match self {
+            FontFace::Outline(font) => font.v_metrics(Scale::uniform(pixel_height)),
+            FontFace::Bitmap(font) => {
+                let scale = pixel_height / font.size;
+                VMetrics {
+                    ascent: font.base * scale,
+                    descent: (font.base - font.line_height) * scale,
+                    line_gap: 0.0,
+                }
+            }
+        }
+    }
+
+    /// How far to move the caret after the character, in pixels.
+    pub fn advance_width(&self, character: char, pixel_height: f32) -> f32 {
+        match self {
+            FontFace::Outline(font) => font
+                .glyph(character)
+                .scaled(Scale::uniform(pixel_height))
+                .h_metrics()
+                .advance_width,
+            FontFace::Bitmap(font) => {
+                bitmap_char(font, character).x_advance * pixel_height / font.size
+            }
+        }
+    }
+
+    /// The kerning between two characters in pixels.
+    pub fn pair_kerning(&self, pixel_height: f32, first: char, second: char) -> f32 {
+        match self {
+            FontFace::Outline(font) => {
+                font.pair_kerning(Scale::uniform(pixel_height), first, second)
+            }
+            FontFace::Bitmap(font) => font.pair_kerning(first, second) * pixel_height / font.size,
+        }
+    }
+
+    /// The character's glyph with its origin on the baseline at `position`.
+    pub fn glyph(&self, character: char, pixel_height: f32, position: Point<f32>) -> FaceGlyph<'a> {
+        match self {
+            FontFace::Outline(font) => FaceGlyph::Outline(
+                font.glyph(character)
+                    .scaled(Scale::uniform(pixel_height))
+                    .positioned(position),
+            ),
+            FontFace::Bitmap(font) => FaceGlyph::Bitmap {
+                font: font.clone(),
+                character: bitmap_char(font, character),
+                scale: pixel_height / font.size,
+                position,
+            },
+        }
+    }
+}
+
+/// Looks up a character in a bitmap font, using `?` for characters it doesn't have.
+fn bitmap_char(font: &BitmapFont, character: char) -> BitmapChar {
+    font.chars
+        .get(&character)
+        .or_else(|| font.chars.get(&'?'))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// A glyph from either kind of font, placed with its origin on the baseline.
+#[derive(Clone)]
+pub enum FaceGlyph<'a> {
+    Outline(PositionedGlyph<'a>),
+    Bitmap {
+        font: Arc<BitmapFont>,
+        character: BitmapChar,
+        /// The size of a texel of the font's pages in pixels.
+        scale: f32,
+        position: Point<f32>,
+    },
+}
+
+impl<'a> FaceGlyph<'a> {
+    pub fn position(&self) -> Point<f32> {
+        match self {
+            FaceGlyph::Outline(glyph) => glyph.position(),
+            FaceGlyph::Bitmap { position, .. } => *position,
+        }
+    }
+
+    /// Returns the same glyph moved by the given distance.
+    pub fn translated(&self, dx: f32, dy: f32) -> FaceGlyph<'a> {
+        let position = self.position();
+        let position = rusttype::point(position.x + dx, position.y + dy);
+        match self {
+            FaceGlyph::Outline(glyph) => {
+                FaceGlyph::Outline(glyph.unpositioned().clone().positioned(position))
+            }
+            FaceGlyph::Bitmap {
+                font,
+                character,
+                scale,
+                ..
+            } => FaceGlyph::Bitmap {
+                font: font.clone(),
+                character: *character,
+                scale: *scale,
+                position,
+            },
+        }
+    }
+}
+
+/// The four faces of a family, ready to draw with.
+struct Faces<'a> {
+    regular: FontFace<'a>,
+    bold: Option<FontFace<'a>>,
+    italic: Option<FontFace<'a>>,
+    bold_italic: Option<FontFace<'a>>,
+}
+
+/// The font a [`FontStack`] chose to draw a character with.
+///
+/// [`FontStack`]: struct.FontStack.html
+pub struct FaceChoice<'s, 'a> {
+    pub font: &'s FontFace<'a>,
+    /// Identifies the font within the stack, so that kerning is only applied between
+    /// characters drawn with the same font.
+    pub face: usize,
+    /// The part of the requested style that has to be imitated.
+    pub synthesized: FontStyle,
+}
+
+/// A font family together with all of its fallbacks, in the order they're tried.
+pub struct FontStack<'a> {
+    /// The name of the primary family.
+    pub name: String,
+    families: Vec<Faces<'a>>,
+}
+
+impl<'a> FontStack<'a> {
+    /// Resolves the named family and its fallbacks, and their fallbacks, and so on.
+    /// Families that appear more than once in the chain are only tried the first time.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if a family or font doesn't exist, or [`Error::None`] if a
+    /// font hasn't been loaded.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    /// [`Error::None`]: ../error/enum.Error.html#variant.None
+    pub fn new(
+        family: &str,
+        families: &[FontFamily],
+        fonts: &[GameFont<'a>],
+    ) -> Result<FontStack<'a>, Error> {
+        let mut chain: Vec<&FontFamily> = vec![];
+        let mut pending = vec![family];
+        while let Some(name) = pending.pop() {
+            if chain.iter().any(|family| family.name == name) {
+                continue;
+            }
+            let family = families
+                .iter()
+                .find(|family| family.name == name)
+                .ok_or(Error::Index())?;
+            chain.push(family);
+            // Pushed in reverse so that the first fallback is tried first.
+            pending.extend(family.fallbacks.iter().rev().map(String::as_str));
+        }
+
+        let font = |index: Index| -> Result<FontFace<'a>, Error> {
+            Ok(fonts.get(index).ok_or(Error::Index())?.get_data()?.clone())
+        };
+        let optional_font = |index: Option<Index>| -> Result<Option<FontFace<'a>>, Error> {
+            index.map(font).transpose()
+        };
+
+        Ok(FontStack {
+            name: family.to_string(),
+            families: chain
+                .into_iter()
+                .map(|family| {
+                    Ok(Faces {
+                        regular: font(family.regular)?,
+                        bold: optional_font(family.bold)?,
+                        italic: optional_font(family.italic)?,
+                        bold_italic: optional_font(family.bold_italic)?,
+                    })
+                })
+                .collect::<Result<Vec<Faces>, Error>>()?,
+        })
+    }
+
+    /// The regular face of the primary family, whose metrics set the size of lines.
+    pub fn primary(&self) -> &FontFace<'a> {
+        &self.families[0].regular
+    }
+
+    /// Chooses the font to draw `character` with in the given style: the first family in
+    /// the stack that has the character. If none do, the primary family is used so that
+    /// its "missing character" glyph is shown.
+    pub fn choose(&self, character: char, style: FontStyle) -> FaceChoice<'_, 'a> {
+        self.families
+            .iter()
+            .enumerate()
+            .map(|(family, faces)| Self::face(family, faces, style))
+            .find(|choice| choice.font.has_glyph(character))
+            .unwrap_or_else(|| Self::face(0, &self.families[0], style))
+    }
+
+    /// Picks the face of a family that best matches `style`. Styles without a face of
+    /// their own fall back to the closest face that exists, and the rest is synthesized.
+    fn face<'s>(
+        family: usize,
+        faces: &'s Faces<'a>,
+        style: FontStyle,
+    ) -> FaceChoice<'s, 'a> {
+        // Each family gets four slots, one per style.
+        let choice = |font: &'s FontFace<'a>, slot: usize, synthesized: FontStyle| FaceChoice {
+            font,
+            face: family * 4 + slot,
+            synthesized,
+        };
+        match style {
+            FontStyle::Regular => choice(&faces.regular, 0, FontStyle::Regular),
+            FontStyle::Bold => match &faces.bold {
+                Some(bold) => choice(bold, 1, FontStyle::Regular),
+                None => choice(&faces.regular, 0, FontStyle::Bold),
+            },
+            FontStyle::Italic => match &faces.italic {
+                Some(italic) => choice(italic, 2, FontStyle::Regular),
+                None => choice(&faces.regular, 0, FontStyle::Italic),
+            },
+            FontStyle::BoldItalic => match (&faces.bold_italic, &faces.bold, &faces.italic) {
+                (Some(bold_italic), _, _) => choice(bold_italic, 3, FontStyle::Regular),
+                (None, Some(bold), _) => choice(bold, 1, FontStyle::Italic),
+                (None, None, Some(italic)) => choice(italic, 2, FontStyle::Bold),
+                (None, None, None) => choice(&faces.regular, 0, FontStyle::BoldItalic),
+            },
+        }
+    }
+}
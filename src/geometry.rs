@@ -5,8 +5,9 @@
 use nalgebra_glm as glm;
 use nalgebra_glm::{Mat3, Mat4, Vec2};
 use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
 
-use crate::serial::{Color, Index, Position2D, Position3D};
+use crate::serial::{Color, Index, Position2D, Position3D, Size};
 
 /// The UV coordinates to make a texture fit a quad precisely.
 pub const QUAD_UVS: [[f32; 3]; 4] = [
@@ -53,6 +54,25 @@ pub enum LayerDepth {
     Background = 1,
 }
 
+/// An axis-aligned rectangle. Positions are measured from the top-left corner.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Rect {
+    pub position: Position2D,
+    pub size: Size,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            position: Position2D { x, y },
+            size: Size {
+                x: width,
+                y: height,
+            },
+        }
+    }
+}
+
 /// Contains all of the data needed for a vertex.
 #[derive(Copy, Clone, Debug)]
 pub struct VertexData {
@@ -66,13 +86,20 @@ pub struct VertexData {
 // but I don't feel like figuring out how to implement Deserialize
 // for a type from an external library.
 /// A representation of objects' positions, orientations, etc.
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct TransformData {
     pub translation: Vec<f32>,
     pub scaling: Vec<f32>,
     pub rotation: f32,
 }
 
+impl Default for TransformData {
+    /// The identity transform.
+    fn default() -> Self {
+        TransformData::new(glm::vec2(0.0, 0.0), glm::vec2(1.0, 1.0), 0.0)
+    }
+}
+
 impl TransformData {
     pub fn new(translation: Vec2, scaling: Vec2, rotation: f32) -> TransformData {
         TransformData {
@@ -81,6 +108,73 @@ impl TransformData {
             rotation,
         }
     }
+
+    /// Splits a matrix built by [`Transform2D::transformation_matrix`] back into its parts.
+    ///
+    /// Rotating something that's scaled by different amounts along x and y skews it, which
+    /// `TransformData` can't represent, so matrices like that only come back approximately.
+    ///
+    /// [`Transform2D::transformation_matrix`]: trait.Transform2D.html#method.transformation_matrix
+    pub fn from_matrix(matrix: &Mat3) -> TransformData {
+        let x_axis = glm::vec2(matrix[(0, 0)], matrix[(1, 0)]);
+        let y_axis = glm::vec2(matrix[(0, 1)], matrix[(1, 1)]);
+        let rotation = x_axis.y.atan2(x_axis.x);
+
+        // A mirrored matrix is treated as a flip along y.
+        let determinant = x_axis.x * y_axis.y - x_axis.y * y_axis.x;
+        let y_scale = if determinant < 0.0 {
+            -glm::length(&y_axis)
+        } else {
+            glm::length(&y_axis)
+        };
+
+        TransformData::new(
+            glm::vec2(matrix[(0, 2)], matrix[(1, 2)]),
+            glm::vec2(glm::length(&x_axis), y_scale),
+            rotation,
+        )
+    }
+
+    /// The transform `alpha` of the way from `self` to `next`, where 0 is `self` and 1 is
+    /// `next`.
+    pub fn interpolate(&self, next: &TransformData, alpha: f32) -> TransformData {
+        let lerp = |from: &f32, to: &f32| from + (to - from) * alpha;
+        TransformData {
+            translation: self
+                .translation
+                .iter()
+                .zip(&next.translation)
+                .map(|(from, to)| lerp(from, to))
+                .collect(),
+            scaling: self
+                .scaling
+                .iter()
+                .zip(&next.scaling)
+                .map(|(from, to)| lerp(from, to))
+                .collect(),
+            rotation: lerp(&self.rotation, &next.rotation),
+        }
+    }
+}
+
+/// Interpolates between two matrices built by [`Transform2D::transformation_matrix`], as
+/// [`TransformData::interpolate`] does, turning whichever way round is shorter.
+///
+/// [`Transform2D::transformation_matrix`]: trait.Transform2D.html#method.transformation_matrix
+/// [`TransformData::interpolate`]: struct.TransformData.html#method.interpolate
+pub fn interpolate_matrix(previous: &Mat3, next: &Mat3, alpha: f32) -> Mat3 {
+    if previous == next {
+        return *next;
+    }
+    let from = TransformData::from_matrix(previous);
+    let mut to = TransformData::from_matrix(next);
+    let turn = to.rotation - from.rotation;
+    if turn > PI {
+        to.rotation -= 2.0 * PI;
+    } else if turn < -PI {
+        to.rotation += 2.0 * PI;
+    }
+    from.interpolate(&to, alpha).transformation_matrix()
 }
 
 /// This trait is to ensure that every component that can be drawn
@@ -104,9 +198,159 @@ pub trait Transform2D {
     }
 }
 
+impl Transform2D for TransformData {
+    fn rotation_matrix(&self) -> Mat3 {
+        glm::rotation2d(self.rotation)
+    }
+
+    fn scaling_matrix(&self) -> Mat3 {
+        glm::scaling2d(&glm::vec2(self.scaling[0], self.scaling[1]))
+    }
+
+    fn translation_matrix(&self) -> Mat3 {
+        glm::translation2d(&glm::vec2(self.translation[0], self.translation[1]))
+    }
+}
+
+/// How a sprite's quad sits on its transform: which point of it is at the transform's
+/// translation, which is also the point it turns and scales around, and whether it's
+/// mirrored.
+///
+/// The matrices assume the quad is [`QUAD_VERTICES`], one unit across and centered on the
+/// origin. Mirroring happens around the origin point, so a sprite with its origin at its
+/// feet keeps its feet in the same place when it turns around.
+///
+/// [`QUAD_VERTICES`]: constant.QUAD_VERTICES.html
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Placement {
+    /// Mirror the sprite left to right.
+    #[serde(default)]
+    pub flip_x: bool,
+    /// Mirror the sprite top to bottom.
+    #[serde(default)]
+    pub flip_y: bool,
+    /// The point of the sprite that's put at its translation, from (0, 0) at the top-left
+    /// corner to (1, 1) at the bottom-right. The center, or a spritesheet frame's own
+    /// pivot, if not given.
+    #[serde(default)]
+    pub origin: Option<Position2D>,
+    /// Round the sprite's final position to whole virtual pixels, the size a world unit
+    /// is on screen, so pixel art doesn't shimmer as it moves.
+    #[serde(default)]
+    pub pixel_snap: bool,
+}
+
+impl Placement {
+    /// -1.0 along the axes the sprite's mirrored on and 1.0 along the others.
+    fn flip(&self) -> Vec2 {
+        let sign = |flip| if flip { -1.0 } else { 1.0 };
+        glm::vec2(sign(self.flip_x), sign(self.flip_y))
+    }
+
+    /// Moves a matrix's translation to the nearest whole virtual pixel, if the sprite
+    /// snaps. `pixel_size` is how big a virtual pixel is in the matrix's space.
+    pub fn snap(&self, matrix: Mat3, pixel_size: f32) -> Mat3 {
+        if !self.pixel_snap || pixel_size <= 0.0 {
+            return matrix;
+        }
+        let mut snapped = matrix;
+        for row in 0..2 {
+            snapped[(row, 2)] = (matrix[(row, 2)] / pixel_size).round() * pixel_size;
+        }
+        snapped
+    }
+}
+
+impl Transform2D for Placement {
+    fn scaling_matrix(&self) -> Mat3 {
+        glm::scaling2d(&self.flip())
+    }
+
+    // Applied after mirroring, so the offset is mirrored too.
+    fn translation_matrix(&self) -> Mat3 {
+        let origin = self.origin.unwrap_or(Position2D { x: 0.5, y: 0.5 });
+        // Origins are measured with y pointing down, and the quad with y pointing up.
+        let offset = glm::vec2(0.5 - origin.x, origin.y - 0.5);
+        glm::translation2d(&offset.component_mul(&self.flip()))
+    }
+}
+
+/// How fast something moves, turns and grows while it's being steered, per second of game
+/// time.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Movement2D {
     pub delta_rotation: f32,
     pub delta_scale: Vec<f32>,
     pub delta_translate: Vec<f32>,
 }
+
+/// What part of the world is on screen.
+///
+/// Kept in the [`Resources`] so systems can move it, e.g. to follow a sprite.
+///
+/// [`Resources`]: ../system/struct.Resources.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Camera {
+    /// The point in the world at the center of the screen.
+    pub position: Position2D,
+    /// How much bigger things look than their actual size. 1.0 is actual size.
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Position2D { x: 0.0, y: 0.0 },
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    /// Transforms world coordinates into coordinates relative to the center of the screen.
+    pub fn view_matrix(&self) -> Mat3 {
+        glm::scaling2d(&glm::vec2(self.zoom, self.zoom))
+            * glm::translation2d(&glm::vec2(-self.position.x, -self.position.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation_turns_the_short_way() {
+        let from = TransformData::new(glm::vec2(0.0, 0.0), glm::vec2(1.0, 1.0), 3.0);
+        let to = TransformData::new(glm::vec2(10.0, -4.0), glm::vec2(3.0, 1.0), -3.0);
+        let halfway = TransformData::from_matrix(&interpolate_matrix(
+            &from.transformation_matrix(),
+            &to.transformation_matrix(),
+            0.5,
+        ));
+        assert!((halfway.translation[0] - 5.0).abs() < 1e-4);
+        assert!((halfway.translation[1] + 2.0).abs() < 1e-4);
+        assert!((halfway.scaling[0] - 2.0).abs() < 1e-4);
+        // Going from 3 to -3 through π is shorter than going through 0.
+        assert!((halfway.rotation.abs() - PI).abs() < 1e-4);
+    }
+
+    #[test]
+    fn placement_mirrors_around_the_origin() {
+        let placement = Placement {
+            flip_x: true,
+            flip_y: false,
+            origin: Some(Position2D { x: 0.25, y: 1.0 }),
+            pixel_snap: true,
+        };
+        let corner = |vertex: [f32; 2]| {
+            (placement.transformation_matrix() * glm::vec3(vertex[0], vertex[1], 1.0)).xy()
+        };
+        // The bottom-left corner is a quarter of the way from the origin, so mirrored it
+        // ends up a quarter to the right, with the bottom edge still on the origin.
+        assert_eq!(corner(QUAD_VERTICES[1]), glm::vec2(0.25, 0.0));
+        assert_eq!(corner(QUAD_VERTICES[3]), glm::vec2(-0.75, 1.0));
+
+        let snapped = placement.snap(glm::translation2d(&glm::vec2(10.4, -3.3)), 2.0);
+        assert_eq!((snapped[(0, 2)], snapped[(1, 2)]), (10.0, -4.0));
+    }
+}
@@ -3,28 +3,51 @@
 // Version 0.0.000000001
 // (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
 
+pub mod animation;
+pub mod arena;
+pub mod aseprite;
+pub mod bitmap_font;
 pub mod component;
 pub mod config;
 pub mod data;
+pub mod dialogue;
 pub mod draw;
 pub mod entity;
 pub mod error;
+pub mod event;
+pub mod font;
 pub mod geometry;
+pub mod markup;
 pub mod pipeline;
+pub mod prefab;
 pub mod render;
 pub mod resource;
+pub mod save;
+pub mod sdf;
 pub mod serial;
+pub mod storage;
+pub mod system;
 pub mod text;
 pub mod texture;
 pub mod time;
+pub mod tween;
+pub mod validate;
 
+use std::time::Instant;
 use winit::{
-    dpi::LogicalSize, Event, EventsLoop, KeyboardInput, VirtualKeyCode, WindowBuilder, WindowEvent,
+    dpi::LogicalSize, ElementState, Event, EventsLoop, KeyboardInput, VirtualKeyCode,
+    WindowBuilder, WindowEvent,
 };
 
 use crate::{
-    component::ReceiveInput, config::Configuration, data::DataManager, draw::DrawingSystem,
+    config::Configuration,
+    data::DataManager,
+    draw::DrawingSystem,
     error::Error,
+    event::{Events, KeyPressed},
+    save::{SaveGame, QUICKSAVE_PATH},
+    system::{Input, Scheduler, Stage},
+    time::{FixedTimestep, GameClock},
 };
 
 /*
@@ -71,9 +94,33 @@ fn main() -> Result<(), Error> {
         &mut data.resource_manager,
     )?;
 
-    // Game loop.
+    let mut scheduler = Scheduler::new();
+    system::register(&mut scheduler);
+
+    // Game loop. The game is updated in fixed steps, as many as have built up since the
+    // last frame, and drawn once per frame, partway between the last two steps.
+    let mut timestep = FixedTimestep::new(
+        configuration.simulation.updates_per_second,
+        configuration.simulation.max_updates_per_frame,
+    );
+    let mut last_frame = Instant::now();
     loop {
+        let steps = timestep.accumulate(last_frame.elapsed());
+        last_frame = Instant::now();
+
+        // Event queues move on once per frame, not once per step, so nothing sent during
+        // this frame's steps is dropped before the `Render` stage reads it. Frames with no
+        // steps leave them be, so `Update` systems still see what the last frame sent.
+        if steps > 0 {
+            data.resources.update_events()?;
+        }
+
         let mut quitting = false;
+        let mut advancing_dialogue = false;
+        let mut quick_saving = false;
+        let mut quick_loading = false;
+        let mut input = data.resources.get_mut::<Input>()?;
+        input.begin_frame();
 
         // If the window is closed, or Escape is pressed, quit
         event_loop.poll_events(|event| {
@@ -91,37 +138,79 @@ fn main() -> Result<(), Error> {
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Right),
-                                ..
-                            },
-                        ..
-                    } => {
-                        data.component_manager
-                            .keyboard_response(VirtualKeyCode::Right)
-                            .unwrap();
-                    }
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Left),
+                                state,
+                                virtual_keycode: Some(keycode),
                                 ..
                             },
                         ..
-                    } => {
-                        data.component_manager
-                            .keyboard_response(VirtualKeyCode::Left)
-                            .unwrap();
-                    }
+                    } => match state {
+                        ElementState::Pressed => {
+                            input.press(keycode);
+                            if keycode == VirtualKeyCode::Return
+                                || keycode == VirtualKeyCode::Space
+                            {
+                                advancing_dialogue = true;
+                            }
+                            match keycode {
+                                VirtualKeyCode::F5 => quick_saving = true,
+                                VirtualKeyCode::F9 => quick_loading = true,
+                                _ => {}
+                            }
+                        }
+                        ElementState::Released => input.release(keycode),
+                    },
                     _ => {}
                 }
             }
         });
+        let mut key_events = data.resources.get_mut::<Events<KeyPressed>>()?;
+        for keycode in &input.pressed {
+            key_events.send(KeyPressed { keycode: *keycode });
+        }
+        drop(key_events);
+        drop(input);
 
         if quitting {
             break;
         }
 
-        drawing_system.draw_frame(&data);
+        if advancing_dialogue {
+            if let Err(err) = data.advance_dialogue() {
+                log::warn!("Couldn't advance dialogue: {}", err);
+            }
+        }
+        if quick_saving {
+            if let Err(err) = data.save_game().and_then(|save| save.write(QUICKSAVE_PATH)) {
+                log::warn!("Couldn't quick-save: {}", err);
+            }
+        }
+        if quick_loading {
+            if let Err(err) = SaveGame::read(QUICKSAVE_PATH).and_then(|save| data.load_game(save))
+            {
+                log::warn!("Couldn't quick-load: {}", err);
+            }
+        }
+
+        for _ in 0..steps {
+            data.entity_manager.record_transforms();
+            data.component_manager.record_transforms()?;
+            data.resources
+                .get_mut::<GameClock>()?
+                .advance(timestep.step());
+
+            // Each failing system is logged by the scheduler, and the others still run, so
+            // the game carries on with the next step.
+            let _ = scheduler.run_update(data.world());
+            data.entity_manager.update_transforms();
+
+            let delta = data.resources.get::<GameClock>()?.delta_millis();
+            if let Some(dialogue) = &mut data.active_dialogue {
+                dialogue.update(delta);
+            }
+        }
+
+        let _ = scheduler.run_stage(Stage::Render, data.world());
+        drawing_system.draw_frame(&data, timestep.alpha());
     }
 
     drawing_system.clean_up()?;
@@ -0,0 +1,342 @@
+// markup.rs
+// Inline markup for styled text.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Parsing of inline text markup into styled runs.
+//!
+//! Markup tags are enclosed in square brackets. Tags that apply a style are closed
+//! with a matching `[/tag]`; tags that mark a point in the text stand alone.
+//!
+//! + `[color=#ff0]...[/color]`: Draws the enclosed text in the given color. Accepts
+//!   `#rgb`, `#rgba`, `#rrggbb`, and `#rrggbbaa`.
+//! + `[b]...[/b]`: Draws the enclosed text in bold.
+//! + `[i]...[/i]`: Draws the enclosed text in italics.
+//! + `[wave]...[/wave]`: Makes the enclosed text bob up and down.
+//! + `[shake]...[/shake]`: Makes the enclosed text jitter in place.
+//! + `[pause=500]`: Pauses a typewriter-style reveal for the given number of milliseconds.
+//! + `[icon=3]`: Places frame 3 of the icon spritesheet inline with the text.
+//!
+//! A literal `[` is written as `[[`.
+
+use crate::{
+    error::Error,
+    serial::{Color, Index},
+};
+
+/// Animated movement applied to individual glyphs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextEffect {
+    /// The glyph stays where the layout put it.
+    None,
+    /// Glyphs bob up and down in a sine wave that travels along the text.
+    Wave,
+    /// Glyphs jitter randomly around their positions.
+    Shake,
+}
+
+/// The visual style of a run of text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    pub color: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub effect: TextEffect,
+}
+
+impl TextStyle {
+    /// A plain style in the given color.
+    pub fn new(color: Color) -> TextStyle {
+        TextStyle {
+            color,
+            bold: false,
+            italic: false,
+            effect: TextEffect::None,
+        }
+    }
+}
+
+/// A piece of parsed markup.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextRun {
+    /// A span of characters that all share a style.
+    Text { text: String, style: TextStyle },
+    /// A frame of the icon spritesheet drawn inline, tinted by the style's color.
+    Icon { frame: Index, style: TextStyle },
+    /// A pause in the reveal of the text, in milliseconds.
+    Pause(u32),
+}
+
+/// Text that has been broken up into styled runs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RichText {
+    pub runs: Vec<TextRun>,
+}
+
+impl RichText {
+    /// Parses the given markup, using `base_color` for any text without a `[color]` tag.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Markup`] if a tag is unknown, unclosed, closed out of order,
+    /// or has a malformed value.
+    ///
+    /// [`Error::Markup`]: ../error/enum.Error.html#variant.Markup
+    pub fn parse(markup: &str, base_color: Color) -> Result<RichText, Error> {
+        let mut parser = Parser {
+            runs: vec![],
+            text: String::new(),
+            colors: vec![base_color],
+            open_tags: vec![],
+            bold: 0,
+            italic: 0,
+            effects: vec![TextEffect::None],
+        };
+
+        let mut chars = markup.char_indices().peekable();
+        while let Some((position, character)) = chars.next() {
+            if character != '[' {
+                parser.text.push(character);
+                continue;
+            }
+
+            // `[[` is an escaped bracket.
+            if let Some((_, '[')) = chars.peek() {
+                chars.next();
+                parser.text.push('[');
+                continue;
+            }
+
+            let mut tag = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, ']')) => break,
+                    Some((_, character)) => tag.push(character),
+                    None => {
+                        return Err(Error::Markup(format!(
+                            "unterminated tag at byte {}",
+                            position
+                        )))
+                    }
+                }
+            }
+            parser.tag(&tag, position)?;
+        }
+
+        if let Some(tag) = parser.open_tags.last() {
+            return Err(Error::Markup(format!("[{}] is never closed", tag)));
+        }
+        parser.flush();
+        Ok(RichText { runs: parser.runs })
+    }
+
+    /// Returns the text with all markup removed. Icons are not included.
+    pub fn plain_text(&self) -> String {
+        self.runs
+            .iter()
+            .filter_map(|run| match run {
+                TextRun::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Keeps track of the open tags while parsing.
+struct Parser {
+    runs: Vec<TextRun>,
+    /// Text read since the style last changed.
+    text: String,
+    colors: Vec<Color>,
+    open_tags: Vec<String>,
+    /// How many `[b]` tags are open.
+    bold: usize,
+    /// How many `[i]` tags are open.
+    italic: usize,
+    effects: Vec<TextEffect>,
+}
+
+impl Parser {
+    fn style(&self) -> TextStyle {
+        TextStyle {
+            color: *self.colors.last().unwrap(),
+            bold: self.bold > 0,
+            italic: self.italic > 0,
+            effect: *self.effects.last().unwrap(),
+        }
+    }
+
+    /// Ends the current run of text, if there is one.
+    fn flush(&mut self) {
+        if !self.text.is_empty() {
+            let style = self.style();
+            self.runs.push(TextRun::Text {
+                text: std::mem::take(&mut self.text),
+                style,
+            });
+        }
+    }
+
+    fn tag(&mut self, tag: &str, position: usize) -> Result<(), Error> {
+        let (name, value) = match tag.find('=') {
+            Some(split) => (&tag[..split], Some(tag[split + 1..].trim())),
+            None => (tag, None),
+        };
+        let name = name.trim();
+
+        // Every tag changes the style or adds a non-text run, so the current run ends here.
+        self.flush();
+
+        if let Some(name) = name.strip_prefix('/') {
+            if self.open_tags.last().map(String::as_str) != Some(name) {
+                return Err(Error::Markup(format!(
+                    "unexpected [/{}] at byte {}",
+                    name, position
+                )));
+            }
+            self.open_tags.pop();
+            match name {
+                "color" => {
+                    self.colors.pop();
+                }
+                "b" => self.bold -= 1,
+                "i" => self.italic -= 1,
+                _ => {
+                    self.effects.pop();
+                }
+            }
+            return Ok(());
+        }
+
+        match (name, value) {
+            ("color", Some(value)) => {
+                let color = parse_color(value).ok_or_else(|| {
+                    Error::Markup(format!("bad color {} at byte {}", value, position))
+                })?;
+                self.colors.push(color);
+            }
+            ("b", None) => self.bold += 1,
+            ("i", None) => self.italic += 1,
+            ("wave", None) => self.effects.push(TextEffect::Wave),
+            ("shake", None) => self.effects.push(TextEffect::Shake),
+            ("pause", Some(value)) => {
+                let millis = value.parse().map_err(|_| {
+                    Error::Markup(format!("bad pause length {} at byte {}", value, position))
+                })?;
+                self.runs.push(TextRun::Pause(millis));
+                return Ok(());
+            }
+            ("icon", Some(value)) => {
+                let frame = value.parse().map_err(|_| {
+                    Error::Markup(format!("bad icon index {} at byte {}", value, position))
+                })?;
+                let style = self.style();
+                self.runs.push(TextRun::Icon { frame, style });
+                return Ok(());
+            }
+            _ => {
+                return Err(Error::Markup(format!(
+                    "unknown tag [{}] at byte {}",
+                    tag, position
+                )))
+            }
+        }
+        self.open_tags.push(name.to_string());
+        Ok(())
+    }
+}
+
+/// Parses a hex color of the form `#rgb`, `#rgba`, `#rrggbb`, or `#rrggbbaa`.
+pub fn parse_color(hex: &str) -> Option<Color> {
+    if !hex.starts_with('#') || !hex.is_ascii() {
+        return None;
+    }
+    let digits = &hex[1..];
+
+    // Expand the short forms so that each channel has two digits.
+    let digits = match digits.len() {
+        3 | 4 => digits.chars().flat_map(|digit| vec![digit, digit]).collect(),
+        6 | 8 => digits.to_string(),
+        _ => return None,
+    };
+
+    let mut channels = [1.0; 4];
+    for (channel, pair) in channels.iter_mut().zip(digits.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).ok()?;
+        *channel = f32::from(u8::from_str_radix(pair, 16).ok()?) / 255.0;
+    }
+
+    Some(Color {
+        r: channels[0],
+        g: channels[1],
+        b: channels[2],
+        a: channels[3],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    #[test]
+    fn nested_styles() {
+        let text = RichText::parse("a[b]b[wave]c[/wave][/b][pause=250][icon=3]", WHITE).unwrap();
+        let bold = TextStyle {
+            bold: true,
+            ..TextStyle::new(WHITE)
+        };
+        assert_eq!(
+            text.runs,
+            vec![
+                TextRun::Text {
+                    text: "a".to_string(),
+                    style: TextStyle::new(WHITE),
+                },
+                TextRun::Text {
+                    text: "b".to_string(),
+                    style: bold,
+                },
+                TextRun::Text {
+                    text: "c".to_string(),
+                    style: TextStyle {
+                        effect: TextEffect::Wave,
+                        ..bold
+                    },
+                },
+                TextRun::Pause(250),
+                TextRun::Icon {
+                    frame: 3,
+                    style: TextStyle::new(WHITE),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn colors() {
+        let yellow = parse_color("#ff0").unwrap();
+        assert_eq!((yellow.r, yellow.g, yellow.b, yellow.a), (1.0, 1.0, 0.0, 1.0));
+        assert_eq!(parse_color("#ff000080").unwrap().a, 128.0 / 255.0);
+        assert!(parse_color("ff0").is_none());
+        assert!(parse_color("#ff00").is_some());
+        assert!(parse_color("#ff000").is_none());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(RichText::parse("[b]unclosed", WHITE).is_err());
+        assert!(RichText::parse("[b][wave][/b][/wave]", WHITE).is_err());
+        assert!(RichText::parse("[sparkle]", WHITE).is_err());
+        assert!(RichText::parse("[pause=soon]", WHITE).is_err());
+        assert_eq!(
+            RichText::parse("[[b]", WHITE).unwrap().plain_text(),
+            "[b]".to_string()
+        );
+    }
+}
@@ -21,16 +21,50 @@ use gfx_hal::{
     Backend as GfxBackend, DescriptorPool, Device, Primitive,
 };
 
-use crate::{error::Error, render::FormattedVertexData, texture::Texture};
+use crate::{error::Error, render::FormattedVertexData, sdf::SdfUniforms, texture::Texture};
 
 use self::backend::Backend;
 use gfx_backend_metal as backend;
 
+/// Which fragment shader a pipeline draws with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shading {
+    /// Draws the texture as-is, via `shader.frag`.
+    Textured,
+    /// Treats the texture as a signed-distance-field atlas, via `sdf.frag`. Effects are
+    /// pushed as [`SdfUniforms`] constants.
+    ///
+    /// [`SdfUniforms`]: ../sdf/struct.SdfUniforms.html
+    DistanceField,
+}
+
+impl Shading {
+    /// The range of 32-bit words of push constants the fragment shader reads.
+    pub fn push_constants(self) -> std::ops::Range<u32> {
+        match self {
+            Shading::Textured => 0..0,
+            Shading::DistanceField => {
+                0..(std::mem::size_of::<SdfUniforms>() / std::mem::size_of::<u32>()) as u32
+            }
+        }
+    }
+
+    /// How textures are sampled. Distance fields are interpolated between texels so that
+    /// edges stay smooth when the atlas is scaled or rotated.
+    pub fn filter(self) -> Filter {
+        match self {
+            Shading::Textured => Filter::Nearest,
+            Shading::DistanceField => Filter::Linear,
+        }
+    }
+}
+
 #[derive(Debug)]
 /// Holds all the data needed to create and use a pipeline.
 pub struct PipelineData {
     pub pipeline_layout: <Backend as GfxBackend>::PipelineLayout,
     pub pipeline: <Backend as GfxBackend>::GraphicsPipeline,
+    pub shading: Shading,
 
     // It seems like one sampler can handle any number of textures as long as
     // only one sampler configuration is needed.
@@ -61,6 +95,7 @@ impl PipelineData {
         capabilities: &SurfaceCapabilities,
         textures: &[Texture],
         surface_color_format: Format,
+        shading: Shading,
     ) -> Result<PipelineData, Error> {
         // Create the set layouts.
         let sampler_layout = device.create_descriptor_set_layout(
@@ -101,7 +136,7 @@ impl PipelineData {
         )?;
 
         // Set up the sampler and descriptor sets.
-        let sampler = device.create_sampler(SamplerInfo::new(Filter::Nearest, WrapMode::Tile))?;
+        let sampler = device.create_sampler(SamplerInfo::new(shading.filter(), WrapMode::Tile))?;
         let sampler_set = pool.allocate_set(&sampler_layout)?;
         let texture_sets = textures
             .iter()
@@ -135,8 +170,15 @@ impl PipelineData {
         device.write_descriptor_sets(sets);
 
         // Create the pipeline layout.
-        let pipeline_layout =
-            device.create_pipeline_layout(vec![&sampler_layout, &texture_layout], &[])?;
+        let push_constants = shading.push_constants();
+        let pipeline_layout = if push_constants.end > 0 {
+            device.create_pipeline_layout(
+                vec![&sampler_layout, &texture_layout],
+                &[(ShaderStageFlags::FRAGMENT, push_constants)],
+            )?
+        } else {
+            device.create_pipeline_layout(vec![&sampler_layout, &texture_layout], &[])?
+        };
 
         // Set up shaders.
         let vertex_shader_module = {
@@ -145,7 +187,10 @@ impl PipelineData {
         };
 
         let fragment_shader_module = {
-            let spirv = include_bytes!("shaders/gen/shader.frag.spv");
+            let spirv: &[u8] = match shading {
+                Shading::Textured => include_bytes!("shaders/gen/shader.frag.spv"),
+                Shading::DistanceField => include_bytes!("shaders/gen/sdf.frag.spv"),
+            };
             device.create_shader_module(spirv)?
         };
 
@@ -294,6 +339,7 @@ impl PipelineData {
             fragment_shader_module,
             pipeline_layout,
             pipeline,
+            shading,
             render_pass,
         })
     }
@@ -0,0 +1,212 @@
+// prefab.rs
+// Reusable entity templates.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Prefabs: entities described once and created as many times as needed.
+//!
+//! A [`Prefab`] gives an entity's name, tags, transform, components and children. It can be
+//! based on another prefab, in which case it only needs to say what's different. Each
+//! [`PrefabInstance`] can override any part of its prefab the same way.
+//!
+//! Overrides are merged into what they override: maps are merged key by key, all the way
+//! down, while anything else, including lists, is replaced outright. So overriding just
+//! `transform_data: { rotation: 1.0 }` on a component keeps its translation and scaling,
+//! but overriding `animations` replaces the whole list.
+//!
+//! Prefabs are loaded from `./data/prefabs.yaml`, and maps can list instances of them to
+//! create when the game loads. They can also be created while the game runs with
+//! [`EntityManager::spawn_prefab`].
+//!
+//! [`EntityManager::spawn_prefab`]: ../entity/struct.EntityManager.html#method.spawn_prefab
+//! [`Prefab`]: struct.Prefab.html
+//! [`PrefabInstance`]: struct.PrefabInstance.html
+
+use log::debug;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::{collections::HashMap, fs};
+
+use crate::{error::Error, geometry::TransformData};
+
+/// An entity template, once its base prefabs and any overrides have been merged in.
+#[derive(Deserialize)]
+pub struct Prefab {
+    /// Names must be unique, so a prefab with a name can only be created once. Usually
+    /// it's given in an instance's overrides instead.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The transform of the created entity relative to its parent.
+    #[serde(default)]
+    pub transform: TransformData,
+    /// Component data keyed by the name each component type is registered under, as in
+    /// `./data/components.yaml`. An entity from a prefab has one component of each type
+    /// listed.
+    #[serde(default)]
+    pub components: Mapping,
+    /// Entities to create as children of this one.
+    #[serde(default)]
+    pub children: Vec<PrefabInstance>,
+}
+
+/// A request to create an entity from a prefab.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PrefabInstance {
+    /// The name of the prefab.
+    pub prefab: String,
+    /// Changes to this instance only, laid out like a prefab.
+    #[serde(default)]
+    pub overrides: Value,
+}
+
+impl PrefabInstance {
+    pub fn new(prefab: &str) -> PrefabInstance {
+        PrefabInstance {
+            prefab: prefab.to_string(),
+            overrides: Value::Null,
+        }
+    }
+}
+
+/// All the prefabs, by name, as written. Bases and overrides are merged in when an
+/// instance is resolved.
+#[derive(Default, Deserialize)]
+pub struct Prefabs {
+    prefabs: HashMap<String, Value>,
+}
+
+impl Prefabs {
+    /// Loads prefabs from `./data/prefabs.yaml`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file can't be read, or [`Error::SerdeYaml`] if it's
+    /// malformed.
+    ///
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn load() -> Result<Prefabs, Error> {
+        debug!("Loading prefabs...");
+        Ok(serde_yaml::from_str(&fs::read_to_string(
+            "./data/prefabs.yaml",
+        )?)?)
+    }
+
+    /// The name of every prefab, in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self
+            .prefabs
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<&str>>();
+        names.sort();
+        names
+    }
+
+    /// Merges an instance's prefab, that prefab's bases, and the instance's overrides into
+    /// a single [`Prefab`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Prefab`] if a prefab doesn't exist or is its own base, or
+    /// [`Error::SerdeYaml`] if the merged result isn't a valid [`Prefab`].
+    ///
+    /// [`Error::Prefab`]: ../error/enum.Error.html#variant.Prefab
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    /// [`Prefab`]: struct.Prefab.html
+    pub fn resolve(&self, instance: &PrefabInstance) -> Result<Prefab, Error> {
+        let mut merged = self.merged(&instance.prefab, &mut vec![])?;
+        merge(&mut merged, instance.overrides.clone());
+        Ok(serde_yaml::from_value(merged)?)
+    }
+
+    /// A prefab with all of its bases merged in. `visiting` holds the prefabs already on
+    /// the way here, to catch cycles.
+    fn merged(&self, name: &str, visiting: &mut Vec<String>) -> Result<Value, Error> {
+        if visiting.iter().any(|visited| visited == name) {
+            visiting.push(name.to_string());
+            return Err(Error::Prefab(format!(
+                "prefabs are based on each other in a loop: {}",
+                visiting.join(" -> ")
+            )));
+        }
+        let mut prefab = self
+            .prefabs
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::Prefab(format!("no prefab named {}", name)))?;
+        visiting.push(name.to_string());
+
+        let base = match &mut prefab {
+            Value::Mapping(mapping) => mapping.remove(&Value::String("base".to_string())),
+            _ => None,
+        };
+        match base {
+            Some(Value::String(base)) => {
+                let mut merged = self.merged(&base, visiting)?;
+                merge(&mut merged, prefab);
+                Ok(merged)
+            }
+            Some(_) => Err(Error::Prefab(format!(
+                "the base of prefab {} should be a prefab name",
+                name
+            ))),
+            None => Ok(prefab),
+        }
+    }
+}
+
+/// Merges `overrides` into `base`. Maps are merged key by key; anything else replaces what
+/// was there. A null override changes nothing.
+pub fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (_, Value::Null) => {}
+        (Value::Mapping(base), Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn merge_is_deep_for_maps_only() {
+        let mut base = yaml("{ a: { b: 1, c: [1, 2] }, d: 4 }");
+        merge(&mut base, yaml("{ a: { b: 2, c: [3] }, e: 5 }"));
+        assert_eq!(base, yaml("{ a: { b: 2, c: [3] }, d: 4, e: 5 }"));
+    }
+
+    #[test]
+    fn bases_chain_and_loops_fail() {
+        let prefabs: Prefabs = serde_yaml::from_str(
+            "prefabs:
+               thing: { tags: [sprite], transform: { translation: [1, 2], scaling: [1, 1], rotation: 0 } }
+               spun: { base: thing, transform: { rotation: 3 } }
+               a: { base: b }
+               b: { base: a }",
+        )
+        .unwrap();
+        let mut instance = PrefabInstance::new("spun");
+        instance.overrides = yaml("{ transform: { translation: [5, 6] } }");
+        let prefab = prefabs.resolve(&instance).unwrap();
+        assert_eq!(prefab.transform.translation, vec![5.0, 6.0]);
+        assert_eq!(prefab.transform.rotation, 3.0);
+        assert!(prefabs.resolve(&PrefabInstance::new("a")).is_err());
+        assert!(prefabs.resolve(&PrefabInstance::new("nothing")).is_err());
+    }
+}
@@ -17,22 +17,24 @@ use gfx_hal::{
         SubpassDesc, SubpassRef,
     },
     pool::{CommandPool, CommandPoolCreateFlags},
-    pso::{PipelineStage, Rect},
+    pso::{PipelineStage, Rect, ShaderStageFlags},
     queue::Submission,
     window::{CompositeAlpha, PresentMode, Surface},
-    Backbuffer, Backend as GfxBackend, Device, FrameSync, Graphics, IndexType, Instance,
+    Backbuffer, Backend as GfxBackend, Device, FrameSync, Graphics, IndexType, Instance, Limits,
     PhysicalDevice, QueueGroup, SwapImageIndex, Swapchain, SwapchainConfig,
 };
+use image::RgbaImage;
 use std::ops::Range;
 use winit::{dpi::PhysicalSize, Window};
 
 use crate::{
     error::Error,
     geometry,
-    pipeline::PipelineData,
+    pipeline::{PipelineData, Shading},
     resource::ResourceManager,
+    sdf::SdfUniforms,
     serial::{Color, Index, Position2D, Position3D},
-    texture,
+    texture::{self, Texture},
 };
 
 use self::backend::Backend;
@@ -156,6 +158,15 @@ pub struct FormattedVertexData {
     pub uv: Position2D,
 }
 
+/// A run of indices drawn with the same texture and shading.
+#[derive(Clone, Debug)]
+pub struct DrawBatch {
+    pub texture_index: Index,
+    pub indices: Range<u32>,
+    /// If set, the texture is a signed-distance-field atlas, drawn with these effects.
+    pub distance_field: Option<SdfUniforms>,
+}
+
 /// Receives graphical data and draws it to the screen.
 pub struct Renderer {
     device: backend::Device,
@@ -176,21 +187,31 @@ pub struct Renderer {
     image_memory: <Backend as GfxBackend>::Memory,
 
     pipeline_data: PipelineData,
+    sdf_pipeline_data: PipelineData,
 
     command_pool: CommandPool<Backend, Graphics>,
 
     // Synchronization.
     frame_semaphore: <Backend as GfxBackend>::Semaphore,
     present_semaphore: <Backend as GfxBackend>::Semaphore,
+    /// Signaled when the last frame submitted has finished on the GPU, after which its
+    /// command buffer and the buffers it read from can be reused.
+    frame_fence: <Backend as GfxBackend>::Fence,
+
+    /// Textures whose new data has been staged by `update_texture`, to be copied into
+    /// their images at the start of the next frame.
+    pending_uploads: Vec<Index>,
 
     view_rect: Rect,
+    limits: Limits,
     pub physical_size: PhysicalSize,
     pub color_format: Format,
 }
 
 impl Renderer {
-    // Determines the size of the vertex and index buffers.
-    const MAX_QUADS: u64 = 10;
+    // Determines the size of the vertex and index buffers. Distance-field text takes a quad
+    // per glyph, or two with effects.
+    const MAX_QUADS: u64 = 1024;
 
     /// Creates a new renderer for the given window and sets up a pipeline.
     pub fn new(
@@ -397,6 +418,16 @@ impl Renderer {
                 &caps,
                 &resource_manager.textures,
                 surface_color_format,
+                Shading::Textured,
+            )?
+        };
+        let sdf_pipeline_data = unsafe {
+            PipelineData::new(
+                &device,
+                &caps,
+                &resource_manager.textures,
+                surface_color_format,
+                Shading::DistanceField,
             )?
         };
 
@@ -460,6 +491,8 @@ impl Renderer {
         // Create synchronization primitives.
         let frame_semaphore = device.create_semaphore().unwrap();
         let present_semaphore = device.create_semaphore().unwrap();
+        // Signaled to start with, since there's no frame to wait for yet.
+        let frame_fence = device.create_fence(true)?;
 
         // Finally, return a new Renderer.
         Ok(Renderer {
@@ -468,7 +501,9 @@ impl Renderer {
             device,
             frame_images,
             frame_semaphore,
+            frame_fence,
             framebuffers,
+            pending_uploads: vec![],
             queue_group,
             present_semaphore,
             render_pass,
@@ -479,21 +514,83 @@ impl Renderer {
             buffer_memory,
             image_memory,
             view_rect,
+            limits,
             pipeline_data,
+            sdf_pipeline_data,
             physical_size,
         })
     }
 
+    /// Replaces the image data of a [`Texture`] that was loaded when the `Renderer` was
+    /// created, for textures whose contents change while the game runs.
+    ///
+    /// The data is staged now and copied into the texture's image by the next frame's
+    /// command buffer, before anything is drawn with it. Only call this when the contents
+    /// have actually changed, since staging waits for the last frame to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::TextureSize`] if `data` isn't the same size as the [`Texture`], or
+    /// [`Error::OutOfMemoryOrDeviceLost`] if waiting for the last frame fails.
+    ///
+    /// [`Error::OutOfMemoryOrDeviceLost`]: ../error/enum.Error.html#variant.OutOfMemoryOrDeviceLost
+    /// [`Error::TextureSize`]: ../error/enum.Error.html#variant.TextureSize
+    /// [`Texture`]: ../texture/struct.Texture.html
+    pub fn update_texture(&mut self, texture: &Texture, data: &RgbaImage) -> Result<(), Error> {
+        unsafe {
+            // The last frame may still be copying the texture's previous data out of the
+            // staging buffer.
+            self.device.wait_for_fence(&self.frame_fence, !0)?;
+            texture.stage_data(&self.device, &self.buffer_memory, &self.limits, data)?;
+        }
+        if !self.pending_uploads.contains(&texture.index) {
+            self.pending_uploads.push(texture.index);
+        }
+        Ok(())
+    }
+
     ///  Renders a frame using the data provided by the given `Component`s.
+    ///
+    /// Each of the `batches` is drawn in order with its own texture, switching to the
+    /// distance-field pipeline for batches that have [`SdfUniforms`]. Any textures updated
+    /// since the last frame are copied from the staging buffer into `textures` first.
+    ///
+    /// Only one frame is in flight at a time: this waits for the last one to finish before
+    /// reusing its command buffer and vertex, index and staging buffers.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::OutOfMemoryOrDeviceLost`] or [`Error::OutOfMemory`] if waiting for
+    /// the last frame fails, [`Error::Index`] if an updated texture isn't in `textures`, or
+    /// [`Error::None`] if its image hasn't been created. Nothing is drawn in those cases.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    /// [`Error::None`]: ../error/enum.Error.html#variant.None
+    /// [`Error::OutOfMemory`]: ../error/enum.Error.html#variant.OutOfMemory
+    /// [`Error::OutOfMemoryOrDeviceLost`]: ../error/enum.Error.html#variant.OutOfMemoryOrDeviceLost
+    /// [`SdfUniforms`]: ../sdf/struct.SdfUniforms.html
     pub fn render_frame(
         &mut self,
         vertex_data: Vec<FormattedVertexData>,
-        index_ranges: Vec<Range<u32>>,
-    ) {
+        batches: Vec<DrawBatch>,
+        textures: &[Texture],
+    ) -> Result<(), Error> {
         unsafe {
+            self.device.wait_for_fence(&self.frame_fence, !0)?;
             self.command_pool.reset();
         }
 
+        // Found before the swapchain image is acquired, so nothing after that can fail.
+        let uploads = self
+            .pending_uploads
+            .drain(..)
+            .map(|index| {
+                let texture = textures.get(index).ok_or(Error::Index())?;
+                texture.get_image()?;
+                Ok(texture)
+            })
+            .collect::<Result<Vec<&Texture>, Error>>()?;
+
         let frame_index: SwapImageIndex = unsafe {
             match self
                 .swapchain
@@ -541,6 +638,17 @@ impl Renderer {
                 command_buffer.begin(false);
             }
 
+            // Copies have to happen outside the render pass.
+            for texture in uploads {
+                unsafe {
+                    texture.record_replacement(
+                        &mut command_buffer,
+                        &self.texture_staging_buffer,
+                        &self.limits,
+                    )?;
+                }
+            }
+
             unsafe {
                 command_buffer.bind_vertex_buffers(0, vec![(&self.vertex_buffer.buffer, 0)]);
 
@@ -549,8 +657,6 @@ impl Renderer {
                     offset: 0,
                     index_type: IndexType::U16,
                 });
-
-                command_buffer.bind_graphics_pipeline(&self.pipeline_data.pipeline);
             }
             {
                 // new scope to prevent compiler whining about borrowing lifetimes
@@ -563,21 +669,40 @@ impl Renderer {
                     )
                 };
 
-                for (group, range) in index_ranges.iter().enumerate() {
+                let mut bound: Option<Shading> = None;
+                for batch in batches.iter() {
+                    let pipeline_data = match batch.distance_field {
+                        Some(_) => &self.sdf_pipeline_data,
+                        None => &self.pipeline_data,
+                    };
+
                     unsafe {
+                        // Only switch pipelines when the shading changes.
+                        if bound != Some(pipeline_data.shading) {
+                            encoder.bind_graphics_pipeline(&pipeline_data.pipeline);
+                            bound = Some(pipeline_data.shading);
+                        }
+
                         encoder.bind_graphics_descriptor_sets(
-                            &self.pipeline_data.pipeline_layout,
+                            &pipeline_data.pipeline_layout,
                             0,
                             vec![
-                                &self.pipeline_data.sampler_set,
-                                &self.pipeline_data.texture_sets[group],
+                                &pipeline_data.sampler_set,
+                                &pipeline_data.texture_sets[batch.texture_index],
                             ],
                             &[],
                         );
 
-                        {
-                            encoder.draw_indexed(range.start..range.end, 0, 0..1);
+                        if let Some(uniforms) = &batch.distance_field {
+                            encoder.push_graphics_constants(
+                                &pipeline_data.pipeline_layout,
+                                ShaderStageFlags::FRAGMENT,
+                                0,
+                                uniforms.as_words(),
+                            );
                         }
+
+                        encoder.draw_indexed(batch.indices.clone(), 0, 0..1);
                     }
                 }
             }
@@ -596,7 +721,8 @@ impl Renderer {
         };
 
         unsafe {
-            self.queue_group.queues[0].submit(submission, None);
+            self.device.reset_fence(&self.frame_fence)?;
+            self.queue_group.queues[0].submit(submission, Some(&self.frame_fence));
 
             match self.swapchain.present(
                 &mut self.queue_group.queues[0],
@@ -607,6 +733,7 @@ impl Renderer {
                 Err(()) => (),
             }
         }
+        Ok(())
     }
 
     /// Waits for executing command buffers to idle, then releases
@@ -629,6 +756,7 @@ impl Renderer {
 
             // Destroy created objects.
             self.pipeline_data.destroy(&self.device);
+            self.sdf_pipeline_data.destroy(&self.device);
             self.device.destroy_buffer(self.vertex_buffer.buffer);
             self.device.destroy_buffer(self.index_buffer.buffer);
             self.device
@@ -636,6 +764,7 @@ impl Renderer {
 
             self.device.destroy_semaphore(self.frame_semaphore);
             self.device.destroy_semaphore(self.present_semaphore);
+            self.device.destroy_fence(self.frame_fence);
 
             for framebuffer in self.framebuffers {
                 self.device.destroy_framebuffer(framebuffer);
@@ -5,29 +5,77 @@
 //! Handling of media including fonts, music, and textures.
 
 use gfx_hal::Backend as GfxBackend;
-use log::debug;
+use log::{debug, warn};
 use serde::Deserialize;
 use std::fs;
 
 use self::backend::Backend;
 use gfx_backend_metal as backend;
 
-use crate::{error::Error, text::GameFont, texture::Texture};
+use crate::{
+    error::Error,
+    font::{FontFamily, FontStack, GameFont},
+    texture::Texture,
+};
 
 /// Central repository (but not direct owner) of media resources.
 #[derive(Deserialize)]
 pub struct ResourceManager<'a> {
     pub fonts: Vec<GameFont<'a>>,
+    #[serde(default)]
+    pub font_families: Vec<FontFamily>,
+    /// Every font family with its fallbacks resolved, ready for laying out text.
+    #[serde(skip)]
+    pub font_stacks: Vec<FontStack<'a>>,
     pub textures: Vec<Texture>,
 }
 
 impl<'a> ResourceManager<'a> {
     /// Acquires all resources specified in `./data/resources.yaml`.
+    ///
+    /// Fonts are loaded here; textures are loaded when the renderer initializes them.
+    ///
+    /// A font that can't be loaded is logged and skipped, along with every font family
+    /// that needs it, so missing fonts only affect the text that uses them.
     pub fn load_resources() -> Result<ResourceManager<'a>, Error> {
         debug!("Loading resources...");
-        Ok(serde_yaml::from_str(&fs::read_to_string(
-            "./data/resources.yaml",
-        )?)?)
+        let mut resources: ResourceManager =
+            serde_yaml::from_str(&fs::read_to_string("./data/resources.yaml")?)?;
+
+        for font in &mut resources.fonts {
+            if let Err(err) = font.initialize() {
+                warn!("Couldn't load font {}: {}", font.file, err);
+            }
+        }
+        let (families, fonts) = (&resources.font_families, &resources.fonts);
+        resources.font_stacks = families
+            .iter()
+            .filter_map(
+                |family| match FontStack::new(&family.name, families, fonts) {
+                    Ok(stack) => Some(stack),
+                    Err(err) => {
+                        warn!("Skipping font family {}: {}", family.name, err);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        Ok(resources)
+    }
+
+    /// Returns the named font family, along with its fallbacks.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Index`] if there's no family with that name.
+    ///
+    /// [`Error::Index`]: ../error/enum.Error.html#variant.Index
+    pub fn font_stack(&self, family: &str) -> Result<&FontStack<'a>, Error> {
+        self.font_stacks
+            .iter()
+            .find(|stack| stack.name == family)
+            .ok_or(Error::Index())
     }
 
     /// Releases all resources held by this object.
@@ -0,0 +1,213 @@
+// save.rs
+// Saving and loading the state of the game world.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Saved games.
+//!
+//! A [`SaveGame`] holds everything about the world that changes while the game is played:
+//! every entity and component, the [`Camera`], the [`GameClock`], and which map the player
+//! is on. Art, fonts, and the rest of the data that's the same every time the game starts
+//! aren't included.
+//!
+//! Entities and components keep their handles, so anything holding a [`Handle`] into the
+//! world still refers to the same thing after a save is loaded. Animations keep time by the
+//! saved clock, so they pick up from the same frame.
+//!
+//! See [`DataManager::save_game`] and [`DataManager::load_game`].
+//!
+//! [`Camera`]: ../geometry/struct.Camera.html
+//! [`DataManager::load_game`]: ../data/struct.DataManager.html#method.load_game
+//! [`DataManager::save_game`]: ../data/struct.DataManager.html#method.save_game
+//! [`GameClock`]: ../time/struct.GameClock.html
+//! [`Handle`]: ../arena/struct.Handle.html
+//! [`SaveGame`]: struct.SaveGame.html
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::{
+    arena::ArenaSnapshot,
+    component::SavedComponent,
+    entity::Entity,
+    error::Error,
+    geometry::Camera,
+    serial::Index,
+    time::GameClock,
+};
+
+/// Where the quick-save slot is kept.
+pub const QUICKSAVE_PATH: &str = "./saves/quicksave.yaml";
+
+/// A snapshot of the game world.
+#[derive(Deserialize, Serialize)]
+pub struct SaveGame {
+    /// The index of the map the player is on.
+    pub current_map: Index,
+    pub camera: Camera,
+    pub clock: GameClock,
+    pub entities: ArenaSnapshot<Entity>,
+    pub components: ArenaSnapshot<SavedComponent>,
+}
+
+impl SaveGame {
+    /// Reads a saved game from a YAML file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file can't be read, or [`Error::SerdeYaml`] if it's
+    /// malformed.
+    ///
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<SaveGame, Error> {
+        debug!("Loading saved game from {}...", path.as_ref().display());
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the saved game to a YAML file, creating its directory if needed and
+    /// replacing anything already there.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file can't be written, or [`Error::SerdeYaml`] if
+    /// something in the snapshot can't be serialized.
+    ///
+    /// [`Error::Io`]: ../error/enum.Error.html#variant.Io
+    /// [`Error::SerdeYaml`]: ../error/enum.Error.html#variant.SerdeYaml
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        debug!("Saving game to {}...", path.as_ref().display());
+        if let Some(directory) = path.as_ref().parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        animation::AnimationController,
+        component::{Animation2D, ComponentManager},
+        entity::EntityManager,
+        serial::Color,
+        tween::Tweener,
+    };
+    use std::time::Duration;
+
+    const COMPONENTS: &str = "
+components:
+  - component_data:
+      Animation2D:
+        texture_index: 0
+        spritesheet_index: 0
+        layer: 0
+        current_animation: 0
+        animations:
+          - { frames: [4, 5, 6], animation_type: Loop, frame_length: 100 }
+          - { frames: [7], animation_type: Once, frame_length: 100 }
+        transform_data: { translation: [1, 2], scaling: [3, 4], rotation: 0.5 }
+        movement: { delta_rotation: 0, delta_scale: [0, 0], delta_translate: [0, 0] }
+        tint: { r: 1, g: 0.5, b: 0.25, a: 0.5 }
+        flip_x: true
+        origin: { x: 0.5, y: 1 }
+  - component_data:
+      Tweener:
+        name: pulse
+        tween: { To: { property: { Opacity: 0.4 }, duration: 800, easing: QuadInOut } }
+        yoyo: true
+        since: 300
+  - component_data:
+      AnimationController:
+        states: { walk: { animation: 0 }, fall: { animation: 1 } }
+        state: walk
+";
+
+    #[test]
+    fn worlds_come_back_as_they_were_saved() {
+        let mut components = ComponentManager::new();
+        let handles = components.load(COMPONENTS).unwrap();
+        let mut entities = EntityManager::new();
+        // A reused slot, so handles with later generations have to survive too.
+        let gone = entities.create_entity();
+        entities.destroy_entity(gone, &mut components).unwrap();
+        let hero = entities.create_entity();
+        entities.set_name(hero, Some("hero")).unwrap();
+        for handle in &handles {
+            components
+                .add_entity_component(hero, &mut entities, *handle)
+                .unwrap();
+        }
+
+        let mut clock = GameClock::new();
+        clock.advance(Duration::from_millis(1000));
+        let now = clock.millis();
+        let frame = {
+            let mut animations = components.storage_mut::<Animation2D>().unwrap();
+            let animation = animations.get_mut(handles[0]).unwrap();
+            animation.playback.start(200);
+            animation.playback.pause(now);
+            animation.queue(1);
+            animation.current_frame(now)
+        };
+        components
+            .storage_mut::<AnimationController>()
+            .unwrap()
+            .get_mut(handles[2])
+            .unwrap()
+            .set_parameter("speed", 2.0);
+
+        let save = SaveGame {
+            current_map: 0,
+            camera: Camera::default(),
+            clock,
+            entities: entities.snapshot(),
+            components: components.snapshot().unwrap(),
+        };
+        let save: SaveGame = serde_yaml::from_str(&serde_yaml::to_string(&save).unwrap()).unwrap();
+
+        let restored = ComponentManager::new().restored(save.components).unwrap();
+        let mut entities = EntityManager::new();
+        entities.restore(save.entities).unwrap();
+        let now = save.clock.millis();
+        assert_eq!(now, 1000);
+        assert_eq!(entities.find_by_name("hero"), Some(hero));
+        assert_eq!(entities.get_entity(hero).unwrap().components, handles);
+        for handle in &handles {
+            assert_eq!(restored.get_component(*handle).unwrap().entity, Some(hero));
+        }
+
+        let animations = restored.storage::<Animation2D>().unwrap();
+        let animation = animations.get(handles[0]).unwrap();
+        assert!(animation.playback.is_paused());
+        assert_eq!(animation.playback.elapsed(now), 800);
+        assert_eq!(animation.current_frame(now), frame);
+        assert_eq!(frame, Some(6));
+        assert_eq!(animation.queued, vec![1]);
+        assert_eq!(
+            animation.tint,
+            Color {
+                r: 1.0,
+                g: 0.5,
+                b: 0.25,
+                a: 0.5
+            }
+        );
+        assert!(animation.placement.flip_x);
+        assert_eq!(animation.placement.origin.map(|origin| origin.y), Some(1.0));
+        assert_eq!(animation.transform_data.translation, vec![1.0, 2.0]);
+
+        let tweeners = restored.storage::<Tweener>().unwrap();
+        let tweener = tweeners.get(handles[1]).unwrap();
+        assert_eq!(
+            (tweener.name.as_deref(), tweener.since, tweener.yoyo),
+            (Some("pulse"), Some(300), true)
+        );
+        let controllers = restored.storage::<AnimationController>().unwrap();
+        let controller = controllers.get(handles[2]).unwrap();
+        assert_eq!(controller.state(), "walk");
+        assert_eq!(controller.parameter("speed"), 2.0);
+    }
+}
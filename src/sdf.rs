@@ -0,0 +1,533 @@
+// sdf.rs
+// Signed-distance-field glyphs and the effects they make possible.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Text drawn from signed distance fields.
+//!
+//! Instead of storing how much of each texel a glyph covers, a distance field stores how
+//! far each texel is from the glyph's outline: positive inside, negative outside. Edges
+//! stay sharp when the field is scaled or rotated, and effects that depend on distance
+//! from the outline, like outlines, drop shadows, and glows, come almost for free.
+//!
+//! Fields are generated from the glyph outlines `rusttype` provides and packed into an
+//! [`SdfAtlas`]. The atlas is uploaded to a texture and each glyph is drawn from it as a
+//! quad by the `sdf.frag` shader, which applies [`SdfEffects`] using [`SdfUniforms`], so
+//! text stays sharp however it's scaled or rotated. Where there's no texture to upload the
+//! atlas to, [`text::render_rich_text`] can draw from it on the CPU instead, with the
+//! effects baked in at the size the text is shown.
+//!
+//! [`SdfAtlas`]: struct.SdfAtlas.html
+//! [`SdfEffects`]: struct.SdfEffects.html
+//! [`SdfUniforms`]: struct.SdfUniforms.html
+//! [`text::render_rich_text`]: ../text/fn.render_rich_text.html
+
+use image::{GenericImage, Rgba, RgbaImage};
+use rusttype::{GlyphId, Point, ScaledGlyph, Segment};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::{
+    font::FaceGlyph,
+    geometry::Rect,
+    serial::{Color, Position2D, Size},
+    text::TextLayout,
+};
+
+/// The width of the atlas image in texels. It grows downward as glyphs are added.
+const ATLAS_WIDTH: u32 = 512;
+/// How many straight lines each curve in an outline is split into.
+const CURVE_STEPS: usize = 8;
+/// Half the width of the blurred band at a glyph's edge, in pixels.
+const EDGE_SMOOTHING: f32 = 0.6;
+
+/// A line around the outside of text.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Outline {
+    /// In pixels.
+    pub width: f32,
+    pub color: Color,
+}
+
+/// A copy of text drawn underneath it, offset and possibly blurred.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Shadow {
+    /// How far the shadow is moved from the text, in pixels. Positive y is down.
+    pub offset: Position2D,
+    /// How far the shadow's edge is blurred, in pixels.
+    #[serde(default)]
+    pub softness: f32,
+    pub color: Color,
+}
+
+/// Light that fades out from the edges of text.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Glow {
+    /// How far the glow reaches, in pixels.
+    pub radius: f32,
+    pub color: Color,
+}
+
+/// Effects applied to distance-field text. Any combination can be used.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct SdfEffects {
+    #[serde(default)]
+    pub outline: Option<Outline>,
+    #[serde(default)]
+    pub shadow: Option<Shadow>,
+    #[serde(default)]
+    pub glow: Option<Glow>,
+}
+
+impl SdfEffects {
+    /// Whether there are no effects, so only the text itself is drawn.
+    pub fn is_empty(&self) -> bool {
+        self.outline.is_none() && self.shadow.is_none() && self.glow.is_none()
+    }
+
+    /// How far outside the glyphs' outlines the effects reach, in pixels.
+    pub fn extent(&self) -> f32 {
+        let outline = self.outline.map_or(0.0, |outline| outline.width);
+        let shadow = self.shadow.map_or(0.0, |shadow| {
+            shadow.offset.x.abs().max(shadow.offset.y.abs()) + shadow.softness
+        });
+        let glow = self.glow.map_or(0.0, |glow| glow.radius);
+        outline.max(shadow).max(glow) + EDGE_SMOOTHING
+    }
+
+    /// Colors a pixel `distance` pixels inside the text (negative if outside), where the
+    /// pixel the shadow's offset away is `shadow_distance` inside. Returns an
+    /// unpremultiplied color.
+    ///
+    /// This does the same as `sdf.frag`.
+    pub fn shade(&self, fill: Color, distance: f32, shadow_distance: f32) -> Color {
+        let coverage = |distance: f32, smoothing: f32| {
+            (distance / (2.0 * smoothing) + 0.5).clamp(0.0, 1.0)
+        };
+
+        // Layers are blended from the bottom up: glow, shadow, outline, fill.
+        let mut result = [0.0; 4];
+        if let Some(glow) = self.glow {
+            let falloff = (1.0 + distance / glow.radius).clamp(0.0, 1.0);
+            blend_over(&mut result, glow.color, falloff * falloff);
+        }
+        if let Some(shadow) = self.shadow {
+            let smoothing = EDGE_SMOOTHING.max(shadow.softness);
+            blend_over(&mut result, shadow.color, coverage(shadow_distance, smoothing));
+        }
+        if let Some(outline) = self.outline {
+            blend_over(
+                &mut result,
+                outline.color,
+                coverage(distance + outline.width, EDGE_SMOOTHING),
+            );
+        }
+        blend_over(&mut result, fill, coverage(distance, EDGE_SMOOTHING));
+
+        if result[3] <= 0.0 {
+            return Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 0.0,
+            };
+        }
+        Color {
+            r: result[0] / result[3],
+            g: result[1] / result[3],
+            b: result[2] / result[3],
+            a: result[3],
+        }
+    }
+}
+
+/// Blends a color over a premultiplied color with the given coverage.
+fn blend_over(under: &mut [f32; 4], color: Color, coverage: f32) {
+    let alpha = color.a * coverage;
+    under[0] = color.r * alpha + under[0] * (1.0 - alpha);
+    under[1] = color.g * alpha + under[1] * (1.0 - alpha);
+    under[2] = color.b * alpha + under[2] * (1.0 - alpha);
+    under[3] = alpha + under[3] * (1.0 - alpha);
+}
+
+/// The push constants `sdf.frag` reads its effects from. The text's fill color comes from
+/// the vertex color.
+///
+/// Distances are measured in atlas texels, so they have to be divided by however much
+/// the atlas is scaled up when drawn.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SdfUniforms {
+    pub outline_color: [f32; 4],
+    pub shadow_color: [f32; 4],
+    pub glow_color: [f32; 4],
+    /// In texture coordinates.
+    pub shadow_offset: [f32; 2],
+    pub outline_width: f32,
+    pub shadow_softness: f32,
+    pub glow_radius: f32,
+    /// The distance stored in an atlas texel with alpha 1; alpha 0 stores the negation.
+    pub spread: f32,
+    /// The number of screen pixels per atlas texel.
+    pub scale: f32,
+}
+
+impl SdfUniforms {
+    /// Converts effects measured in screen pixels for an atlas drawn `scale` times its size
+    /// from a texture `texture_size` texels across. Effects that aren't used are given
+    /// transparent colors.
+    pub fn new(
+        effects: &SdfEffects,
+        atlas: &SdfAtlas,
+        texture_size: Size,
+        scale: f32,
+    ) -> SdfUniforms {
+        let color = |color: Color| [color.r, color.g, color.b, color.a];
+        let mut uniforms = SdfUniforms {
+            spread: atlas.spread,
+            scale,
+            ..SdfUniforms::default()
+        };
+        if let Some(outline) = effects.outline {
+            uniforms.outline_color = color(outline.color);
+            uniforms.outline_width = outline.width / scale;
+        }
+        if let Some(shadow) = effects.shadow {
+            uniforms.shadow_color = color(shadow.color);
+            uniforms.shadow_offset = [
+                shadow.offset.x / scale / texture_size.x,
+                shadow.offset.y / scale / texture_size.y,
+            ];
+            uniforms.shadow_softness = shadow.softness / scale;
+        }
+        if let Some(glow) = effects.glow {
+            uniforms.glow_color = color(glow.color);
+            uniforms.glow_radius = glow.radius / scale;
+        }
+        uniforms
+    }
+
+    /// The uniforms as the 32-bit words `gfx_hal` pushes to the shader.
+    pub fn as_words(&self) -> &[u32] {
+        // SAFETY: `SdfUniforms` is `repr(C)` and made only of `f32`s, so it has no padding
+        // and every field is exactly one word.
+        unsafe {
+            std::slice::from_raw_parts(
+                self as *const SdfUniforms as *const u32,
+                std::mem::size_of::<SdfUniforms>() / 4,
+            )
+        }
+    }
+}
+
+/// Where a glyph's distance field is in an [`SdfAtlas`].
+///
+/// [`SdfAtlas`]: struct.SdfAtlas.html
+#[derive(Clone, Copy, Debug)]
+pub struct SdfGlyph {
+    /// The texels holding the field.
+    pub rect: Rect,
+    /// The position of the field's top-left corner relative to the glyph's origin on the
+    /// baseline, in pixels.
+    pub offset: Position2D,
+}
+
+/// Distance fields for a set of glyphs, packed into one image.
+///
+/// Every texel is white, with the distance in the alpha channel: 0.5 on the outline,
+/// rising to 1.0 at `spread` pixels inside and falling to 0.0 at `spread` pixels outside.
+pub struct SdfAtlas {
+    pub image: RgbaImage,
+    /// The greatest distance from an outline that the fields record, in pixels.
+    pub spread: f32,
+    glyphs: HashMap<(usize, GlyphId), SdfGlyph>,
+}
+
+impl SdfAtlas {
+    /// Generates fields for all the outline glyphs in the layouts, at the size they were
+    /// laid out at. Glyphs from bitmap fonts are skipped.
+    pub fn from_layouts<'l, 'a: 'l, I>(layouts: I, spread: f32) -> SdfAtlas
+    where
+        I: IntoIterator<Item = &'l TextLayout<'a>>,
+    {
+        let mut fields: Vec<((usize, GlyphId), DistanceField)> = vec![];
+        for layout in layouts {
+            for styled in &layout.glyphs {
+                if let FaceGlyph::Outline(glyph) = &styled.glyph {
+                    let key = (styled.face, glyph.id());
+                    if fields.iter().any(|(existing, _)| *existing == key) {
+                        continue;
+                    }
+                    if let Some(field) = DistanceField::new(glyph.unpositioned(), spread) {
+                        fields.push((key, field));
+                    }
+                }
+            }
+        }
+        SdfAtlas::pack(fields, spread)
+    }
+
+    /// Arranges the fields in rows, tallest first.
+    fn pack(mut fields: Vec<((usize, GlyphId), DistanceField)>, spread: f32) -> SdfAtlas {
+        fields.sort_by_key(|(_, field)| std::cmp::Reverse(field.height));
+
+        let mut glyphs = HashMap::new();
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        let mut positions = vec![];
+        for (key, field) in &fields {
+            if x + field.width > ATLAS_WIDTH {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+            positions.push((x, y));
+            glyphs.insert(
+                *key,
+                SdfGlyph {
+                    rect: Rect::new(
+                        x as f32,
+                        y as f32,
+                        field.width as f32,
+                        field.height as f32,
+                    ),
+                    offset: field.offset,
+                },
+            );
+            x += field.width;
+            row_height = row_height.max(field.height);
+        }
+
+        let mut image = RgbaImage::from_pixel(
+            ATLAS_WIDTH,
+            (y + row_height).max(1),
+            Rgba {
+                data: [255, 255, 255, 0],
+            },
+        );
+        for ((_, field), (left, top)) in fields.iter().zip(positions) {
+            for row in 0..field.height {
+                for column in 0..field.width {
+                    let distance = field.distances[(row * field.width + column) as usize];
+                    let alpha = (distance / spread * 0.5 + 0.5).clamp(0.0, 1.0);
+                    image.get_pixel_mut(left + column, top + row).data[3] =
+                        (alpha * 255.0).round() as u8;
+                }
+            }
+        }
+
+        SdfAtlas {
+            image,
+            spread,
+            glyphs,
+        }
+    }
+
+    /// The atlas image in the top-left corner of an otherwise empty image of the given
+    /// size, ready to be uploaded to a texture that size. Returns `None` if it doesn't fit.
+    pub fn padded_image(&self, width: u32, height: u32) -> Option<RgbaImage> {
+        if self.image.width() > width || self.image.height() > height {
+            return None;
+        }
+        let mut padded = RgbaImage::from_pixel(
+            width,
+            height,
+            Rgba {
+                data: [255, 255, 255, 0],
+            },
+        );
+        padded.copy_from(&self.image, 0, 0);
+        Some(padded)
+    }
+
+    /// Finds a glyph's field. `face` identifies the font as in [`FaceChoice`].
+    ///
+    /// [`FaceChoice`]: ../font/struct.FaceChoice.html
+    pub fn get(&self, face: usize, id: GlyphId) -> Option<&SdfGlyph> {
+        self.glyphs.get(&(face, id))
+    }
+
+    /// The distance stored at a point within a glyph's field, interpolated between texels.
+    /// Points off the field are as far away as the field records.
+    pub fn distance(&self, glyph: &SdfGlyph, x: f32, y: f32) -> f32 {
+        let texel = |column: i32, row: i32| -> f32 {
+            if column < 0
+                || row < 0
+                || column as f32 >= glyph.rect.size.x
+                || row as f32 >= glyph.rect.size.y
+            {
+                return -self.spread;
+            }
+            let alpha = self
+                .image
+                .get_pixel(
+                    glyph.rect.position.x as u32 + column as u32,
+                    glyph.rect.position.y as u32 + row as u32,
+                )
+                .data[3];
+            (f32::from(alpha) / 255.0 - 0.5) * 2.0 * self.spread
+        };
+
+        // Texel centers are at half-texel positions.
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (column, row) = (x.floor() as i32, y.floor() as i32);
+        let (dx, dy) = (x - x.floor(), y - y.floor());
+        let top = texel(column, row) * (1.0 - dx) + texel(column + 1, row) * dx;
+        let bottom = texel(column, row + 1) * (1.0 - dx) + texel(column + 1, row + 1) * dx;
+        top * (1.0 - dy) + bottom * dy
+    }
+}
+
+/// The signed distance from a single glyph's outline to each pixel around it.
+struct DistanceField {
+    width: u32,
+    height: u32,
+    /// The position of the top-left pixel relative to the glyph's origin.
+    offset: Position2D,
+    /// Rows of distances in pixels, positive inside the glyph.
+    distances: Vec<f32>,
+}
+
+impl DistanceField {
+    /// Measures the field out to `spread` pixels beyond the glyph's outline. Returns `None`
+    /// for glyphs with no outline, like spaces.
+    fn new(glyph: &ScaledGlyph, spread: f32) -> Option<DistanceField> {
+        let bounds = glyph.exact_bounding_box()?;
+        // Outlines are y-up; flip them to match the y-down bounding box and images.
+        let edges = flatten(&glyph.shape()?);
+        if edges.is_empty() {
+            return None;
+        }
+
+        let left = (bounds.min.x - spread).floor();
+        let top = (bounds.min.y - spread).floor();
+        let width = ((bounds.max.x + spread).ceil() - left) as u32;
+        let height = ((bounds.max.y + spread).ceil() - top) as u32;
+
+        let mut distances = Vec::with_capacity((width * height) as usize);
+        for row in 0..height {
+            for column in 0..width {
+                let point = rusttype::point(left + column as f32 + 0.5, top + row as f32 + 0.5);
+                let mut nearest = f32::INFINITY;
+                let mut winding = 0;
+                for edge in &edges {
+                    nearest = nearest.min(distance_to_line(point, edge));
+                    winding += crossing(point, edge);
+                }
+                let distance = nearest.min(spread);
+                distances.push(if winding != 0 { distance } else { -distance });
+            }
+        }
+
+        Some(DistanceField {
+            width,
+            height,
+            offset: Position2D { x: left, y: top },
+            distances,
+        })
+    }
+}
+
+/// Breaks an outline into straight edges, flipped to be y-down.
+fn flatten(contours: &[rusttype::Contour]) -> Vec<[Point<f32>; 2]> {
+    let flip = |point: Point<f32>| rusttype::point(point.x, -point.y);
+    let mut edges = vec![];
+    for contour in contours {
+        for segment in &contour.segments {
+            match segment {
+                Segment::Line(line) => edges.push([flip(line.p[0]), flip(line.p[1])]),
+                Segment::Curve(curve) => {
+                    let [start, control, end] = curve.p;
+                    let mut previous = flip(start);
+                    for step in 1..=CURVE_STEPS {
+                        let t = step as f32 / CURVE_STEPS as f32;
+                        let u = 1.0 - t;
+                        let next = rusttype::point(
+                            u * u * start.x + 2.0 * u * t * control.x + t * t * end.x,
+                            u * u * start.y + 2.0 * u * t * control.y + t * t * end.y,
+                        );
+                        edges.push([previous, flip(next)]);
+                        previous = flip(next);
+                    }
+                }
+            }
+        }
+    }
+    edges
+}
+
+fn distance_to_line(point: Point<f32>, line: &[Point<f32>; 2]) -> f32 {
+    let [start, end] = *line;
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length = dx * dx + dy * dy;
+    let t = if length > 0.0 {
+        (((point.x - start.x) * dx + (point.y - start.y) * dy) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (x, y) = (start.x + t * dx - point.x, start.y + t * dy - point.y);
+    (x * x + y * y).sqrt()
+}
+
+/// How a ray from the point toward +x crosses the edge: +1 or -1 depending on the
+/// edge's direction, or 0 if it doesn't. Summed over an outline, this is nonzero
+/// exactly when the point is inside.
+fn crossing(point: Point<f32>, line: &[Point<f32>; 2]) -> i32 {
+    let [start, end] = *line;
+    let side = (end.x - start.x) * (point.y - start.y) - (point.x - start.x) * (end.y - start.y);
+    if start.y <= point.y {
+        if end.y > point.y && side > 0.0 {
+            return 1;
+        }
+    } else if end.y <= point.y && side < 0.0 {
+        return -1;
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color {
+        r: 1.0,
+        g: 1.0,
+        b: 1.0,
+        a: 1.0,
+    };
+
+    #[test]
+    fn winding() {
+        // A clockwise square from (0, 0) to (2, 2).
+        let square = [
+            [rusttype::point(0.0, 0.0), rusttype::point(2.0, 0.0)],
+            [rusttype::point(2.0, 0.0), rusttype::point(2.0, 2.0)],
+            [rusttype::point(2.0, 2.0), rusttype::point(0.0, 2.0)],
+            [rusttype::point(0.0, 2.0), rusttype::point(0.0, 0.0)],
+        ];
+        let winding = |x, y| {
+            square
+                .iter()
+                .map(|edge| crossing(rusttype::point(x, y), edge))
+                .sum::<i32>()
+        };
+        assert_ne!(winding(1.0, 1.0), 0);
+        assert_eq!(winding(3.0, 1.0), 0);
+        assert_eq!(winding(-1.0, 1.0), 0);
+        assert_eq!(distance_to_line(rusttype::point(1.0, 3.0), &square[2]), 1.0);
+    }
+
+    #[test]
+    fn effects() {
+        let effects = SdfEffects {
+            outline: Some(Outline {
+                width: 2.0,
+                color: Color { r: 0.0, ..WHITE },
+            }),
+            ..SdfEffects::default()
+        };
+        assert_eq!(effects.shade(WHITE, 3.0, 0.0).r, 1.0);
+        let outline = effects.shade(WHITE, -1.0, 0.0);
+        assert_eq!((outline.r, outline.a), (0.0, 1.0));
+        assert_eq!(effects.shade(WHITE, -4.0, 0.0).a, 0.0);
+        assert_eq!(effects.extent(), 2.0 + EDGE_SMOOTHING);
+    }
+}
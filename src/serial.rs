@@ -39,7 +39,7 @@ impl From<Vec3> for Position3D {
 }
 
 /// A unified representation of a 2D size.
-#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
 pub struct Size {
     pub x: f32,
     pub y: f32,
@@ -64,7 +64,7 @@ impl From<Vec2> for Size {
 pub type Position2D = Size;
 
 /// A unified representation of rgba color.
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -72,6 +72,18 @@ pub struct Color {
     pub a: f32,
 }
 
+impl Default for Color {
+    /// Opaque white, which leaves a texture as it is when it's used as a tint.
+    fn default() -> Self {
+        Color {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+        }
+    }
+}
+
 impl Into<Vec4> for Color {
     /// (Hopefully) automatic conversion to simplify use with `glm` functions.
     fn into(self) -> Vec4 {
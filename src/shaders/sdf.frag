@@ -0,0 +1,69 @@
+#version 450
+
+// Draws text from a signed-distance-field atlas, with the effects described by
+// `SdfEffects`. Keep this in step with `SdfEffects::shade` in sdf.rs.
+
+layout (location = 0) in vec4 rgba;
+layout (location = 1) in vec2 texture_coordinates;
+
+layout (location = 0) out vec4 color;
+
+layout (set = 1, binding = 0) uniform sampler texture_sampler;
+layout (set = 1, binding = 1) uniform texture2D texture_data;
+
+// Matches `SdfUniforms`. Distances are in atlas texels.
+layout (push_constant) uniform Effects {
+    vec4 outline_color;
+    vec4 shadow_color;
+    vec4 glow_color;
+    vec2 shadow_offset;
+    float outline_width;
+    float shadow_softness;
+    float glow_radius;
+    float spread;
+    float scale;
+} effects;
+
+// Half the width of the blurred band at a glyph's edge, in screen pixels.
+const float EDGE_SMOOTHING = 0.6;
+
+float distance_at(vec2 uv) {
+    float alpha = texture(sampler2D(texture_data, texture_sampler), uv).a;
+    return (alpha - 0.5) * 2.0 * effects.spread;
+}
+
+float coverage(float distance, float smoothing) {
+    return clamp(distance / (2.0 * smoothing) + 0.5, 0.0, 1.0);
+}
+
+// Blends a color over a premultiplied color with the given coverage.
+vec4 blend_over(vec4 under, vec4 over, float coverage) {
+    float alpha = over.a * coverage;
+    return vec4(over.rgb * alpha, alpha) + under * (1.0 - alpha);
+}
+
+void main() {
+    float smoothing = EDGE_SMOOTHING / effects.scale;
+    float distance = distance_at(texture_coordinates);
+    float shadow_distance = distance_at(texture_coordinates - effects.shadow_offset);
+
+    // Unused effects have transparent colors, so they blend away to nothing.
+    vec4 result = vec4(0.0);
+    if (effects.glow_radius > 0.0) {
+        float falloff = clamp(1.0 + distance / effects.glow_radius, 0.0, 1.0);
+        result = blend_over(result, effects.glow_color, falloff * falloff);
+    }
+    result = blend_over(
+        result,
+        effects.shadow_color,
+        coverage(shadow_distance, max(smoothing, effects.shadow_softness))
+    );
+    result = blend_over(
+        result,
+        effects.outline_color,
+        coverage(distance + effects.outline_width, smoothing)
+    );
+    result = blend_over(result, rgba, coverage(distance, smoothing));
+
+    color = result.a > 0.0 ? vec4(result.rgb / result.a, result.a) : vec4(0.0);
+}
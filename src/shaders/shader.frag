@@ -9,5 +9,5 @@ layout (set = 1, binding = 0) uniform sampler texture_sampler;
 layout (set = 1, binding = 1) uniform texture2D texture_data;
 
 void main() {
-    color = texture(sampler2D(texture_data, texture_sampler), texture_coordinates);
+    color = texture(sampler2D(texture_data, texture_sampler), texture_coordinates) * rgba;
 }
\ No newline at end of file
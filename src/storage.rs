@@ -0,0 +1,311 @@
+// storage.rs
+// Per-type component storage and queries across it.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Typed storage for [`Component`]s.
+//!
+//! Every type of component lives in its own [`Storage`], a sparse set keyed by the
+//! component's [`Handle`]. Values are packed together so systems that only care about one
+//! type can walk straight through them without looking at anything else.
+//!
+//! The [`ComponentManager`] keeps each storage behind a lock so a [`Query`] can hold
+//! several at once, reading some types and writing others.
+//!
+//! [`Component`]: ../component/struct.Component.html
+//! [`ComponentManager`]: ../component/struct.ComponentManager.html
+//! [`Handle`]: ../arena/struct.Handle.html
+//! [`Query`]: trait.Query.html
+//! [`Storage`]: struct.Storage.html
+
+use std::{
+    any::{Any, TypeId},
+    marker::PhantomData,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::{
+    arena::Handle,
+    component::ComponentManager,
+    error::Error,
+    serial::Index,
+};
+
+/// All the components of one type.
+#[derive(Debug)]
+pub struct Storage<T> {
+    /// Positions in `values` for each component handle's slot.
+    sparse: Vec<Option<Index>>,
+    handles: Vec<Handle>,
+    values: Vec<T>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage {
+            sparse: Vec::new(),
+            handles: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T> Storage<T> {
+    /// Stores the value for a component, replacing any value it already had.
+    pub fn insert(&mut self, handle: Handle, value: T) {
+        if self.sparse.len() <= handle.index {
+            self.sparse.resize(handle.index + 1, None);
+        }
+        match self.sparse[handle.index] {
+            Some(position) => {
+                self.handles[position] = handle;
+                self.values[position] = value;
+            }
+            None => {
+                self.sparse[handle.index] = Some(self.values.len());
+                self.handles.push(handle);
+                self.values.push(value);
+            }
+        }
+    }
+
+    /// Takes a component's value out of the storage, moving the last value into its place.
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let position = self.position(handle)?;
+        self.sparse[handle.index] = None;
+        self.handles.swap_remove(position);
+        let value = self.values.swap_remove(position);
+        if let Some(moved) = self.handles.get(position) {
+            self.sparse[moved.index] = Some(position);
+        }
+        Some(value)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.position(handle).map(|position| &self.values[position])
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.position(handle)
+            .map(move |position| &mut self.values[position])
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The handles of every component in the storage.
+    pub fn handles(&self) -> &[Handle] {
+        &self.handles
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> {
+        self.handles.iter().cloned().zip(self.values.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &mut T)> {
+        self.handles.iter().cloned().zip(self.values.iter_mut())
+    }
+
+    /// Where the component's value is in `values`, if it's here and the handle isn't stale.
+    fn position(&self, handle: Handle) -> Option<Index> {
+        match self.sparse.get(handle.index) {
+            Some(Some(position)) if self.handles[*position] == handle => Some(*position),
+            _ => None,
+        }
+    }
+}
+
+/// A [`Storage`] with its type erased, so storages of every type can be kept together.
+///
+/// [`Storage`]: struct.Storage.html
+pub trait AnyStorage: Any + Send + Sync {
+    /// Drops the component's value, if it's here.
+    fn remove(&mut self, handle: Handle);
+    /// The handles of every component in the storage.
+    fn handles(&self) -> Vec<Handle>;
+    /// A new, empty storage for the same type.
+    fn empty(&self) -> Box<dyn AnyStorage>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Any + Send + Sync> AnyStorage for RwLock<Storage<T>> {
+    fn remove(&mut self, handle: Handle) {
+        // A lock poisoned by a panicking system still holds perfectly good data.
+        match self.get_mut() {
+            Ok(storage) => storage.remove(handle),
+            Err(poisoned) => poisoned.into_inner().remove(handle),
+        };
+    }
+
+    fn handles(&self) -> Vec<Handle> {
+        match self.read() {
+            Ok(storage) => storage.handles().to_vec(),
+            Err(poisoned) => poisoned.into_inner().handles().to_vec(),
+        }
+    }
+
+    fn empty(&self) -> Box<dyn AnyStorage> {
+        Box::new(RwLock::new(Storage::<T>::default()))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Asks a [`Query`] for shared access to components of type `T`.
+///
+/// [`Query`]: trait.Query.html
+pub struct Read<T>(PhantomData<T>);
+
+/// Asks a [`Query`] for mutable access to components of type `T`.
+///
+/// [`Query`]: trait.Query.html
+pub struct Write<T>(PhantomData<T>);
+
+/// A set of component types to look for on each entity, along with the kind of access
+/// needed to each. Implemented for [`Read`], [`Write`], and tuples of up to four of them.
+///
+/// See [`ComponentManager::query`].
+///
+/// [`ComponentManager::query`]: ../component/struct.ComponentManager.html#method.query
+/// [`Read`]: struct.Read.html
+/// [`Write`]: struct.Write.html
+pub trait Query {
+    /// The storage locks held while the query runs.
+    type Locks<'m>;
+    /// What's handed out for each matching entity.
+    type Item<'l>;
+
+    /// The component types an entity must have to match, in order.
+    fn types() -> Vec<TypeId>;
+
+    /// Locks the storages the query needs.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Borrow`] if a storage is already locked in a conflicting way, for
+    /// instance if the same type is asked for twice with [`Write`].
+    ///
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Write`]: struct.Write.html
+    fn lock(manager: &ComponentManager) -> Result<Self::Locks<'_>, Error>;
+
+    /// Takes one component handle per type from `components` and fetches their values.
+    fn fetch<'l>(
+        locks: &'l mut Self::Locks<'_>,
+        components: &mut dyn Iterator<Item = Handle>,
+    ) -> Option<Self::Item<'l>>;
+}
+
+impl<T: Any + Send + Sync> Query for Read<T> {
+    type Locks<'m> = RwLockReadGuard<'m, Storage<T>>;
+    type Item<'l> = &'l T;
+
+    fn types() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
+    }
+
+    fn lock(manager: &ComponentManager) -> Result<Self::Locks<'_>, Error> {
+        manager.storage::<T>()
+    }
+
+    fn fetch<'l>(
+        locks: &'l mut Self::Locks<'_>,
+        components: &mut dyn Iterator<Item = Handle>,
+    ) -> Option<Self::Item<'l>> {
+        locks.get(components.next()?)
+    }
+}
+
+impl<T: Any + Send + Sync> Query for Write<T> {
+    type Locks<'m> = RwLockWriteGuard<'m, Storage<T>>;
+    type Item<'l> = &'l mut T;
+
+    fn types() -> Vec<TypeId> {
+        vec![TypeId::of::<T>()]
+    }
+
+    fn lock(manager: &ComponentManager) -> Result<Self::Locks<'_>, Error> {
+        manager.storage_mut::<T>()
+    }
+
+    fn fetch<'l>(
+        locks: &'l mut Self::Locks<'_>,
+        components: &mut dyn Iterator<Item = Handle>,
+    ) -> Option<Self::Item<'l>> {
+        locks.get_mut(components.next()?)
+    }
+}
+
+macro_rules! tuple_query {
+    ($($query:ident),+) => {
+        impl<$($query: Query),+> Query for ($($query,)+) {
+            type Locks<'m> = ($($query::Locks<'m>,)+);
+            type Item<'l> = ($($query::Item<'l>,)+);
+
+            fn types() -> Vec<TypeId> {
+                let mut types = vec![];
+                $(types.extend($query::types());)+
+                types
+            }
+
+            fn lock(manager: &ComponentManager) -> Result<Self::Locks<'_>, Error> {
+                Ok(($($query::lock(manager)?,)+))
+            }
+
+            #[allow(non_snake_case)]
+            fn fetch<'l>(
+                locks: &'l mut Self::Locks<'_>,
+                components: &mut dyn Iterator<Item = Handle>,
+            ) -> Option<Self::Item<'l>> {
+                let ($($query,)+) = locks;
+                Some(($($query::fetch($query, components)?,)+))
+            }
+        }
+    };
+}
+
+tuple_query!(A);
+tuple_query!(A, B);
+tuple_query!(A, B, C);
+tuple_query!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_set_removal() {
+        let handle = |index| Handle {
+            index,
+            generation: 0,
+        };
+        let mut storage = Storage::default();
+        storage.insert(handle(4), "four");
+        storage.insert(handle(1), "one");
+        storage.insert(handle(7), "seven");
+
+        // Removing moves the last value into the gap; lookups have to follow it.
+        assert_eq!(storage.remove(handle(4)), Some("four"));
+        assert_eq!(storage.get(handle(4)), None);
+        assert_eq!(storage.get(handle(7)), Some(&"seven"));
+        assert_eq!(storage.get(handle(1)), Some(&"one"));
+        assert_eq!(storage.len(), 2);
+
+        // Stale handles to a reused slot miss.
+        storage.insert(
+            Handle {
+                index: 1,
+                generation: 1,
+            },
+            "new one",
+        );
+        assert_eq!(storage.get(handle(1)), None);
+        assert_eq!(storage.len(), 2);
+    }
+}
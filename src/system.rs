@@ -0,0 +1,847 @@
+// system.rs
+// Systems, the parts of the game that act on components, and scheduling them.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! The "system" part of the entity-component-system model.
+//!
+//! A [`System`] is a piece of game logic that runs every frame, like moving sprites in
+//! response to input. Each one says which [`Stage`] of the frame it belongs in and which
+//! component types and [`Resources`] it reads or writes. The [`Scheduler`] runs the stages
+//! in order, and can run the systems within a stage in parallel as long as their access
+//! doesn't conflict.
+//!
+//! Systems only ever see a [`World`], which hands out component storages and resources
+//! behind locks, so they can't step on each other even when they run at the same time.
+//! They tell each other what's happened by sending [`Events`], which are resources too.
+//!
+//! [`Events`]: ../event/struct.Events.html
+//! [`Resources`]: struct.Resources.html
+//! [`Scheduler`]: struct.Scheduler.html
+//! [`Stage`]: enum.Stage.html
+//! [`System`]: trait.System.html
+//! [`World`]: struct.World.html
+
+use log::{error, warn};
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    panic::{self, AssertUnwindSafe},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+use winit::VirtualKeyCode;
+
+use crate::{
+    animation::{AnimationController, AnimationType, TransitionClock},
+    arena::Handle,
+    component::{Animation2D, ComponentManager, Quad, ReceiveInput},
+    entity::EntityManager,
+    error::Error,
+    event::{AnimationEvent, AnimationFinished, Events, Subscription, TweenFinished},
+    serial::Index,
+    storage::Write,
+    time::GameClock,
+    tween::Tweener,
+};
+
+/// The parts of a frame, run in this order.
+///
+/// The game is updated in fixed-length steps, and there can be any number of them in a
+/// frame, including none. `Input`, `Update` and `LateUpdate` run once per step; `Render`
+/// runs once per frame.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Stage {
+    /// Turning input into changes to the game.
+    Input,
+    /// Game logic.
+    Update,
+    /// Logic that needs to see the results of `Update`, like a camera following a sprite.
+    LateUpdate,
+    /// Preparing things to be drawn.
+    Render,
+}
+
+impl Stage {
+    /// Every stage, in the order they run.
+    pub const ALL: [Stage; 4] = [Stage::Input, Stage::Update, Stage::LateUpdate, Stage::Render];
+
+    /// The stages that run once per update step, in order.
+    pub const UPDATE: [Stage; 3] = [Stage::Input, Stage::Update, Stage::LateUpdate];
+}
+
+/// Something a [`System`] reads or writes: a component type or a resource type.
+///
+/// [`System`]: trait.System.html
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Access {
+    Read(TypeId),
+    Write(TypeId),
+}
+
+impl Access {
+    pub fn read<T: Any>() -> Access {
+        Access::Read(TypeId::of::<T>())
+    }
+
+    pub fn write<T: Any>() -> Access {
+        Access::Write(TypeId::of::<T>())
+    }
+
+    fn type_id(self) -> TypeId {
+        match self {
+            Access::Read(type_id) | Access::Write(type_id) => type_id,
+        }
+    }
+
+    /// Two systems conflict if either writes something the other uses.
+    fn conflicts_with(self, other: Access) -> bool {
+        match (self, other) {
+            (Access::Read(_), Access::Read(_)) => false,
+            _ => self.type_id() == other.type_id(),
+        }
+    }
+}
+
+/// Moves one type of event queue on to the next frame.
+type UpdateEvents = fn(&Resources) -> Result<(), Error>;
+
+/// Data that isn't attached to any entity, like the keys pressed this frame. There's at
+/// most one of each type.
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    /// Moves each event queue added with `add_events` on to the next frame.
+    event_updates: Vec<UpdateEvents>,
+}
+
+impl Resources {
+    pub fn new() -> Resources {
+        Resources::default()
+    }
+
+    /// Adds a resource, replacing any resource of the same type.
+    pub fn insert<T: Any + Send + Sync>(&mut self, resource: T) {
+        self.resources
+            .insert(TypeId::of::<T>(), Box::new(RwLock::new(resource)));
+    }
+
+    /// Adds an empty [`Events`] queue for events of type `T`, which [`update_events`]
+    /// keeps up to date. Adding the same type again empties its queue.
+    ///
+    /// [`Events`]: ../event/struct.Events.html
+    /// [`update_events`]: #method.update_events
+    pub fn add_events<T: Any + Send + Sync>(&mut self) {
+        if !self.resources.contains_key(&TypeId::of::<Events<T>>()) {
+            self.event_updates.push(|resources| {
+                resources.get_mut::<Events<T>>()?.update();
+                Ok(())
+            });
+        }
+        self.insert(Events::<T>::new());
+    }
+
+    /// Starts a new frame for every event queue added with [`add_events`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Resource`] if a queue has been removed.
+    ///
+    /// [`add_events`]: #method.add_events
+    /// [`Error::Resource`]: ../error/enum.Error.html#variant.Resource
+    pub fn update_events(&mut self) -> Result<(), Error> {
+        for update in &self.event_updates {
+            update(self)?;
+        }
+        Ok(())
+    }
+
+    /// Sends an event through its [`Events`] queue.
+    ///
+    /// # Errors
+    ///
+    /// See [`get_mut`](#method.get_mut).
+    ///
+    /// [`Events`]: ../event/struct.Events.html
+    pub fn send<T: Any + Send + Sync>(&self, event: T) -> Result<(), Error> {
+        self.get_mut::<Events<T>>()?.send(event);
+        Ok(())
+    }
+
+    /// Locks a resource for reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Resource`] if there's no resource of type `T`, or
+    /// [`Error::Borrow`] if it's locked for writing.
+    ///
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Error::Resource`]: ../error/enum.Error.html#variant.Resource
+    pub fn get<T: Any + Send + Sync>(&self) -> Result<RwLockReadGuard<'_, T>, Error> {
+        self.lock::<T>()?
+            .try_read()
+            .map_err(|_| Error::Borrow(std::any::type_name::<T>()))
+    }
+
+    /// Locks a resource for writing.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Resource`] if there's no resource of type `T`, or
+    /// [`Error::Borrow`] if it's already locked.
+    ///
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    /// [`Error::Resource`]: ../error/enum.Error.html#variant.Resource
+    pub fn get_mut<T: Any + Send + Sync>(&self) -> Result<RwLockWriteGuard<'_, T>, Error> {
+        self.lock::<T>()?
+            .try_write()
+            .map_err(|_| Error::Borrow(std::any::type_name::<T>()))
+    }
+
+    fn lock<T: Any + Send + Sync>(&self) -> Result<&RwLock<T>, Error> {
+        self.resources
+            .get(&TypeId::of::<T>())
+            .and_then(|resource| resource.downcast_ref::<RwLock<T>>())
+            .ok_or_else(|| Error::Resource(std::any::type_name::<T>()))
+    }
+}
+
+/// Everything a [`System`] can see.
+///
+/// [`System`]: trait.System.html
+#[derive(Clone, Copy)]
+pub struct World<'w> {
+    pub components: &'w ComponentManager,
+    pub entities: &'w EntityManager,
+    pub resources: &'w Resources,
+}
+
+/// A piece of game logic that runs once per frame.
+pub trait System: Send {
+    /// Used in log messages.
+    fn name(&self) -> &str;
+
+    /// When in the frame the system runs.
+    fn stage(&self) -> Stage {
+        Stage::Update
+    }
+
+    /// The component and resource types the system reads and writes. Systems that run in
+    /// parallel must declare everything they lock, or the lock will fail with
+    /// [`Error::Borrow`].
+    ///
+    /// [`Error::Borrow`]: ../error/enum.Error.html#variant.Borrow
+    fn access(&self) -> Vec<Access> {
+        vec![]
+    }
+
+    fn run(&mut self, world: World<'_>) -> Result<(), Error>;
+}
+
+/// Runs [`System`]s stage by stage.
+///
+/// Within a stage, systems run in the order they were added. With parallelism turned on,
+/// consecutive systems whose access doesn't conflict run at the same time, each on its own
+/// thread; a system that conflicts with any of them waits until they've all finished.
+///
+/// [`System`]: trait.System.html
+#[derive(Default)]
+pub struct Scheduler {
+    systems: HashMap<Stage, Vec<Box<dyn System>>>,
+    parallel: bool,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Whether systems in the same stage may run in parallel. Off by default.
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    /// Adds a system to the end of its stage.
+    pub fn add<S: System + 'static>(&mut self, system: S) {
+        self.systems
+            .entry(system.stage())
+            .or_default()
+            .push(Box::new(system));
+    }
+
+    /// Runs every stage once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Systems`] with the errors of every system that failed. The rest of
+    /// the systems still run.
+    ///
+    /// [`Error::Systems`]: ../error/enum.Error.html#variant.Systems
+    pub fn run(&mut self, world: World<'_>) -> Result<(), Error> {
+        self.run_stages(&Stage::ALL, world)
+    }
+
+    /// Runs every stage but `Render` once, for one update step.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Systems`] with the errors of every system that failed. The rest of
+    /// the systems still run.
+    ///
+    /// [`Error::Systems`]: ../error/enum.Error.html#variant.Systems
+    pub fn run_update(&mut self, world: World<'_>) -> Result<(), Error> {
+        self.run_stages(&Stage::UPDATE, world)
+    }
+
+    fn run_stages(&mut self, stages: &[Stage], world: World<'_>) -> Result<(), Error> {
+        let mut errors = vec![];
+        for stage in stages {
+            if let Err(Error::Systems(failed)) = self.run_stage(*stage, world) {
+                errors.extend(failed);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Systems(errors))
+        }
+    }
+
+    /// Runs the systems in one stage.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Systems`] with the errors of every system that failed, in the
+    /// order the systems were added. A failing system doesn't stop the rest of the stage.
+    ///
+    /// # Panics
+    ///
+    /// If a system panics, which system it was and the panic's message are logged and the
+    /// panic carries on up through this. In parallel, that's once the systems running
+    /// alongside it have finished.
+    ///
+    /// [`Error::Systems`]: ../error/enum.Error.html#variant.Systems
+    pub fn run_stage(&mut self, stage: Stage, world: World<'_>) -> Result<(), Error> {
+        let systems = match self.systems.get_mut(&stage) {
+            Some(systems) => systems,
+            None => return Ok(()),
+        };
+
+        let mut errors = vec![];
+        if !self.parallel {
+            for system in systems.iter_mut() {
+                errors.extend(run_system(system, world).err());
+            }
+        } else {
+            for batch in batches(systems) {
+                if batch.len() == 1 {
+                    errors.extend(run_system(batch.into_iter().next().unwrap(), world).err());
+                    continue;
+                }
+                let results = std::thread::scope(|scope| {
+                    batch
+                        .into_iter()
+                        .map(|system| scope.spawn(move || run_system(system, world)))
+                        .collect::<Vec<_>>()
+                        .into_iter()
+                        .map(|thread| {
+                            // The panic's already been logged by `run_system`.
+                            thread
+                                .join()
+                                .unwrap_or_else(|payload| panic::resume_unwind(payload))
+                        })
+                        .collect::<Vec<Result<(), Error>>>()
+                });
+                errors.extend(results.into_iter().filter_map(Result::err));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Systems(errors))
+        }
+    }
+}
+
+/// Adds every gameplay system to the scheduler. Systems in the same stage run in the order
+/// they're added here, so new ones go wherever they need to fall in that order.
+pub fn register(scheduler: &mut Scheduler) {
+    scheduler.add(MovementSystem::new());
+    scheduler.add(AnimationSystem::new());
+    scheduler.add(AnimationControllerSystem::new());
+    scheduler.add(TweenSystem::new());
+}
+
+/// Runs a system, logging which one it was if it fails or panics.
+fn run_system(system: &mut Box<dyn System>, world: World<'_>) -> Result<(), Error> {
+    match panic::catch_unwind(AssertUnwindSafe(|| system.run(world))) {
+        Ok(result) => result.map_err(|err| {
+            warn!("The {} system failed: {}", system.name(), err);
+            err
+        }),
+        Err(payload) => {
+            error!(
+                "The {} system panicked: {}",
+                system.name(),
+                panic_message(payload.as_ref())
+            );
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+/// The message a panic was started with, if it's text.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(no message)")
+}
+
+/// Splits systems, in order, into groups that can run at the same time.
+fn batches(systems: &mut [Box<dyn System>]) -> Vec<Vec<&mut Box<dyn System>>> {
+    let mut batches: Vec<Vec<&mut Box<dyn System>>> = vec![];
+    let mut batch_access: Vec<Access> = vec![];
+    for system in systems.iter_mut() {
+        let access = system.access();
+        let conflicts = access
+            .iter()
+            .any(|mine| batch_access.iter().any(|theirs| mine.conflicts_with(*theirs)));
+        match batches.last_mut() {
+            Some(batch) if !conflicts => batch.push(system),
+            _ => {
+                batch_access.clear();
+                batches.push(vec![system]);
+            }
+        }
+        batch_access.extend(access);
+    }
+    batches
+}
+
+/// The keys pressed since the last frame, as a resource.
+#[derive(Debug, Default)]
+pub struct Input {
+    /// Keys that were pressed, in order. Held keys repeat.
+    pub pressed: Vec<VirtualKeyCode>,
+    /// Keys that are down right now.
+    pub held: HashSet<VirtualKeyCode>,
+}
+
+impl Input {
+    /// Forgets last frame's presses. Held keys stay held.
+    pub fn begin_frame(&mut self) {
+        self.pressed.clear();
+    }
+
+    pub fn press(&mut self, keycode: VirtualKeyCode) {
+        self.pressed.push(keycode);
+        self.held.insert(keycode);
+    }
+
+    pub fn release(&mut self, keycode: VirtualKeyCode) {
+        self.held.remove(&keycode);
+    }
+}
+
+/// Moves, turns and scales [`Animation2D`]s by their `movement` for as long as the arrow
+/// keys are held, going by the [`GameClock`], so nothing moves while it's paused.
+///
+/// [`Animation2D`]: ../component/struct.Animation2D.html
+/// [`GameClock`]: ../time/struct.GameClock.html
+#[derive(Default)]
+pub struct MovementSystem;
+
+impl MovementSystem {
+    pub fn new() -> MovementSystem {
+        MovementSystem
+    }
+}
+
+impl System for MovementSystem {
+    fn name(&self) -> &str {
+        "movement"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Input
+    }
+
+    fn access(&self) -> Vec<Access> {
+        vec![
+            Access::read::<Input>(),
+            Access::read::<GameClock>(),
+            Access::write::<Animation2D>(),
+        ]
+    }
+
+    fn run(&mut self, world: World<'_>) -> Result<(), Error> {
+        let input = world.resources.get::<Input>()?;
+        let seconds = world.resources.get::<GameClock>()?.delta().as_secs_f32();
+        let mut animations = world.components.storage_mut::<Animation2D>()?;
+        for keycode in &input.held {
+            for (_, animation) in animations.iter_mut() {
+                animation.keyboard_response(*keycode, seconds)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends an [`AnimationEvent`] for each event on a frame an [`Animation2D`] reaches, and
+/// [`AnimationFinished`] when its current animation stops. Then starts the next queued
+/// animation, if the current one is done.
+///
+/// [`Animation2D`]: ../component/struct.Animation2D.html
+/// [`AnimationEvent`]: ../event/struct.AnimationEvent.html
+/// [`AnimationFinished`]: ../event/struct.AnimationFinished.html
+#[derive(Default)]
+pub struct AnimationSystem;
+
+impl AnimationSystem {
+    pub fn new() -> AnimationSystem {
+        AnimationSystem
+    }
+}
+
+impl System for AnimationSystem {
+    fn name(&self) -> &str {
+        "animation"
+    }
+
+    fn access(&self) -> Vec<Access> {
+        vec![
+            Access::read::<GameClock>(),
+            Access::write::<Animation2D>(),
+            Access::write::<Events<AnimationEvent>>(),
+            Access::write::<Events<AnimationFinished>>(),
+        ]
+    }
+
+    fn run(&mut self, world: World<'_>) -> Result<(), Error> {
+        let now = world.resources.get::<GameClock>()?.millis();
+        let mut frame_events = vec![];
+        let mut finished = vec![];
+        world
+            .components
+            .query::<Write<Animation2D>, _>(world.entities, |entity, animation| {
+                animation.playback.start(now);
+                let index = animation.current_animation;
+                let elapsed = animation.elapsed(now);
+                let previous = animation.events_until.saturating_sub(1);
+                if let Some(current) = animation.animations.get(index) {
+                    // The frame showing now has started, so it counts.
+                    for frame in current.frames_started(animation.events_until, elapsed + 1) {
+                        for name in &current.frames[frame].events {
+                            frame_events.push(AnimationEvent {
+                                entity,
+                                animation: index,
+                                frame,
+                                name: name.clone(),
+                            });
+                        }
+                    }
+                }
+                animation.events_until = elapsed + 1;
+
+                if !animation.finished && animation.is_finished(now) {
+                    animation.finished = true;
+                    finished.push(AnimationFinished {
+                        entity,
+                        animation: index,
+                    });
+                }
+
+                let done = match animation.animations.get(index) {
+                    Some(current) if current.animation_type == AnimationType::Once => {
+                        animation.finished
+                    }
+                    // Repeating animations are done when they get back to the start.
+                    Some(current) => {
+                        let duration = current.duration().max(1);
+                        elapsed / duration > previous / duration
+                    }
+                    None => true,
+                };
+                if done {
+                    animation.play_queued();
+                }
+            })?;
+
+        let mut events = world.resources.get_mut::<Events<AnimationEvent>>()?;
+        for event in frame_events {
+            events.send(event);
+        }
+        let mut events = world.resources.get_mut::<Events<AnimationFinished>>()?;
+        for event in finished {
+            events.send(event);
+        }
+        Ok(())
+    }
+}
+
+/// Moves each [`AnimationController`] between states and has its entity's [`Animation2D`]
+/// play the animation for the state it's in.
+///
+/// Runs after the [`AnimationSystem`], so transitions see the events it sends that frame.
+///
+/// [`Animation2D`]: ../component/struct.Animation2D.html
+/// [`AnimationController`]: ../animation/struct.AnimationController.html
+/// [`AnimationSystem`]: struct.AnimationSystem.html
+#[derive(Default)]
+pub struct AnimationControllerSystem {
+    frame_events: Subscription<AnimationEvent>,
+    finished: Subscription<AnimationFinished>,
+}
+
+impl AnimationControllerSystem {
+    pub fn new() -> AnimationControllerSystem {
+        AnimationControllerSystem::default()
+    }
+}
+
+impl System for AnimationControllerSystem {
+    fn name(&self) -> &str {
+        "animation controller"
+    }
+
+    fn access(&self) -> Vec<Access> {
+        vec![
+            Access::read::<Events<AnimationEvent>>(),
+            Access::read::<Events<AnimationFinished>>(),
+            Access::write::<AnimationController>(),
+            Access::write::<Animation2D>(),
+        ]
+    }
+
+    fn run(&mut self, world: World<'_>) -> Result<(), Error> {
+        // What happened to each entity's animations, by animation, since the last run.
+        let mut frame_events: HashMap<(Handle, Index), Vec<String>> = HashMap::new();
+        for event in world
+            .resources
+            .get::<Events<AnimationEvent>>()?
+            .read(&mut self.frame_events)
+        {
+            frame_events
+                .entry((event.entity, event.animation))
+                .or_default()
+                .push(event.name.clone());
+        }
+        let finished = world
+            .resources
+            .get::<Events<AnimationFinished>>()?
+            .read(&mut self.finished)
+            .map(|event| (event.entity, event.animation))
+            .collect::<HashSet<(Handle, Index)>>();
+
+        world.components.query::<(Write<AnimationController>, Write<Animation2D>), _>(
+            world.entities,
+            |entity, (controller, animation)| {
+                // Only events from the animation the current state plays count.
+                let key = (entity, animation.current_animation);
+                let events = frame_events.get(&key).map_or(&[][..], Vec::as_slice);
+                match controller.update(events, finished.contains(&key)) {
+                    Some((next, TransitionClock::Restart)) => animation.play(next),
+                    Some((next, TransitionClock::Continue)) => animation.switch(next),
+                    None => match controller.animation() {
+                        // Catch up with a state set in the data.
+                        Some(current) if current != animation.current_animation => {
+                            animation.play(current)
+                        }
+                        _ => {}
+                    },
+                }
+            },
+        )
+    }
+}
+
+/// Plays each [`Tweener`] on its entity's [`Animation2D`], or its [`Quad`] if it doesn't
+/// have one, and sends [`TweenFinished`] when a tween is done.
+///
+/// [`Animation2D`]: ../component/struct.Animation2D.html
+/// [`Quad`]: ../component/struct.Quad.html
+/// [`TweenFinished`]: ../event/struct.TweenFinished.html
+/// [`Tweener`]: ../tween/struct.Tweener.html
+#[derive(Default)]
+pub struct TweenSystem;
+
+impl TweenSystem {
+    pub fn new() -> TweenSystem {
+        TweenSystem
+    }
+}
+
+impl System for TweenSystem {
+    fn name(&self) -> &str {
+        "tween"
+    }
+
+    fn access(&self) -> Vec<Access> {
+        vec![
+            Access::read::<GameClock>(),
+            Access::write::<Tweener>(),
+            Access::write::<Animation2D>(),
+            Access::write::<Quad>(),
+            Access::write::<Events<TweenFinished>>(),
+        ]
+    }
+
+    fn run(&mut self, world: World<'_>) -> Result<(), Error> {
+        let now = world.resources.get::<GameClock>()?.millis();
+        let mut tweened = HashSet::new();
+        let mut finished = vec![];
+        world.components.query::<(Write<Tweener>, Write<Animation2D>), _>(
+            world.entities,
+            |entity, (tweener, animation)| {
+                tweened.insert(entity);
+                if tweener.update(now, animation) {
+                    finished.push(TweenFinished {
+                        entity,
+                        name: tweener.name.clone(),
+                    });
+                }
+            },
+        )?;
+        world.components.query::<(Write<Tweener>, Write<Quad>), _>(
+            world.entities,
+            |entity, (tweener, quad)| {
+                if !tweened.contains(&entity) && tweener.update(now, quad) {
+                    finished.push(TweenFinished {
+                        entity,
+                        name: tweener.name.clone(),
+                    });
+                }
+            },
+        )?;
+
+        let mut events = world.resources.get_mut::<Events<TweenFinished>>()?;
+        for event in finished {
+            events.send(event);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Uses(Vec<Access>);
+
+    impl System for Uses {
+        fn name(&self) -> &str {
+            "uses"
+        }
+
+        fn access(&self) -> Vec<Access> {
+            self.0.clone()
+        }
+
+        fn run(&mut self, _world: World<'_>) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    struct Panics;
+
+    impl System for Panics {
+        fn name(&self) -> &str {
+            "panics"
+        }
+
+        fn run(&mut self, _world: World<'_>) -> Result<(), Error> {
+            panic!("out of cheese")
+        }
+    }
+
+    #[test]
+    fn panics_in_parallel_systems_carry_on() {
+        let components = ComponentManager::new();
+        let entities = EntityManager::new();
+        let resources = Resources::new();
+        let world = World {
+            components: &components,
+            entities: &entities,
+            resources: &resources,
+        };
+        let mut scheduler = Scheduler::new();
+        scheduler.set_parallel(true);
+        scheduler.add(Uses(vec![Access::read::<u8>()]));
+        scheduler.add(Panics);
+
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            scheduler.run_stage(Stage::Update, world)
+        }))
+        .unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "out of cheese");
+    }
+
+    struct Fails;
+
+    impl System for Fails {
+        fn name(&self) -> &str {
+            "fails"
+        }
+
+        fn run(&mut self, _world: World<'_>) -> Result<(), Error> {
+            Err(Error::None())
+        }
+    }
+
+    /// Counts how many times it's run.
+    struct Counts(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl System for Counts {
+        fn name(&self) -> &str {
+            "counts"
+        }
+
+        fn run(&mut self, _world: World<'_>) -> Result<(), Error> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failures_dont_stop_the_other_systems() {
+        let components = ComponentManager::new();
+        let entities = EntityManager::new();
+        let resources = Resources::new();
+        let world = World {
+            components: &components,
+            entities: &entities,
+            resources: &resources,
+        };
+        let runs = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        for parallel in [false, true].iter() {
+            let mut scheduler = Scheduler::new();
+            scheduler.set_parallel(*parallel);
+            scheduler.add(Fails);
+            scheduler.add(Counts(runs.clone()));
+            scheduler.add(Fails);
+            match scheduler.run(world) {
+                Err(Error::Systems(errors)) => assert_eq!(errors.len(), 2),
+                other => panic!("expected both failures, got {:?}", other),
+            }
+        }
+        assert_eq!(runs.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn batches_split_on_conflicts() {
+        let mut systems: Vec<Box<dyn System>> = vec![
+            Box::new(Uses(vec![Access::read::<u8>()])),
+            Box::new(Uses(vec![Access::read::<u8>(), Access::write::<u16>()])),
+            Box::new(Uses(vec![Access::write::<u8>()])),
+            Box::new(Uses(vec![Access::write::<u32>()])),
+            Box::new(Uses(vec![Access::read::<u16>()])),
+        ];
+        let sizes = batches(&mut systems)
+            .iter()
+            .map(|batch| batch.len())
+            .collect::<Vec<usize>>();
+        assert_eq!(sizes, vec![2, 3]);
+    }
+}
@@ -5,12 +5,32 @@
 use image::{Rgba, RgbaImage};
 use rusttype::{Font, PositionedGlyph, Scale};
 use serde::Deserialize;
+use std::ops::Range;
 
 use crate::{
+    draw::Spritesheet,
     error::Error,
-    serial::{Filename, Index},
+    bitmap_font::{BitmapChar, BitmapFont},
+    font::{FaceGlyph, FontStack, FontStyle},
+    geometry::Rect,
+    markup::{RichText, TextEffect, TextRun, TextStyle},
+    sdf::{SdfAtlas, SdfEffects},
+    serial::{Color, Index, Position2D, Size},
 };
 
+/// How far `[wave]` text rises and falls, as a fraction of the line height.
+const WAVE_AMPLITUDE: f32 = 0.12;
+/// How long one rise and fall of `[wave]` text takes, in milliseconds.
+const WAVE_PERIOD: f32 = 1200.0;
+/// The difference in phase between neighboring glyphs in `[wave]` text, in radians.
+const WAVE_PHASE_STEP: f32 = 0.5;
+/// How far `[shake]` text strays from its position, as a fraction of the line height.
+const SHAKE_AMPLITUDE: f32 = 0.05;
+/// How often `[shake]` text jumps to a new position, in milliseconds.
+const SHAKE_INTERVAL: u64 = 50;
+/// How far faux italic text leans, in pixels across per pixel up.
+const ITALIC_SLANT: f32 = 0.2;
+
 // There are a few ways of getting text done.
 //
 // 1) Generate a static texture for each font. Then, drawing text is basically the
@@ -97,10 +117,833 @@ pub fn render_text(
     Ok(texture)
 }
 
-#[derive(Deserialize)]
-pub struct GameFont<'a> {
-    pub index: Index,
-    pub file: Filename,
-    #[serde(skip)]
-    pub data: Option<Font<'a>>,
+/// A glyph placed by [`layout_rich_text`] along with the style to draw it in.
+///
+/// [`layout_rich_text`]: fn.layout_rich_text.html
+#[derive(Clone)]
+pub struct StyledGlyph<'a> {
+    pub glyph: FaceGlyph<'a>,
+    pub style: TextStyle,
+    /// Which font in the [`FontStack`] the glyph is from, as in [`FaceChoice`].
+    ///
+    /// [`FaceChoice`]: ../font/struct.FaceChoice.html
+    /// [`FontStack`]: ../font/struct.FontStack.html
+    pub face: usize,
+    /// The part of the style the glyph's font doesn't have a face for, which is imitated
+    /// when the glyph is drawn.
+    pub synthesized: FontStyle,
+    /// The glyph's place in the text, counting both characters and icons.
+    pub index: usize,
+    /// The line the glyph is on, starting from 0.
+    pub line: usize,
+}
+
+/// An inline icon placed by [`layout_rich_text`].
+///
+/// [`layout_rich_text`]: fn.layout_rich_text.html
+#[derive(Clone)]
+pub struct StyledIcon {
+    /// The frame of the icon [`Spritesheet`] to draw.
+    ///
+    /// [`Spritesheet`]: ../draw/struct.Spritesheet.html
+    pub frame: Index,
+    pub style: TextStyle,
+    /// The position of the icon's top-left corner in pixels.
+    pub position: Position2D,
+    /// The icon's width and height in pixels.
+    pub size: f32,
+    /// The icon's place in the text, counting both characters and icons.
+    pub index: usize,
+    /// The line the icon is on, starting from 0.
+    pub line: usize,
+}
+
+/// Rich text that has been broken into lines and positioned glyph by glyph.
+pub struct TextLayout<'a> {
+    pub glyphs: Vec<StyledGlyph<'a>>,
+    pub icons: Vec<StyledIcon>,
+    /// Pauses in the text as (number of characters and icons before the pause, milliseconds).
+    pub pauses: Vec<(usize, u32)>,
+    /// The distance between the baselines of consecutive lines in pixels.
+    pub line_height: f32,
+    /// The number of lines the text was broken into.
+    pub lines: usize,
+    /// The size of the laid-out text in pixels.
+    pub size: Size,
+    /// The total number of characters and icons in the text.
+    pub length: usize,
+}
+
+impl<'a> TextLayout<'a> {
+    /// Whether any of the text moves by itself, like `[wave]` text, so that rendering it at
+    /// different times gives different pictures.
+    pub fn is_animated(&self) -> bool {
+        self.glyphs
+            .iter()
+            .map(|glyph| glyph.style.effect)
+            .chain(self.icons.iter().map(|icon| icon.style.effect))
+            .any(|effect| effect != TextEffect::None)
+    }
+
+    /// Returns the part of the layout that falls on the given lines, moved up so that the
+    /// first of those lines is at the top. Indices are counted from the start of the part.
+    pub fn page(&self, lines: Range<usize>) -> TextLayout<'a> {
+        let first = self
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph.line, glyph.index))
+            .chain(self.icons.iter().map(|icon| (icon.line, icon.index)))
+            .filter(|(line, _)| *line >= lines.start)
+            .map(|(_, index)| index)
+            .min()
+            .unwrap_or(self.length);
+        let end = self
+            .glyphs
+            .iter()
+            .map(|glyph| (glyph.line, glyph.index))
+            .chain(self.icons.iter().map(|icon| (icon.line, icon.index)))
+            .filter(|(line, _)| *line >= lines.end)
+            .map(|(_, index)| index)
+            .min()
+            .unwrap_or(self.length);
+        let shift = lines.start as f32 * self.line_height;
+
+        TextLayout {
+            glyphs: self
+                .glyphs
+                .iter()
+                .filter(|glyph| lines.contains(&glyph.line))
+                .map(|glyph| StyledGlyph {
+                    glyph: glyph.glyph.translated(0.0, -shift),
+                    index: glyph.index - first,
+                    line: glyph.line - lines.start,
+                    ..glyph.clone()
+                })
+                .collect(),
+            icons: self
+                .icons
+                .iter()
+                .filter(|icon| lines.contains(&icon.line))
+                .map(|icon| StyledIcon {
+                    position: Position2D {
+                        x: icon.position.x,
+                        y: icon.position.y - shift,
+                    },
+                    index: icon.index - first,
+                    line: icon.line - lines.start,
+                    ..icon.clone()
+                })
+                .collect(),
+            pauses: self
+                .pauses
+                .iter()
+                .filter(|(index, _)| *index >= first && *index < end)
+                .map(|(index, millis)| (index - first, *millis))
+                .collect(),
+            line_height: self.line_height,
+            lines: lines.len(),
+            size: Size {
+                x: self.size.x,
+                y: self.line_height * lines.len() as f32,
+            },
+            length: end - first,
+        }
+    }
+}
+
+/// An image containing the icons that can be placed inline with `[icon]` tags.
+pub struct IconSheet<'a> {
+    pub image: &'a RgbaImage,
+    pub spritesheet: &'a Spritesheet,
+}
+
+/// Positions every glyph and icon of the given text, with glyphs `pixel_height` pixels tall.
+///
+/// Each character is drawn with the first font in the stack that has it. Line spacing
+/// comes from the primary font.
+///
+/// Lines are broken at `\n` characters and, if `max_width` is given, wrapped at spaces
+/// to keep them within `max_width` pixels. A word too long to fit on a line by itself is
+/// broken wherever it overflows.
+pub fn layout_rich_text<'a>(
+    text: &RichText,
+    fonts: &FontStack<'a>,
+    pixel_height: f32,
+    max_width: Option<f32>,
+) -> TextLayout<'a> {
+    let v_metrics = fonts.primary().v_metrics(pixel_height);
+    let line_height = v_metrics.ascent - v_metrics.descent + v_metrics.line_gap;
+    let icon_size = v_metrics.ascent - v_metrics.descent;
+    let max_width = max_width.unwrap_or(f32::INFINITY);
+
+    let mut layout = TextLayout {
+        glyphs: vec![],
+        icons: vec![],
+        pauses: vec![],
+        line_height,
+        lines: 1,
+        size: Size { x: 0.0, y: 0.0 },
+        length: 0,
+    };
+
+    let mut caret = rusttype::point(0.0, v_metrics.ascent);
+    let mut previous = None;
+    // Where the current line can be wrapped: the glyph and icon that follow the most
+    // recent space, and the caret's x-position after it.
+    let mut wrap_point: Option<(usize, usize, f32)> = None;
+
+    for run in &text.runs {
+        // Each run yields either a character or an icon.
+        let items: Vec<Option<char>> = match run {
+            TextRun::Text { text, .. } => text.chars().map(Some).collect(),
+            TextRun::Icon { .. } => vec![None],
+            TextRun::Pause(millis) => {
+                layout.pauses.push((layout.length, *millis));
+                continue;
+            }
+        };
+
+        for item in items {
+            if item == Some('\n') {
+                layout.size.x = layout.size.x.max(caret.x);
+                caret = rusttype::point(0.0, caret.y + line_height);
+                layout.lines += 1;
+                previous = None;
+                wrap_point = None;
+                continue;
+            }
+            if item.is_some_and(char::is_control) {
+                continue;
+            }
+
+            let choice = match (item, run) {
+                (Some(character), TextRun::Text { style, .. }) => Some((
+                    character,
+                    fonts.choose(character, FontStyle::new(style.bold, style.italic)),
+                )),
+                _ => None,
+            };
+            // Kerning only makes sense between glyphs from the same font.
+            let kerning = match (&choice, previous) {
+                (Some((character, choice)), Some((face, previous))) if face == choice.face => {
+                    choice.font.pair_kerning(pixel_height, previous, *character)
+                }
+                _ => 0.0,
+            };
+            let advance = match &choice {
+                Some((character, choice)) => {
+                    let advance = choice.font.advance_width(*character, pixel_height);
+                    // Faux bold is drawn one pixel wider than the regular glyph.
+                    if choice.synthesized.is_bold() {
+                        advance + 1.0
+                    } else {
+                        advance
+                    }
+                }
+                None => icon_size,
+            };
+
+            let is_space = item.is_some_and(char::is_whitespace);
+            if !is_space && caret.x + kerning + advance > max_width {
+                match wrap_point.take() {
+                    // Move the word that overflowed down to the next line.
+                    Some((glyph_start, icon_start, wrap_x)) if wrap_x > 0.0 => {
+                        layout.size.x = layout.size.x.max(wrap_x);
+                        for styled in &mut layout.glyphs[glyph_start..] {
+                            styled.glyph = styled.glyph.translated(-wrap_x, line_height);
+                            styled.line += 1;
+                        }
+                        for icon in &mut layout.icons[icon_start..] {
+                            icon.position.x -= wrap_x;
+                            icon.position.y += line_height;
+                            icon.line += 1;
+                        }
+                        caret = rusttype::point(caret.x - wrap_x, caret.y + line_height);
+                    }
+                    // There's nowhere to wrap, so break the word here.
+                    _ if caret.x > 0.0 => {
+                        layout.size.x = layout.size.x.max(caret.x);
+                        caret = rusttype::point(0.0, caret.y + line_height);
+                        previous = None;
+                    }
+                    _ => {}
+                }
+                layout.lines = layout.lines.max(1 + (caret.y / line_height) as usize);
+            }
+
+            match (choice, run) {
+                (Some((character, choice)), TextRun::Text { style, .. }) => {
+                    // Breaking a word at the end of a line leaves nothing to kern against.
+                    if previous.is_some() {
+                        caret.x += kerning;
+                    }
+                    previous = Some((choice.face, character));
+                    layout.glyphs.push(StyledGlyph {
+                        glyph: choice.font.glyph(character, pixel_height, caret),
+                        style: *style,
+                        face: choice.face,
+                        synthesized: choice.synthesized,
+                        index: layout.length,
+                        line: layout.lines - 1,
+                    });
+                }
+                (_, TextRun::Icon { frame, style }) => {
+                    layout.icons.push(StyledIcon {
+                        frame: *frame,
+                        style: *style,
+                        position: Position2D {
+                            x: caret.x,
+                            y: caret.y - v_metrics.ascent,
+                        },
+                        size: icon_size,
+                        index: layout.length,
+                        line: layout.lines - 1,
+                    });
+                    previous = None;
+                }
+                _ => unreachable!(),
+            }
+            caret.x += advance;
+            layout.length += 1;
+
+            if is_space {
+                wrap_point = Some((layout.glyphs.len(), layout.icons.len(), caret.x));
+            }
+        }
+    }
+
+    layout.size.x = layout.size.x.max(caret.x);
+    layout.size.y = line_height * layout.lines as f32;
+    layout
+}
+
+/// The timing of a typewriter-style reveal of some laid-out text, kept apart from the
+/// layout so it can be checked without laying the text out again.
+#[derive(Clone, Debug)]
+pub struct Reveal {
+    /// Pauses in the text as (number of characters and icons before the pause, milliseconds).
+    pauses: Vec<(usize, u32)>,
+    /// The total number of characters and icons in the text.
+    length: usize,
+    characters_per_second: f32,
+}
+
+impl Reveal {
+    pub fn new(layout: &TextLayout, characters_per_second: f32) -> Reveal {
+        Reveal {
+            pauses: layout.pauses.clone(),
+            length: layout.length,
+            characters_per_second,
+        }
+    }
+
+    /// Returns how many characters and icons of the text are showing `elapsed`
+    /// milliseconds after the reveal began, stopping for any pauses.
+    pub fn revealed_length(&self, elapsed: u64) -> usize {
+        let character_time = 1000.0 / self.characters_per_second;
+        let mut remaining = elapsed as f32;
+        let mut pauses = self.pauses.iter().peekable();
+
+        for shown in 0..self.length {
+            while let Some((_, millis)) = pauses.peek().filter(|(index, _)| *index == shown) {
+                remaining -= *millis as f32;
+                pauses.next();
+            }
+            remaining -= character_time;
+            if remaining < 0.0 {
+                return shown;
+            }
+        }
+        self.length
+    }
+
+    /// Returns how long, in milliseconds, the whole reveal takes, including any pauses.
+    pub fn duration(&self) -> u64 {
+        let pauses: u64 = self
+            .pauses
+            .iter()
+            .filter(|(index, _)| *index < self.length)
+            .map(|(_, millis)| u64::from(*millis))
+            .sum();
+        (self.length as f32 * 1000.0 / self.characters_per_second).ceil() as u64 + pauses
+    }
+}
+
+/// How glyphs are turned into pixels.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub enum TextRendering {
+    /// Each pixel is as opaque as the fraction of it the glyph covers.
+    #[default]
+    Coverage,
+    /// Glyphs are drawn from signed distance fields, with optional effects. Glyphs from
+    /// bitmap fonts are still drawn by coverage.
+    DistanceField(SdfEffects),
+}
+
+/// What [`render_rich_text`] needs to draw text from distance fields.
+///
+/// [`render_rich_text`]: fn.render_rich_text.html
+pub struct DistanceFieldText<'r> {
+    /// Fields for the text's glyphs, at the size the text was laid out at.
+    pub atlas: &'r SdfAtlas,
+    pub effects: &'r SdfEffects,
+}
+
+/// Draws laid-out rich text to a texture as it appears `elapsed` milliseconds into its
+/// animation. If `visible` is given, only that many characters and icons are drawn.
+/// Glyphs are drawn by coverage unless `distance_field` is given.
+///
+/// The texture has a margin on every side so that `[wave]` and `[shake]` text and
+/// distance-field effects aren't clipped. Use [`text_margin`] to find its size.
+///
+/// # Errors
+///
+/// Returns [`Error::None`] if the text contains icons but no [`IconSheet`] is given.
+///
+/// [`Error::None`]: ../error/enum.Error.html#variant.None
+/// [`IconSheet`]: struct.IconSheet.html
+/// [`text_margin`]: fn.text_margin.html
+pub fn render_rich_text(
+    layout: &TextLayout,
+    icons: Option<&IconSheet>,
+    distance_field: Option<&DistanceFieldText>,
+    elapsed: u64,
+    visible: Option<usize>,
+) -> Result<RgbaImage, Error> {
+    let margin = text_margin(
+        layout.line_height,
+        distance_field.map(|field| field.effects),
+    );
+    let visible = visible.unwrap_or(layout.length);
+    let mut texture = RgbaImage::new(
+        (layout.size.x + margin * 2.0).ceil() as u32,
+        (layout.size.y + margin * 2.0).ceil() as u32,
+    );
+
+    if let Some(field) = distance_field {
+        draw_distance_fields(&mut texture, layout, field, margin, elapsed, visible);
+    }
+
+    for styled in layout.glyphs.iter().filter(|glyph| glyph.index < visible) {
+        // Outline glyphs have already been drawn from their distance fields.
+        if let (Some(_), FaceGlyph::Outline(_)) = (distance_field, &styled.glyph) {
+            continue;
+        }
+
+        let (dx, dy) = effect_offset(
+            styled.style.effect,
+            styled.index,
+            layout.line_height,
+            elapsed,
+        );
+        let glyph = styled.glyph.translated(dx + margin, dy + margin);
+        let slant = if styled.synthesized.is_italic() {
+            ITALIC_SLANT
+        } else {
+            0.0
+        };
+        draw_glyph(&mut texture, &glyph, styled.style.color, 0, slant);
+        if styled.synthesized.is_bold() {
+            draw_glyph(&mut texture, &glyph, styled.style.color, 1, slant);
+        }
+    }
+
+    if !layout.icons.is_empty() {
+        let icons = icons.ok_or(Error::None())?;
+        for icon in layout.icons.iter().filter(|icon| icon.index < visible) {
+            let (dx, dy) =
+                effect_offset(icon.style.effect, icon.index, layout.line_height, elapsed);
+            draw_icon(
+                &mut texture,
+                icons,
+                icon,
+                (icon.position.x + dx + margin, icon.position.y + dy + margin),
+            );
+        }
+    }
+
+    Ok(texture)
+}
+
+/// The width of the empty border [`render_rich_text`] leaves around text with the given
+/// line height, drawn with the given distance-field effects, if any.
+///
+/// [`render_rich_text`]: fn.render_rich_text.html
+pub fn text_margin(line_height: f32, effects: Option<&SdfEffects>) -> f32 {
+    (WAVE_AMPLITUDE.max(SHAKE_AMPLITUDE) * line_height
+        + effects.map_or(0.0, SdfEffects::extent))
+    .ceil()
+}
+
+/// A glyph to be drawn from an [`SdfAtlas`] uploaded to a texture.
+///
+/// [`SdfAtlas`]: ../sdf/struct.SdfAtlas.html
+pub struct SdfQuad {
+    /// The corners of the quad in pixels from the text's top-left corner, in the order
+    /// top-left, bottom-left, bottom-right, top-right.
+    pub corners: [Position2D; 4],
+    /// The glyph's field in the atlas, in texels.
+    pub texels: Rect,
+    pub color: Color,
+}
+
+/// Places a quad for every visible outline glyph of laid-out text, as it appears `elapsed`
+/// milliseconds into its animation, so that the glyphs can be drawn from `atlas` by the
+/// `sdf.frag` shader. If `visible` is given, only that many characters and icons are
+/// shown. Glyphs from bitmap fonts and icons are left for [`render_rich_text`].
+///
+/// [`render_rich_text`]: fn.render_rich_text.html
+pub fn distance_field_quads(
+    layout: &TextLayout,
+    atlas: &SdfAtlas,
+    elapsed: u64,
+    visible: Option<usize>,
+) -> Vec<SdfQuad> {
+    let visible = visible.unwrap_or(layout.length);
+    let mut quads = vec![];
+    for styled in layout.glyphs.iter().filter(|glyph| glyph.index < visible) {
+        let glyph = match &styled.glyph {
+            FaceGlyph::Outline(glyph) => glyph,
+            FaceGlyph::Bitmap { .. } => continue,
+        };
+        let sdf_glyph = match atlas.get(styled.face, glyph.id()) {
+            Some(sdf_glyph) => sdf_glyph,
+            None => continue,
+        };
+        let (dx, dy) = effect_offset(
+            styled.style.effect,
+            styled.index,
+            layout.line_height,
+            elapsed,
+        );
+        let origin = glyph.position();
+        let left = origin.x + dx + sdf_glyph.offset.x;
+        let top = origin.y + dy + sdf_glyph.offset.y;
+        let bottom = top + sdf_glyph.rect.size.y;
+        // Italics lean the quad instead of each row of pixels.
+        let slant = if styled.synthesized.is_italic() {
+            ITALIC_SLANT
+        } else {
+            0.0
+        };
+        let lean = |y: f32| (origin.y + dy - y) * slant;
+
+        // Faux bold draws the glyph a second time, a pixel to the right.
+        let copies = if styled.synthesized.is_bold() { 2 } else { 1 };
+        for copy in 0..copies {
+            let left = left + copy as f32;
+            let right = left + sdf_glyph.rect.size.x;
+            quads.push(SdfQuad {
+                corners: [
+                    Position2D {
+                        x: left + lean(top),
+                        y: top,
+                    },
+                    Position2D {
+                        x: left + lean(bottom),
+                        y: bottom,
+                    },
+                    Position2D {
+                        x: right + lean(bottom),
+                        y: bottom,
+                    },
+                    Position2D {
+                        x: right + lean(top),
+                        y: top,
+                    },
+                ],
+                texels: sdf_glyph.rect,
+                color: styled.style.color,
+            });
+        }
+    }
+    quads
+}
+
+/// Draws every visible outline glyph from its distance field.
+///
+/// The fields of all the glyphs are merged first, so that the effects around one glyph
+/// go under its neighbors instead of over them.
+fn draw_distance_fields(
+    texture: &mut RgbaImage,
+    layout: &TextLayout,
+    field: &DistanceFieldText,
+    margin: f32,
+    elapsed: u64,
+    visible: usize,
+) {
+    let (width, height) = texture.dimensions();
+    let spread = field.atlas.spread;
+    // The distance to the nearest glyph at each pixel, and that glyph's color.
+    let mut distances = vec![-spread; (width * height) as usize];
+    let mut colors = vec![None; (width * height) as usize];
+
+    for styled in layout.glyphs.iter().filter(|glyph| glyph.index < visible) {
+        let glyph = match &styled.glyph {
+            FaceGlyph::Outline(glyph) => glyph,
+            FaceGlyph::Bitmap { .. } => continue,
+        };
+        let sdf_glyph = match field.atlas.get(styled.face, glyph.id()) {
+            Some(sdf_glyph) => sdf_glyph,
+            None => continue,
+        };
+        let (dx, dy) = effect_offset(
+            styled.style.effect,
+            styled.index,
+            layout.line_height,
+            elapsed,
+        );
+        let origin = glyph.position();
+        let (origin_x, origin_y) = (origin.x + dx + margin, origin.y + dy + margin);
+        let left = origin_x + sdf_glyph.offset.x;
+        let top = origin_y + sdf_glyph.offset.y;
+        let slant = if styled.synthesized.is_italic() {
+            ITALIC_SLANT
+        } else {
+            0.0
+        };
+        // Faux bold pushes the outline out by half a pixel.
+        let thickening = if styled.synthesized.is_bold() {
+            0.5
+        } else {
+            0.0
+        };
+
+        // Slanting moves rows by up to this much.
+        let bottom = sdf_glyph.offset.y + sdf_glyph.rect.size.y;
+        let lean = (sdf_glyph.offset.y.abs().max(bottom.abs()) * slant).ceil() as i32;
+        for y in top.floor() as i32..(top + sdf_glyph.rect.size.y).ceil() as i32 {
+            if y < 0 || y as u32 >= height {
+                continue;
+            }
+            let shift = (origin_y - (y as f32 + 0.5)) * slant;
+            for x in left.floor() as i32 - lean
+                ..(left + sdf_glyph.rect.size.x).ceil() as i32 + lean
+            {
+                if x < 0 || x as u32 >= width {
+                    continue;
+                }
+                let distance = field.atlas.distance(
+                    sdf_glyph,
+                    x as f32 + 0.5 - shift - left,
+                    y as f32 + 0.5 - top,
+                ) + thickening;
+                let pixel = (y as u32 * width + x as u32) as usize;
+                if distance > distances[pixel] {
+                    distances[pixel] = distance;
+                    colors[pixel] = Some(styled.style.color);
+                }
+            }
+        }
+    }
+
+    let shadow_offset = field.effects.shadow.map_or((0, 0), |shadow| {
+        (
+            shadow.offset.x.round() as i32,
+            shadow.offset.y.round() as i32,
+        )
+    });
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = (y * width + x) as usize;
+            let (shadow_x, shadow_y) = (x as i32 - shadow_offset.0, y as i32 - shadow_offset.1);
+            let shadow_distance = if shadow_x < 0
+                || shadow_y < 0
+                || shadow_x as u32 >= width
+                || shadow_y as u32 >= height
+            {
+                -spread
+            } else {
+                distances[(shadow_y as u32 * width + shadow_x as u32) as usize]
+            };
+            let fill = colors[pixel].unwrap_or(Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 0.0,
+            });
+            let color = field
+                .effects
+                .shade(fill, distances[pixel], shadow_distance);
+            if color.a > 0.0 {
+                texture.put_pixel(
+                    x,
+                    y,
+                    Rgba {
+                        data: [
+                            (color.r * 255.0) as u8,
+                            (color.g * 255.0) as u8,
+                            (color.b * 255.0) as u8,
+                            (color.a * 255.0) as u8,
+                        ],
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Calculates how far an animated glyph is displaced from its laid-out position.
+fn effect_offset(effect: TextEffect, index: usize, line_height: f32, elapsed: u64) -> (f32, f32) {
+    match effect {
+        TextEffect::None => (0.0, 0.0),
+        TextEffect::Wave => {
+            let phase = elapsed as f32 / WAVE_PERIOD * 2.0 * std::f32::consts::PI
+                + index as f32 * WAVE_PHASE_STEP;
+            (0.0, phase.sin() * WAVE_AMPLITUDE * line_height)
+        }
+        TextEffect::Shake => {
+            // A cheap hash of the glyph and the current shake interval, so each glyph
+            // jumps somewhere different but stays put until the interval ends.
+            let mut seed = (index as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (elapsed / SHAKE_INTERVAL).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            seed ^= seed >> 31;
+            seed = seed.wrapping_mul(0x94D0_49BB_1331_11EB);
+            let x = (seed & 0xFFFF) as f32 / 65535.0 * 2.0 - 1.0;
+            let y = ((seed >> 16) & 0xFFFF) as f32 / 65535.0 * 2.0 - 1.0;
+            let amplitude = SHAKE_AMPLITUDE * line_height;
+            (x * amplitude, y * amplitude)
+        }
+    }
+}
+
+/// Draws a single glyph to the texture in the given color, shifted `dx` pixels right.
+/// Each row is also shifted right by `slant` pixels per pixel above the baseline.
+fn draw_glyph(texture: &mut RgbaImage, glyph: &FaceGlyph, color: Color, dx: i32, slant: f32) {
+    match glyph {
+        FaceGlyph::Outline(glyph) => draw_outline_glyph(texture, glyph, color, dx, slant),
+        FaceGlyph::Bitmap {
+            font,
+            character,
+            scale,
+            position,
+        } => draw_bitmap_glyph(
+            texture,
+            font,
+            character,
+            *scale,
+            (position.x + dx as f32, position.y),
+            color,
+            slant,
+        ),
+    }
+}
+
+/// Draws an outline glyph's coverage to the texture.
+fn draw_outline_glyph(
+    texture: &mut RgbaImage,
+    glyph: &PositionedGlyph,
+    color: Color,
+    dx: i32,
+    slant: f32,
+) {
+    let bounds = match glyph.pixel_bounding_box() {
+        Some(bounds) => bounds,
+        // Whitespace has nothing to draw.
+        None => return,
+    };
+
+    let baseline = glyph.position().y;
+    glyph.draw(|x, y, coverage| {
+        let y = bounds.min.y + y as i32;
+        let x = bounds.min.x + x as i32 + dx + ((baseline - y as f32) * slant).round() as i32;
+        blend_pixel(texture, x, y, color, coverage);
+    });
+}
+
+/// Draws a bitmap glyph with its origin on the baseline at `origin`, tinting its texels
+/// by the given color.
+fn draw_bitmap_glyph(
+    texture: &mut RgbaImage,
+    font: &BitmapFont,
+    character: &BitmapChar,
+    scale: f32,
+    origin: (f32, f32),
+    color: Color,
+    slant: f32,
+) {
+    let page = match font.pages.get(character.page) {
+        Some(page) => page,
+        None => return,
+    };
+    let left = (origin.0 + character.x_offset * scale).round() as i32;
+    let top = (origin.1 + (character.y_offset - font.base) * scale).round() as i32;
+    let width = (character.width as f32 * scale).round() as i32;
+    let height = (character.height as f32 * scale).round() as i32;
+
+    for y in 0..height {
+        let shift = ((origin.1 - (top + y) as f32) * slant).round() as i32;
+        for x in 0..width {
+            // Nearest-neighbor sampling keeps pixel art crisp.
+            let u = character.x + ((x as f32 + 0.5) / scale) as u32;
+            let v = character.y + ((y as f32 + 0.5) / scale) as u32;
+            if u >= page.width() || v >= page.height() {
+                continue;
+            }
+            let texel = page.get_pixel(u, v).data;
+            blend_pixel(
+                texture,
+                left + x + shift,
+                top + y,
+                Color {
+                    r: f32::from(texel[0]) / 255.0 * color.r,
+                    g: f32::from(texel[1]) / 255.0 * color.g,
+                    b: f32::from(texel[2]) / 255.0 * color.b,
+                    a: color.a,
+                },
+                f32::from(texel[3]) / 255.0,
+            );
+        }
+    }
+}
+
+/// Draws an icon with its top-left corner at `corner`, scaled to the icon's size.
+fn draw_icon(texture: &mut RgbaImage, icons: &IconSheet, icon: &StyledIcon, corner: (f32, f32)) {
+    let source = icons.spritesheet.frame_rect(icon.frame as u16);
+
+    let size = icon.size.round() as i32;
+    for y in 0..size {
+        for x in 0..size {
+            // Nearest-neighbor sampling keeps pixel art crisp.
+            let u = (source.position.x + (x as f32 + 0.5) / icon.size * source.size.x) as u32;
+            let v = (source.position.y + (y as f32 + 0.5) / icon.size * source.size.y) as u32;
+            if u >= icons.image.width() || v >= icons.image.height() {
+                continue;
+            }
+            let texel = icons.image.get_pixel(u, v).data;
+            let tint = icon.style.color;
+            blend_pixel(
+                texture,
+                corner.0 as i32 + x,
+                corner.1 as i32 + y,
+                Color {
+                    r: f32::from(texel[0]) / 255.0 * tint.r,
+                    g: f32::from(texel[1]) / 255.0 * tint.g,
+                    b: f32::from(texel[2]) / 255.0 * tint.b,
+                    a: tint.a,
+                },
+                f32::from(texel[3]) / 255.0,
+            );
+        }
+    }
+}
+
+/// Writes a pixel unless a more opaque one is already there, so overlapping glyphs don't
+/// erase each other's edges.
+fn blend_pixel(texture: &mut RgbaImage, x: i32, y: i32, color: Color, coverage: f32) {
+    if x < 0 || y < 0 || x as u32 >= texture.width() || y as u32 >= texture.height() {
+        return;
+    }
+    let alpha = (color.a * coverage * 255.0) as u8;
+    let pixel = texture.get_pixel_mut(x as u32, y as u32);
+    if alpha > pixel.data[3] {
+        *pixel = Rgba {
+            data: [
+                (color.r * 255.0) as u8,
+                (color.g * 255.0) as u8,
+                (color.b * 255.0) as u8,
+                alpha,
+            ],
+        };
+    }
 }
@@ -8,7 +8,7 @@ use gfx_backend_metal as backend;
 use nalgebra_glm as glm;
 
 use gfx_hal::{
-    command::{BufferImageCopy, CommandBuffer, OneShot},
+    command::{BufferImageCopy, CommandBuffer, OneShot, Shot},
     format::{Aspects, Format},
     image::{Access, Extent, Layout, Offset, SubresourceLayers, SubresourceRange, Usage},
     memory::{Barrier, Dependencies},
@@ -60,6 +60,11 @@ pub struct Texture {
 
     /// The size in texels.
     pub size: Size,
+    /// The image file to load. If no file is given, the `Texture` starts out blank and
+    /// transparent, ready to be drawn into with [`Renderer::update_texture`].
+    ///
+    /// [`Renderer::update_texture`]: ../render/struct.Renderer.html#method.update_texture
+    #[serde(default)]
     pub file: Filename,
 
     /// When this `Texture` is bound to buffer memory, this stores the range of bytes within
@@ -146,7 +151,11 @@ impl Texture {
         device: &<Backend as GfxBackend>::Device,
         color_format: Format,
     ) -> Result<(), Error> {
-        let data = image::open(&self.file)?.to_rgba();
+        let data = if self.file.is_empty() {
+            RgbaImage::new(self.size.x as u32, self.size.y as u32)
+        } else {
+            image::open(&self.file)?.to_rgba()
+        };
         let image = unsafe {
             device.create_image(
                 gfx_hal::image::Kind::D2(data.width(), data.height(), 1, 1),
@@ -239,34 +248,7 @@ impl Texture {
             PipelineStage::TRANSFER,
         );
 
-        // Figure out the size of the texture data.
-        let pixel_size = mem::size_of::<Rgba<u8>>() as u32;
-        let row_size = pixel_size * self.size.x as u32;
-        let row_alignment_mask = limits.min_buffer_copy_pitch_alignment as u32 - 1;
-        let row_pitch = (row_size + row_alignment_mask) & !row_alignment_mask;
-
-        // Copy the data.
-        command_buffer.copy_buffer_to_image(
-            &staging_buffer.buffer,
-            self.get_image()?,
-            Layout::TransferDstOptimal,
-            &[BufferImageCopy {
-                buffer_offset: (self.buffer_memory_range.start - staging_buffer.offset) as u64,
-                buffer_width: (row_pitch / pixel_size) as u32,
-                buffer_height: self.size.y as u32,
-                image_layers: SubresourceLayers {
-                    aspects: Aspects::COLOR,
-                    level: 0,
-                    layers: 0..1,
-                },
-                image_offset: Offset { x: 0, y: 0, z: 0 },
-                image_extent: Extent {
-                    width: self.size.x as u32,
-                    height: self.size.y as u32,
-                    depth: 1,
-                },
-            }],
-        );
+        self.record_copy(&mut command_buffer, staging_buffer, limits)?;
 
         // Set Image to read mode.
         Texture::reformat_image(
@@ -305,11 +287,115 @@ impl Texture {
         Ok(())
     }
 
+    /// Writes new data of the same size for the `Texture` to the staging buffer, ready for
+    /// [`record_replacement`] to copy into its `Image`.
+    ///
+    /// The data is staged in the same part of the buffer that the `Texture`'s original
+    /// data was copied to by [`buffer_data`], so nothing still copying from there may be
+    /// in flight.
+    ///
+    /// [`buffer_data`]: #method.buffer_data
+    /// [`record_replacement`]: #method.record_replacement
+    pub unsafe fn stage_data(
+        &self,
+        device: &<Backend as GfxBackend>::Device,
+        buffer_memory: &<Backend as GfxBackend>::Memory,
+        limits: &Limits,
+        data: &RgbaImage,
+    ) -> Result<(), Error> {
+        if data.width() != self.size.x as u32 || data.height() != self.size.y as u32 {
+            return Err(Error::TextureSize());
+        }
+
+        Texture::write_image_to_buffer(
+            device,
+            buffer_memory,
+            self.buffer_memory_range.start as u64..self.buffer_memory_range.end as u64,
+            data,
+            limits,
+        )
+    }
+
+    /// Records the commands to replace the contents of the `Texture`'s `Image` with the
+    /// data staged by [`stage_data`]. This goes in a frame's command buffer before its
+    /// render pass, so the barriers also wait for earlier frames to finish sampling the
+    /// `Image`.
+    ///
+    /// [`stage_data`]: #method.stage_data
+    pub unsafe fn record_replacement<S: Shot>(
+        &self,
+        command_buffer: &mut CommandBuffer<Backend, Graphics, S>,
+        staging_buffer: &BufferObject,
+        limits: &Limits,
+    ) -> Result<(), Error> {
+        // The Image is being read by the shaders, so it has to be switched back to
+        // write mode first.
+        Texture::reformat_image(
+            command_buffer,
+            (Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+            (Access::TRANSFER_WRITE, Layout::TransferDstOptimal),
+            self.get_image()?,
+            PipelineStage::FRAGMENT_SHADER,
+            PipelineStage::TRANSFER,
+        );
+
+        self.record_copy(command_buffer, staging_buffer, limits)?;
+
+        Texture::reformat_image(
+            command_buffer,
+            (Access::TRANSFER_WRITE, Layout::TransferDstOptimal),
+            (Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+            self.get_image()?,
+            PipelineStage::TRANSFER,
+            PipelineStage::FRAGMENT_SHADER,
+        );
+        Ok(())
+    }
+
+    /// Records the command to copy this `Texture`'s data from the staging buffer into its
+    /// `Image`, which must already be in write mode.
+    unsafe fn record_copy<S: Shot>(
+        &self,
+        command_buffer: &mut CommandBuffer<Backend, Graphics, S>,
+        staging_buffer: &BufferObject,
+        limits: &Limits,
+    ) -> Result<(), Error> {
+        // Figure out the size of the texture data.
+        let pixel_size = mem::size_of::<Rgba<u8>>() as u32;
+        let row_size = pixel_size * self.size.x as u32;
+        let row_alignment_mask = limits.min_buffer_copy_pitch_alignment as u32 - 1;
+        let row_pitch = (row_size + row_alignment_mask) & !row_alignment_mask;
+
+        // Copy the data.
+        command_buffer.copy_buffer_to_image(
+            &staging_buffer.buffer,
+            self.get_image()?,
+            Layout::TransferDstOptimal,
+            &[BufferImageCopy {
+                buffer_offset: (self.buffer_memory_range.start - staging_buffer.offset) as u64,
+                buffer_width: (row_pitch / pixel_size) as u32,
+                buffer_height: self.size.y as u32,
+                image_layers: SubresourceLayers {
+                    aspects: Aspects::COLOR,
+                    level: 0,
+                    layers: 0..1,
+                },
+                image_offset: Offset { x: 0, y: 0, z: 0 },
+                image_extent: Extent {
+                    width: self.size.x as u32,
+                    height: self.size.y as u32,
+                    depth: 1,
+                },
+            }],
+        );
+        Ok(())
+    }
+
     // Extracted from copy_image_to_memory to clean it up a bit.
     /// Switches an Image to the given state/format, handling the synchronization
     /// involved.
-    fn reformat_image(
-        command_buffer: &mut CommandBuffer<Backend, Graphics>,
+    fn reformat_image<S: Shot>(
+        command_buffer: &mut CommandBuffer<Backend, Graphics, S>,
         source_format: (Access, Layout),
         target_format: (Access, Layout),
         resource: &<Backend as GfxBackend>::Image,
@@ -2,14 +2,23 @@
 // Timers and time-based calculations.
 // (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
 
-use lazy_static::lazy_static;
-use log;
-use std::time::Instant;
+//! Game time and real time.
+//!
+//! Everything that happens over time in the game, like animations and dialogue, runs on the
+//! [`GameClock`] resource rather than the system clock. It can be paused or slowed down,
+//! and tests can step it by exact amounts.
+//!
+//! The game is updated in steps of a fixed length, however fast frames are drawn. A
+//! [`FixedTimestep`] works out how many steps each frame's worth of real time makes, and
+//! how far into the next step the frame is drawn, so that things can be drawn partway
+//! between where they were before the last update and where they are now.
+//!
+//! [`FixedTimestep`]: struct.FixedTimestep.html
+//! [`GameClock`]: struct.GameClock.html
 
-// Rust doesn't like function calls in statics, hence the lazy_static.
-lazy_static! {
-    pub static ref EPOCH: Instant = { Instant::now() };
-}
+use log;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 
 /// Returns the time elapsed in milliseconds since the given Instant.
 pub fn elapsed_as_millis(start_time: Instant) -> u64 {
@@ -19,10 +28,95 @@ pub fn elapsed_as_millis(start_time: Instant) -> u64 {
     result
 }
 
-/// Returns the current frame for a simple looping animation.
-pub fn calculate_frame(start_time: Instant, frame_count: usize, frame_length: u32) -> usize {
-    let elapsed = elapsed_as_millis(start_time);
-    ((elapsed / u64::from(frame_length)) % frame_count as u64) as usize
+/// Time as it passes in the game.
+///
+/// The clock only moves when it's told to, once a frame, so everything that reads it during
+/// a frame sees the same time.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GameClock {
+    /// The game time since the clock started.
+    time: Duration,
+    /// The game time before the last advance.
+    previous: Duration,
+    /// How fast game time passes compared to real time.
+    time_scale: f32,
+    paused: bool,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock {
+            time: Duration::from_secs(0),
+            previous: Duration::from_secs(0),
+            time_scale: 1.0,
+            paused: false,
+        }
+    }
+}
+
+impl GameClock {
+    pub fn new() -> GameClock {
+        GameClock::default()
+    }
+
+    /// Moves the clock on by `real` time, scaled by the time scale. A paused clock stays
+    /// where it is.
+    pub fn advance(&mut self, real: Duration) {
+        self.previous = self.time;
+        if !self.paused {
+            self.time += real.mul_f32(self.time_scale);
+        }
+    }
+
+    /// Moves the clock on by exactly `step`, whether it's paused or not and whatever the time
+    /// scale, for going through the game a frame at a time.
+    pub fn step(&mut self, step: Duration) {
+        self.previous = self.time;
+        self.time += step;
+    }
+
+    /// The game time since the clock started.
+    pub fn now(&self) -> Duration {
+        self.time
+    }
+
+    /// The game time since the clock started, in whole milliseconds.
+    pub fn millis(&self) -> u64 {
+        self.time.as_millis() as u64
+    }
+
+    /// How much game time the last advance added.
+    pub fn delta(&self) -> Duration {
+        self.time - self.previous
+    }
+
+    /// How many whole milliseconds the last advance added. Fractions carry over to later
+    /// frames, so these add up to `millis`.
+    pub fn delta_millis(&self) -> u64 {
+        self.millis() - self.previous.as_millis() as u64
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Changes how fast game time passes: 2 is twice as fast as real time, 0.5 half as fast.
+    /// Negative scales are taken as 0.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
 }
 
 /// A quick thing for calculating FPS sort of.
@@ -52,17 +146,85 @@ impl Profiler {
     }
 }
 
+/// Divides real time into the fixed-length steps the game is updated in.
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    step: Duration,
+    /// Real time that hasn't made up a whole step yet.
+    accumulator: Duration,
+    /// The most steps taken for one frame. After a long stall the game skips ahead rather
+    /// than trying to catch up, which would only make the next frame slower still.
+    max_steps: u32,
+}
+
+impl FixedTimestep {
+    /// Takes `updates_per_second` steps each second, but no more than `max_steps` at once.
+    pub fn new(updates_per_second: u32, max_steps: u32) -> FixedTimestep {
+        FixedTimestep {
+            step: Duration::from_secs(1) / updates_per_second.max(1),
+            accumulator: Duration::from_secs(0),
+            max_steps: max_steps.max(1),
+        }
+    }
+
+    /// The length of each step.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Adds `real` time and returns how many whole steps to take.
+    pub fn accumulate(&mut self, real: Duration) -> u32 {
+        self.accumulator += real;
+        let steps = (self.accumulator.as_nanos() / self.step.as_nanos()) as u32;
+        if steps > self.max_steps {
+            let dropped = self.accumulator.as_nanos() % self.step.as_nanos();
+            self.accumulator = Duration::from_nanos(dropped as u64);
+            return self.max_steps;
+        }
+        self.accumulator -= self.step * steps;
+        steps
+    }
+
+    /// How far through the next step the leftover time goes, from 0 to 1. Things drawn now
+    /// should be drawn this far between their last two updates.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // Tests that a sprites' current frame indices do in fact depend on elapsed time.
     #[test]
-    fn print_frame() {
-        let start = std::time::Instant::now();
-        for _i in 0..3 {
-            println!("{}", calculate_frame(start, 3, 1000));
-            std::thread::sleep(std::time::Duration::new(1, 0));
+    fn clock_scales_and_pauses() {
+        let mut clock = GameClock::new();
+        let frame = Duration::from_micros(16_700);
+        clock.set_time_scale(0.5);
+        for _ in 0..3 {
+            clock.advance(frame);
         }
+        // 3 frames at half speed is 25.05 ms, and fractions of a millisecond carry over.
+        assert_eq!(clock.millis(), 25);
+        assert_eq!(clock.delta_millis(), 9);
+
+        clock.pause();
+        clock.advance(frame);
+        assert_eq!(clock.millis(), 25);
+        assert_eq!(clock.delta(), Duration::from_secs(0));
+        clock.step(Duration::from_millis(10));
+        assert_eq!(clock.millis(), 35);
+    }
+
+    #[test]
+    fn timestep_caps_catching_up() {
+        let mut timestep = FixedTimestep::new(100, 3);
+        assert_eq!(timestep.accumulate(Duration::from_millis(25)), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(timestep.accumulate(Duration::from_millis(4)), 0);
+        assert_eq!(timestep.accumulate(Duration::from_millis(1)), 1);
+        // A long stall only takes the most steps allowed, and keeps the fraction.
+        assert_eq!(timestep.accumulate(Duration::from_millis(1003)), 3);
+        assert!((timestep.alpha() - 0.3).abs() < 1e-4);
     }
 }
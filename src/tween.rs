@@ -0,0 +1,551 @@
+// tween.rs
+// Smoothly changing things over time.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Tweens: moving, turning, scaling, tinting and fading things over time.
+//!
+//! A [`Tween`] changes [`Property`]s to new values over some number of milliseconds,
+//! following an [`Easing`] curve. Tweens can be put one after another in a `Sequence`, run
+//! together in a `Parallel` group, and spaced out with a `Delay`:
+//!
+//! ```yaml
+//! Tweener:
+//!   name: slide_in
+//!   tween:
+//!     Sequence:
+//!       - Delay: 250
+//!       - Parallel:
+//!           - To: { property: { Translation: [0, -200] }, duration: 500, easing: BackOut }
+//!           - To: { property: { Opacity: 1.0 }, duration: 300 }
+//!   repeat: { Times: 2 }
+//!   yoyo: true
+//! ```
+//!
+//! A [`Tweener`] component plays a tween on its entity's [`Animation2D`], or its [`Quad`]
+//! if it doesn't have one. It can repeat a number of times or forever, and with `yoyo`
+//! every other time through plays backwards. The [`TweenSystem`] plays them, going by the
+//! [`GameClock`], and sends a [`TweenFinished`] event when one is done.
+//!
+//! Anything else that implements [`Tweenable`], like the [`Camera`], can be tweened by
+//! calling [`Tweener::update`] on it directly.
+//!
+//! [`Animation2D`]: ../component/struct.Animation2D.html
+//! [`Camera`]: ../geometry/struct.Camera.html
+//! [`Easing`]: enum.Easing.html
+//! [`GameClock`]: ../time/struct.GameClock.html
+//! [`Property`]: enum.Property.html
+//! [`Quad`]: ../component/struct.Quad.html
+//! [`Tween`]: enum.Tween.html
+//! [`TweenFinished`]: ../event/struct.TweenFinished.html
+//! [`Tweenable`]: trait.Tweenable.html
+//! [`Tweener`]: struct.Tweener.html
+//! [`Tweener::update`]: struct.Tweener.html#method.update
+//! [`TweenSystem`]: ../system/struct.TweenSystem.html
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::{
+    component::{Animation2D, Quad},
+    geometry::{Camera, TransformData},
+    serial::Color,
+};
+
+/// How a tween speeds up and slows down. Each curve comes in three kinds: `In` starts
+/// slowly, `Out` ends slowly, and `InOut` does both.
+///
+/// `Back` curves go a little past where they start or end, `Elastic` ones wobble around
+/// it, and `Bounce` ones bounce off it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+}
+
+/// How far `Back` curves overshoot.
+const BACK_OVERSHOOT: f32 = 1.701_58;
+
+impl Easing {
+    /// How far along the change is when `t` of the time has passed, where both start at 0
+    /// and end at 1.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        let in_out = |ease_in: fn(f32) -> f32, t: f32| {
+            if t < 0.5 {
+                ease_in(t * 2.0) / 2.0
+            } else {
+                1.0 - ease_in((1.0 - t) * 2.0) / 2.0
+            }
+        };
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => quad_in(t),
+            Easing::QuadOut => 1.0 - quad_in(1.0 - t),
+            Easing::QuadInOut => in_out(quad_in, t),
+            Easing::CubicIn => cubic_in(t),
+            Easing::CubicOut => 1.0 - cubic_in(1.0 - t),
+            Easing::CubicInOut => in_out(cubic_in, t),
+            Easing::BackIn => back_in(t),
+            Easing::BackOut => 1.0 - back_in(1.0 - t),
+            Easing::BackInOut => in_out(back_in, t),
+            Easing::ElasticIn => elastic_in(t),
+            Easing::ElasticOut => 1.0 - elastic_in(1.0 - t),
+            Easing::ElasticInOut => in_out(elastic_in, t),
+            Easing::BounceIn => 1.0 - bounce_out(1.0 - t),
+            Easing::BounceOut => bounce_out(t),
+            Easing::BounceInOut => in_out(|t| 1.0 - bounce_out(1.0 - t), t),
+        }
+    }
+}
+
+fn quad_in(t: f32) -> f32 {
+    t * t
+}
+
+fn cubic_in(t: f32) -> f32 {
+    t * t * t
+}
+
+fn back_in(t: f32) -> f32 {
+    t * t * ((BACK_OVERSHOOT + 1.0) * t - BACK_OVERSHOOT)
+}
+
+fn elastic_in(t: f32) -> f32 {
+    if t <= 0.0 || t >= 1.0 {
+        return t;
+    }
+    -(2.0_f32.powf(10.0 * t - 10.0)) * ((t * 10.0 - 10.75) * (2.0 * PI / 3.0)).sin()
+}
+
+fn bounce_out(t: f32) -> f32 {
+    const STRENGTH: f32 = 7.5625;
+    const WIDTH: f32 = 2.75;
+    if t < 1.0 / WIDTH {
+        STRENGTH * t * t
+    } else if t < 2.0 / WIDTH {
+        let t = t - 1.5 / WIDTH;
+        STRENGTH * t * t + 0.75
+    } else if t < 2.5 / WIDTH {
+        let t = t - 2.25 / WIDTH;
+        STRENGTH * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / WIDTH;
+        STRENGTH * t * t + 0.984_375
+    }
+}
+
+/// Everything about a thing that tweens can change.
+///
+/// The default pose is the identity transform with a white tint, which changes nothing
+/// when something is offset by it.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Pose {
+    pub transform: TransformData,
+    pub tint: Color,
+}
+
+impl Pose {
+    /// This pose moved and turned by `by`'s translation and rotation, with its scale and
+    /// tint multiplied by `by`'s.
+    pub fn offset_by(&self, by: &Pose) -> Pose {
+        let (transform, offset) = (&self.transform, &by.transform);
+        Pose {
+            transform: TransformData {
+                translation: vec![
+                    transform.translation[0] + offset.translation[0],
+                    transform.translation[1] + offset.translation[1],
+                ],
+                scaling: vec![
+                    transform.scaling[0] * offset.scaling[0],
+                    transform.scaling[1] * offset.scaling[1],
+                ],
+                rotation: transform.rotation + offset.rotation,
+            },
+            tint: Color {
+                r: self.tint.r * by.tint.r,
+                g: self.tint.g * by.tint.g,
+                b: self.tint.b * by.tint.b,
+                a: self.tint.a * by.tint.a,
+            },
+        }
+    }
+}
+
+/// Something with a [`Pose`] to tween.
+///
+/// [`Pose`]: struct.Pose.html
+pub trait Tweenable {
+    fn pose(&self) -> Pose;
+
+    /// Changes whatever the thing has of `pose`. Things that can't be tinted, for
+    /// instance, can ignore the tint.
+    fn set_pose(&mut self, pose: &Pose);
+}
+
+/// A value for a tween to change something to.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Property {
+    Translation([f32; 2]),
+    /// In radians. Tweens don't take the short way round, so a tween to 2π from 0 makes a
+    /// full turn.
+    Rotation(f32),
+    Scale([f32; 2]),
+    /// The red, green and blue a texture is multiplied by.
+    Tint([f32; 3]),
+    /// From 0, invisible, to 1.
+    Opacity(f32),
+}
+
+impl Property {
+    /// Moves `pose` `amount` of the way from where it is to this value. An `amount` past 1
+    /// overshoots.
+    fn blend(&self, pose: &mut Pose, amount: f32) {
+        let lerp = |from: &mut f32, to: f32| *from += (to - *from) * amount;
+        let transform = &mut pose.transform;
+        let tint = &mut pose.tint;
+        match self {
+            Property::Translation(to) => {
+                lerp(&mut transform.translation[0], to[0]);
+                lerp(&mut transform.translation[1], to[1]);
+            }
+            Property::Rotation(to) => lerp(&mut transform.rotation, *to),
+            Property::Scale(to) => {
+                lerp(&mut transform.scaling[0], to[0]);
+                lerp(&mut transform.scaling[1], to[1]);
+            }
+            Property::Tint(to) => {
+                lerp(&mut tint.r, to[0]);
+                lerp(&mut tint.g, to[1]);
+                lerp(&mut tint.b, to[2]);
+            }
+            Property::Opacity(to) => lerp(&mut tint.a, *to),
+        }
+    }
+
+    /// Copies the part of `from` this property is about into `pose`.
+    fn copy(&self, from: &Pose, pose: &mut Pose) {
+        match self {
+            Property::Translation(_) => {
+                pose.transform.translation = from.transform.translation.clone()
+            }
+            Property::Rotation(_) => pose.transform.rotation = from.transform.rotation,
+            Property::Scale(_) => pose.transform.scaling = from.transform.scaling.clone(),
+            Property::Tint(_) => {
+                pose.tint.r = from.tint.r;
+                pose.tint.g = from.tint.g;
+                pose.tint.b = from.tint.b;
+            }
+            Property::Opacity(_) => pose.tint.a = from.tint.a,
+        }
+    }
+}
+
+/// Changes to make over time.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Tween {
+    /// Changes a property from wherever it is when the tween gets to it, over `duration`
+    /// milliseconds.
+    To {
+        property: Property,
+        duration: u32,
+        #[serde(default)]
+        easing: Easing,
+    },
+    /// Waits for some number of milliseconds.
+    Delay(u32),
+    /// Plays tweens one after another.
+    Sequence(Vec<Tween>),
+    /// Plays tweens at the same time, taking as long as the longest one.
+    Parallel(Vec<Tween>),
+}
+
+impl Tween {
+    /// How long the tween takes, in milliseconds.
+    pub fn duration(&self) -> u64 {
+        match self {
+            Tween::To { duration, .. } | Tween::Delay(duration) => u64::from(*duration),
+            Tween::Sequence(tweens) => tweens.iter().map(Tween::duration).sum(),
+            Tween::Parallel(tweens) => tweens.iter().map(Tween::duration).max().unwrap_or(0),
+        }
+    }
+
+    /// Changes `pose` as the tween would have once it's been playing for `elapsed`
+    /// milliseconds, starting from `pose`.
+    pub fn apply(&self, elapsed: u64, pose: &mut Pose) {
+        match self {
+            Tween::To {
+                property,
+                duration,
+                easing,
+            } => {
+                let progress = if *duration == 0 {
+                    1.0
+                } else {
+                    elapsed as f32 / *duration as f32
+                };
+                property.blend(pose, easing.apply(progress));
+            }
+            Tween::Delay(_) => {}
+            Tween::Sequence(tweens) => {
+                let mut start = 0;
+                for tween in tweens {
+                    if elapsed < start {
+                        break;
+                    }
+                    tween.apply(elapsed - start, pose);
+                    start += tween.duration();
+                }
+            }
+            Tween::Parallel(tweens) => {
+                for tween in tweens {
+                    tween.apply(elapsed, pose);
+                }
+            }
+        }
+    }
+
+    /// Every property the tween changes.
+    pub fn properties(&self) -> Vec<&Property> {
+        match self {
+            Tween::To { property, .. } => vec![property],
+            Tween::Delay(_) => vec![],
+            Tween::Sequence(tweens) | Tween::Parallel(tweens) => {
+                tweens.iter().flat_map(Tween::properties).collect()
+            }
+        }
+    }
+}
+
+/// How many times a [`Tweener`] plays its tween.
+///
+/// [`Tweener`]: struct.Tweener.html
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Repeat {
+    #[default]
+    Once,
+    Times(u32),
+    Forever,
+}
+
+/// A component that plays a [`Tween`] on its entity. See the [module documentation].
+///
+/// [module documentation]: index.html
+/// [`Tween`]: enum.Tween.html
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Tweener {
+    /// Sent with [`TweenFinished`] to say which tween it was.
+    ///
+    /// [`TweenFinished`]: ../event/struct.TweenFinished.html
+    #[serde(default)]
+    pub name: Option<String>,
+    pub tween: Tween,
+    #[serde(default)]
+    pub repeat: Repeat,
+    /// Whether every other time through plays backwards, back to where it started.
+    #[serde(default)]
+    pub yoyo: bool,
+    /// The game time the tween started at, in milliseconds, once it has.
+    #[serde(default)]
+    pub since: Option<u64>,
+    /// What was being tweened, as it was when the tween started.
+    #[serde(default)]
+    pub from: Option<Pose>,
+    #[serde(default)]
+    pub finished: bool,
+}
+
+impl Tweener {
+    pub fn new(tween: Tween) -> Tweener {
+        Tweener {
+            name: None,
+            tween,
+            repeat: Repeat::Once,
+            yoyo: false,
+            since: None,
+            from: None,
+            finished: false,
+        }
+    }
+
+    /// Starts the tween over from wherever its target is the next time it's updated.
+    pub fn restart(&mut self) {
+        self.since = None;
+        self.from = None;
+        self.finished = false;
+    }
+
+    /// How far into the tween `target` should be at game time `now`, in milliseconds, and
+    /// whether it's done.
+    fn position(&self, now: u64) -> (u64, bool) {
+        let duration = self.tween.duration();
+        let elapsed = now.saturating_sub(self.since.unwrap_or(now));
+        let times = match self.repeat {
+            Repeat::Once => Some(1),
+            Repeat::Times(times) => Some(u64::from(times.max(1))),
+            Repeat::Forever => None,
+        };
+        let (pass, position, done) = match times {
+            _ if duration == 0 => (times.unwrap_or(1) - 1, duration, true),
+            Some(times) if elapsed >= duration * times => (times - 1, duration, true),
+            _ => (elapsed / duration, elapsed % duration, false),
+        };
+        if self.yoyo && pass % 2 == 1 {
+            (duration - position, done)
+        } else {
+            (position, done)
+        }
+    }
+
+    /// Puts `target` where the tween has it at game time `now`, starting the tween if it
+    /// hasn't started. Once the tween is done, `target` is left alone.
+    ///
+    /// Returns `true` if the tween finished just now.
+    pub fn update<T: Tweenable + ?Sized>(&mut self, now: u64, target: &mut T) -> bool {
+        if self.finished {
+            return false;
+        }
+        self.since.get_or_insert(now);
+        let from = self.from.get_or_insert_with(|| target.pose()).clone();
+
+        let (position, done) = self.position(now);
+        let mut tweened = from;
+        self.tween.apply(position, &mut tweened);
+
+        // Only what the tween changes is touched, so anything else moving the target
+        // still can.
+        let mut pose = target.pose();
+        for property in self.tween.properties() {
+            property.copy(&tweened, &mut pose);
+        }
+        target.set_pose(&pose);
+
+        self.finished = done;
+        done
+    }
+}
+
+impl Tweenable for Animation2D {
+    fn pose(&self) -> Pose {
+        Pose {
+            transform: self.transform_data.clone(),
+            tint: self.tint,
+        }
+    }
+
+    fn set_pose(&mut self, pose: &Pose) {
+        self.transform_data = pose.transform.clone();
+        self.tint = pose.tint;
+    }
+}
+
+impl Tweenable for Quad {
+    fn pose(&self) -> Pose {
+        Pose {
+            transform: self.transform_data.clone(),
+            tint: self.tint,
+        }
+    }
+
+    fn set_pose(&mut self, pose: &Pose) {
+        self.transform_data = pose.transform.clone();
+        self.tint = pose.tint;
+    }
+}
+
+/// The camera's position is its translation and its zoom is its horizontal scale.
+impl Tweenable for Camera {
+    fn pose(&self) -> Pose {
+        Pose {
+            transform: TransformData::new(
+                nalgebra_glm::vec2(self.position.x, self.position.y),
+                nalgebra_glm::vec2(self.zoom, self.zoom),
+                0.0,
+            ),
+            tint: Color::default(),
+        }
+    }
+
+    fn set_pose(&mut self, pose: &Pose) {
+        self.position.x = pose.transform.translation[0];
+        self.position.y = pose.transform.translation[1];
+        self.zoom = pose.transform.scaling[0];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_start_and_end_in_place() {
+        use Easing::*;
+        for easing in [
+            Linear,
+            QuadIn,
+            QuadOut,
+            QuadInOut,
+            CubicIn,
+            CubicOut,
+            CubicInOut,
+            BackIn,
+            BackOut,
+            BackInOut,
+            ElasticIn,
+            ElasticOut,
+            ElasticInOut,
+            BounceIn,
+            BounceOut,
+            BounceInOut,
+        ]
+        .iter()
+        {
+            assert!(easing.apply(0.0).abs() < 1e-5, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-5, "{:?}", easing);
+        }
+        assert!(BackIn.apply(0.2) < 0.0);
+        assert!((QuadInOut.apply(0.25) - 0.125).abs() < 1e-5);
+    }
+
+    #[test]
+    fn sequences_yoyo_back_to_the_start() {
+        let mut camera = Camera::default();
+        let mut tweener = Tweener::new(Tween::Sequence(vec![
+            Tween::Delay(100),
+            Tween::To {
+                property: Property::Translation([100.0, 0.0]),
+                duration: 100,
+                easing: Easing::Linear,
+            },
+        ]));
+        tweener.repeat = Repeat::Times(2);
+        tweener.yoyo = true;
+
+        assert!(!tweener.update(1000, &mut camera));
+        tweener.update(1150, &mut camera);
+        assert!((camera.position.x - 50.0).abs() < 1e-4);
+        tweener.update(1200, &mut camera);
+        assert!((camera.position.x - 100.0).abs() < 1e-4);
+        tweener.update(1250, &mut camera);
+        assert!((camera.position.x - 50.0).abs() < 1e-4);
+        // Played backwards, the move comes first and then the delay.
+        tweener.update(1350, &mut camera);
+        assert!(camera.position.x.abs() < 1e-4);
+        assert!(tweener.update(1400, &mut camera));
+        assert!(!tweener.update(1500, &mut camera));
+    }
+}
@@ -0,0 +1,794 @@
+// validate.rs
+// Checking that loaded data fits together.
+// (c) 2019 Ryan McGowan <ryan@internally-combusted.net>
+
+//! Checks that the data files refer to each other correctly.
+//!
+//! Each file can be perfectly good YAML while still referring to components, entities,
+//! textures, or frames that don't exist. Left alone, those mistakes turn into panics deep
+//! in the drawing code. [`validate`] looks for all of them at once, right after the data is
+//! loaded, and reports each one as a [`Problem`] saying which file it's in and where.
+//!
+//! [`Problem`]: struct.Problem.html
+//! [`validate`]: fn.validate.html
+
+use serde_yaml::Value;
+use std::{any::TypeId, fmt};
+
+use crate::{
+    animation::AnimationController,
+    arena::Handle,
+    component::{Animation2D, ComponentManager, Quad},
+    data::GameMap,
+    dialogue::DialogueData,
+    draw::{Background, Spritesheet},
+    entity::EntityManager,
+    prefab::{Prefab, PrefabInstance, Prefabs},
+    serial::Index,
+    texture::Texture,
+};
+
+/// Something wrong with the data.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub file: &'static str,
+    /// Where in the file the problem is, e.g. `entities[0].components[1]`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(formatter, "{}: {}: {}", self.file, self.path, self.message)
+    }
+}
+
+/// Everything that gets checked, as loaded.
+pub struct Sources<'s> {
+    pub entities: &'s EntityManager,
+    pub components: &'s ComponentManager,
+    /// The handles of the components from `./data/components.yaml`, in the order they're
+    /// listed.
+    pub component_handles: &'s [Handle],
+    pub textures: &'s [Texture],
+    pub spritesheets: &'s [Spritesheet],
+    pub backgrounds: &'s [Background],
+    pub maps: &'s [GameMap],
+    pub prefabs: &'s Prefabs,
+    pub dialogue: &'s DialogueData,
+}
+
+/// Finds every dangling reference, duplicate index and out-of-range frame in the data.
+///
+/// Entities are expected to be as they were loaded from `./data/entities.yaml`, before any
+/// are created from prefabs.
+pub fn validate(sources: &Sources) -> Vec<Problem> {
+    let mut checker = Checker {
+        sources,
+        problems: vec![],
+    };
+    checker.check_indices();
+    checker.check_entities();
+    checker.check_components();
+    checker.check_maps();
+    checker.check_dialogue();
+    checker.check_prefabs();
+    checker.problems
+}
+
+/// Component data that can refer to other data.
+trait Validate {
+    /// Reports any problems with `self`, found at `path` in `file`.
+    fn validate(&self, checker: &mut Checker, file: &'static str, path: &str);
+}
+
+impl Validate for Animation2D {
+    fn validate(&self, checker: &mut Checker, file: &'static str, path: &str) {
+        checker.check_texture(self.texture_index, file, &format!("{}.texture_index", path));
+
+        let spritesheet = checker.check_spritesheet(
+            self.spritesheet_index,
+            file,
+            &format!("{}.spritesheet_index", path),
+        );
+        if let Some(spritesheet) = spritesheet {
+            for (position, name) in self.spritesheet_animations.iter().enumerate() {
+                if !spritesheet.animations.contains_key(name) {
+                    checker.report(
+                        file,
+                        format!("{}.spritesheet_animations[{}]", path, position),
+                        format!(
+                            "spritesheet {} has no animation named {}",
+                            self.spritesheet_index, name
+                        ),
+                    );
+                }
+            }
+        }
+        let animation_count = self.animations.len() + self.spritesheet_animations.len();
+        if self.current_animation >= animation_count {
+            checker.report(
+                file,
+                format!("{}.current_animation", path),
+                format!(
+                    "there's no animation {}; there are {}",
+                    self.current_animation, animation_count
+                ),
+            );
+        }
+
+        for (index, animation) in self.animations.iter().enumerate() {
+            let path = format!("{}.animations[{}]", path, index);
+            if animation.frames.is_empty() {
+                checker.report(file, format!("{}.frames", path), "there are no frames");
+            }
+            if animation.frame_length == 0 {
+                checker.report(
+                    file,
+                    format!("{}.frame_length", path),
+                    "frames can't last 0 milliseconds",
+                );
+            }
+            for (position, frame) in animation.frames.iter().enumerate() {
+                if frame.duration == Some(0) {
+                    checker.report(
+                        file,
+                        format!("{}.frames[{}].duration", path, position),
+                        "frames can't last 0 milliseconds",
+                    );
+                }
+            }
+            for (position, pair) in animation.keyframes.windows(2).enumerate() {
+                if pair[1].time < pair[0].time {
+                    checker.report(
+                        file,
+                        format!("{}.keyframes[{}].time", path, position + 1),
+                        format!(
+                            "keyframe at {} ms comes after one at {} ms",
+                            pair[1].time, pair[0].time
+                        ),
+                    );
+                }
+            }
+            if let Some(spritesheet) = spritesheet {
+                let frame_count = spritesheet.frame_count();
+                for (position, frame) in animation.frames.iter().enumerate() {
+                    if usize::from(frame.index) >= frame_count {
+                        checker.report(
+                            file,
+                            format!("{}.frames[{}]", path, position),
+                            format!(
+                                "frame {} is past the end of spritesheet {}, which has {}",
+                                frame.index, self.spritesheet_index, frame_count
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Validate for AnimationController {
+    fn validate(&self, checker: &mut Checker, file: &'static str, path: &str) {
+        if !self.states.contains_key(self.state()) {
+            checker.report(
+                file,
+                format!("{}.state", path),
+                format!("there's no state named {}", self.state()),
+            );
+        }
+        let mut names = self.states.keys().collect::<Vec<&String>>();
+        names.sort();
+        for name in names {
+            for (position, transition) in self.states[name].transitions.iter().enumerate() {
+                if !self.states.contains_key(&transition.to) {
+                    checker.report(
+                        file,
+                        format!("{}.states.{}.transitions[{}].to", path, name, position),
+                        format!("there's no state named {}", transition.to),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Validate for Quad {
+    fn validate(&self, checker: &mut Checker, file: &'static str, path: &str) {
+        checker.check_texture(self.texture_index, file, &format!("{}.texture_index", path));
+    }
+}
+
+struct Checker<'c, 's> {
+    sources: &'c Sources<'s>,
+    problems: Vec<Problem>,
+}
+
+impl<'c, 's> Checker<'c, 's> {
+    fn report<M: Into<String>>(&mut self, file: &'static str, path: String, message: M) {
+        self.problems.push(Problem {
+            file,
+            path,
+            message: message.into(),
+        });
+    }
+
+    fn check_texture(&mut self, texture: Index, file: &'static str, path: &str) {
+        if texture >= self.sources.textures.len() {
+            self.report(
+                file,
+                path.to_string(),
+                format!("there's no texture {}", texture),
+            );
+        }
+    }
+
+    /// Returns the spritesheet if it exists.
+    fn check_spritesheet(
+        &mut self,
+        spritesheet: Index,
+        file: &'static str,
+        path: &str,
+    ) -> Option<&'s Spritesheet> {
+        let found = self.sources.spritesheets.get(spritesheet);
+        if found.is_none() {
+            self.report(
+                file,
+                path.to_string(),
+                format!("there's no spritesheet {}", spritesheet),
+            );
+        }
+        found
+    }
+
+    /// Data that's looked up by position has to have its `index` match its position.
+    fn check_indices(&mut self) {
+        let sources = self.sources;
+        let lists: [(&'static str, &str, Vec<Index>); 6] = [
+            (
+                "resources.yaml",
+                "textures",
+                sources.textures.iter().map(|texture| texture.index).collect(),
+            ),
+            (
+                "spritesheets.yaml",
+                "",
+                sources.spritesheets.iter().map(|sheet| sheet.index).collect(),
+            ),
+            (
+                "backgrounds.yaml",
+                "",
+                sources.backgrounds.iter().map(|background| background.index).collect(),
+            ),
+            (
+                "maps.yaml",
+                "",
+                sources.maps.iter().map(|map| map.index).collect(),
+            ),
+            (
+                "dialogue.yaml",
+                "windows",
+                sources.dialogue.windows.iter().map(|window| window.index).collect(),
+            ),
+            (
+                "dialogue.yaml",
+                "scripts",
+                sources.dialogue.scripts.iter().map(|script| script.index).collect(),
+            ),
+        ];
+        for (file, list, indices) in lists.iter() {
+            for (position, index) in indices.iter().enumerate() {
+                let path = format!("{}[{}].index", list, position);
+                match indices[..position].iter().position(|other| other == index) {
+                    Some(first) => self.report(
+                        file,
+                        path,
+                        format!("index {} is also used by {}[{}]", index, list, first),
+                    ),
+                    None if *index != position => self.report(
+                        file,
+                        path,
+                        format!("index {} should be {}, its position in the list", index, position),
+                    ),
+                    None => {}
+                }
+            }
+        }
+
+        for (position, spritesheet) in sources.spritesheets.iter().enumerate() {
+            if spritesheet.frames.is_empty() && spritesheet.pitch == 0 {
+                self.report(
+                    "spritesheets.yaml",
+                    format!("[{}].pitch", position),
+                    "there has to be at least one frame in each row",
+                );
+            }
+        }
+    }
+
+    fn check_entities(&mut self) {
+        let sources = self.sources;
+        let mut owners: Vec<(Handle, Index)> = vec![];
+        for (handle, entity) in sources.entities.entities.iter() {
+            let path = format!("entities[{}]", handle.index);
+            for (position, component) in entity.components.iter().enumerate() {
+                let path = format!("{}.components[{}]", path, position);
+                if sources.components.get_component(*component).is_none() {
+                    self.report(
+                        "entities.yaml",
+                        path,
+                        format!("there's no component {}", component.index),
+                    );
+                } else if let Some((_, owner)) =
+                    owners.iter().find(|(owned, _)| owned == component)
+                {
+                    self.report(
+                        "entities.yaml",
+                        path,
+                        format!(
+                            "component {} already belongs to entities[{}]",
+                            component.index, owner
+                        ),
+                    );
+                } else {
+                    owners.push((*component, handle.index));
+                }
+            }
+            if let Some(parent) = entity.parent() {
+                if sources.entities.get_entity(parent).is_none() {
+                    self.report(
+                        "entities.yaml",
+                        format!("{}.parent", path),
+                        format!("there's no entity {}", parent.index),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_components(&mut self) {
+        let sources = self.sources;
+        for (position, handle) in sources.component_handles.iter().enumerate() {
+            let type_id = match sources.components.get_component(*handle) {
+                Some(component) => component.type_id,
+                None => continue,
+            };
+            let path = format!("components[{}].component_data", position);
+            if type_id == TypeId::of::<Animation2D>() {
+                if let Ok(storage) = sources.components.storage::<Animation2D>() {
+                    if let Some(animation) = storage.get(*handle) {
+                        animation.validate(self, "components.yaml", &format!("{}.Animation2D", path));
+                    }
+                }
+            } else if type_id == TypeId::of::<AnimationController>() {
+                if let Ok(storage) = sources.components.storage::<AnimationController>() {
+                    if let Some(controller) = storage.get(*handle) {
+                        let path = format!("{}.AnimationController", path);
+                        controller.validate(self, "components.yaml", &path);
+                    }
+                }
+            } else if type_id == TypeId::of::<Quad>() {
+                if let Ok(storage) = sources.components.storage::<Quad>() {
+                    if let Some(quad) = storage.get(*handle) {
+                        quad.validate(self, "components.yaml", &format!("{}.Quad", path));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_maps(&mut self) {
+        let sources = self.sources;
+        for (map_position, map) in sources.maps.iter().enumerate() {
+            for (position, entity) in map.entities.iter().enumerate() {
+                if sources.entities.get_entity(*entity).is_none() {
+                    self.report(
+                        "maps.yaml",
+                        format!("[{}].entities[{}]", map_position, position),
+                        format!("there's no entity {}", entity.index),
+                    );
+                }
+            }
+            for (position, instance) in map.prefabs.iter().enumerate() {
+                let path = format!("[{}].prefabs[{}]", map_position, position);
+                self.check_instance(instance, "maps.yaml", &path);
+            }
+            if let Some(script) = map.dialogue {
+                if script >= sources.dialogue.scripts.len() {
+                    self.report(
+                        "maps.yaml",
+                        format!("[{}].dialogue", map_position),
+                        format!("there's no dialogue script {}", script),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_dialogue(&mut self) {
+        let sources = self.sources;
+        let file = "dialogue.yaml";
+        for (position, window) in sources.dialogue.windows.iter().enumerate() {
+            let path = format!("windows[{}]", position);
+            let texture_path = format!("{}.texture_index", path);
+            self.check_texture(window.texture_index, file, &texture_path);
+            // The window is drawn into its texture whole.
+            if let Some(texture) = sources.textures.get(window.texture_index) {
+                let (size, bounds) = (texture.size, window.bounds.size);
+                if size.x != bounds.x || size.y != bounds.y {
+                    self.report(
+                        file,
+                        texture_path,
+                        format!(
+                            "texture {} is {}x{}, but the window is {}x{}",
+                            window.texture_index, size.x, size.y, bounds.x, bounds.y
+                        ),
+                    );
+                }
+            }
+            self.check_texture(
+                window.frame.texture_index,
+                file,
+                &format!("{}.frame.texture_index", path),
+            );
+            self.check_texture(
+                window.name_plate.frame.texture_index,
+                file,
+                &format!("{}.name_plate.frame.texture_index", path),
+            );
+            if let Some(atlas) = window.atlas_texture_index {
+                self.check_texture(atlas, file, &format!("{}.atlas_texture_index", path));
+            }
+            if let Some(icons) = &window.icons {
+                self.check_texture(
+                    icons.texture_index,
+                    file,
+                    &format!("{}.icons.texture_index", path),
+                );
+                self.check_spritesheet(
+                    icons.spritesheet_index,
+                    file,
+                    &format!("{}.icons.spritesheet_index", path),
+                );
+            }
+        }
+
+        for (position, script) in sources.dialogue.scripts.iter().enumerate() {
+            let path = format!("scripts[{}]", position);
+            if script.window >= sources.dialogue.windows.len() {
+                self.report(
+                    file,
+                    format!("{}.window", path),
+                    format!("there's no window {}", script.window),
+                );
+            }
+            if script.lines.is_empty() {
+                self.report(file, format!("{}.lines", path), "there are no lines");
+            }
+            for (line_position, line) in script.lines.iter().enumerate() {
+                let portrait = match &line.portrait {
+                    Some(portrait) => portrait,
+                    None => continue,
+                };
+                let path = format!("{}.lines[{}].portrait", path, line_position);
+                self.check_texture(
+                    portrait.texture_index,
+                    file,
+                    &format!("{}.texture_index", path),
+                );
+                let spritesheet = self.check_spritesheet(
+                    portrait.spritesheet_index,
+                    file,
+                    &format!("{}.spritesheet_index", path),
+                );
+                if let Some(spritesheet) = spritesheet {
+                    let frame_count = spritesheet.frame_count();
+                    if usize::from(portrait.frame) >= frame_count {
+                        self.report(
+                            file,
+                            format!("{}.frame", path),
+                            format!(
+                                "frame {} is past the end of spritesheet {}, which has {}",
+                                portrait.frame, portrait.spritesheet_index, frame_count
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_prefabs(&mut self) {
+        let sources = self.sources;
+        for name in sources.prefabs.names() {
+            let path = format!("prefabs.{}", name);
+            self.check_instance(&PrefabInstance::new(name), "prefabs.yaml", &path);
+        }
+    }
+
+    /// Checks a prefab as it would be created, along with its children.
+    fn check_instance(&mut self, instance: &PrefabInstance, file: &'static str, path: &str) {
+        let prefab = match self.sources.prefabs.resolve(instance) {
+            Ok(prefab) => prefab,
+            Err(err) => {
+                self.report(file, path.to_string(), format!("{}", err));
+                return;
+            }
+        };
+        let Prefab {
+            components,
+            children,
+            ..
+        } = prefab;
+
+        for (name, data) in components {
+            let name = match name {
+                Value::String(name) => name,
+                _ => continue,
+            };
+            let component_path = format!("{}.components.{}", path, name);
+            let type_id = match self.sources.components.named_type(&name) {
+                Some(type_id) => type_id,
+                None => {
+                    self.report(
+                        file,
+                        component_path,
+                        format!("there's no component type named {}", name),
+                    );
+                    continue;
+                }
+            };
+            if type_id == TypeId::of::<Animation2D>() {
+                self.check_data::<Animation2D>(data, file, &component_path);
+            } else if type_id == TypeId::of::<AnimationController>() {
+                self.check_data::<AnimationController>(data, file, &component_path);
+            } else if type_id == TypeId::of::<Quad>() {
+                self.check_data::<Quad>(data, file, &component_path);
+            }
+        }
+
+        for (position, child) in children.iter().enumerate() {
+            self.check_instance(child, file, &format!("{}.children[{}]", path, position));
+        }
+    }
+
+    /// Checks component data that hasn't been turned into a component yet.
+    fn check_data<T>(&mut self, data: Value, file: &'static str, path: &str)
+    where
+        T: Validate + serde::de::DeserializeOwned,
+    {
+        match serde_yaml::from_value::<T>(data) {
+            Ok(component) => component.validate(self, file, path),
+            Err(err) => self.report(file, path.to_string(), format!("{}", err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problems_say_where_they_are() {
+        let textures: Vec<Texture> =
+            serde_yaml::from_str("[{ index: 0, size: { x: 16, y: 16 } }]").unwrap();
+        // Two frames in one row; the second spritesheet reuses the first's index.
+        let spritesheets: Vec<Spritesheet> = serde_yaml::from_str(
+            "[{ index: 0, pitch: 2, position: { x: 0, y: 0 }, size: { x: 32, y: 16 },
+                frame_size: { x: 16, y: 16 } },
+              { index: 0, pitch: 1, position: { x: 0, y: 0 } }]",
+        )
+        .unwrap();
+        let backgrounds: Vec<Background> = serde_yaml::from_str(
+            "[{ index: 1, position: { x: 0, y: 0 }, size: { x: 16, y: 16 } }]",
+        )
+        .unwrap();
+        let entities: EntityManager =
+            serde_yaml::from_str("entities: [{ components: [0, 5] }]").unwrap();
+        let mut components = ComponentManager::new();
+        let component_handles = components
+            .load(
+                "components:
+                   - component_data:
+                       Animation2D:
+                         texture_index: 1
+                         spritesheet_index: 0
+                         layer: 0
+                         current_animation: 2
+                         animations: [{ frames: [0, 9], animation_type: Loop, frame_length: 100 }]
+                         transform_data: { translation: [0, 0], scaling: [1, 1], rotation: 0 }
+                         movement:
+                           { delta_rotation: 0, delta_scale: [0, 0], delta_translate: [0, 0] }
+                   - component_data:
+                       Quad:
+                         texture_index: 0
+                         layer: 0
+                         uv_offset: { x: 0, y: 0 }
+                         transform_data: { translation: [0, 0], scaling: [1, 1], rotation: 0 }",
+            )
+            .unwrap();
+        let prefabs: Prefabs =
+            serde_yaml::from_str("prefabs: { thing: { components: { Health: { hp: 3 } } } }")
+                .unwrap();
+        let dialogue: DialogueData = serde_yaml::from_str("{ windows: [], scripts: [] }").unwrap();
+
+        let problems = validate(&Sources {
+            entities: &entities,
+            components: &components,
+            component_handles: &component_handles,
+            textures: &textures,
+            spritesheets: &spritesheets,
+            backgrounds: &backgrounds,
+            maps: &[],
+            prefabs: &prefabs,
+            dialogue: &dialogue,
+        });
+        let places = problems
+            .iter()
+            .map(|problem| (problem.file, problem.path.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            places,
+            vec![
+                ("spritesheets.yaml", "[1].index"),
+                ("backgrounds.yaml", "[0].index"),
+                ("entities.yaml", "entities[0].components[1]"),
+                (
+                    "components.yaml",
+                    "components[0].component_data.Animation2D.texture_index"
+                ),
+                (
+                    "components.yaml",
+                    "components[0].component_data.Animation2D.current_animation"
+                ),
+                (
+                    "components.yaml",
+                    "components[0].component_data.Animation2D.animations[0].frames[1]"
+                ),
+                ("prefabs.yaml", "prefabs.thing.components.Health"),
+            ]
+        );
+        assert_eq!(
+            problems[0].to_string(),
+            "spritesheets.yaml: [1].index: index 0 is also used by [0]"
+        );
+        assert_eq!(
+            problems[5].message,
+            "frame 9 is past the end of spritesheet 0, which has 2"
+        );
+    }
+
+    #[test]
+    fn prefab_components_are_checked_as_they_would_be_created() {
+        let prefabs: Prefabs = serde_yaml::from_str(
+            "prefabs:
+               sprite:
+                 components:
+                   Animation2D:
+                     texture_index: 0
+                     spritesheet_index: 4
+                     layer: 0
+                     current_animation: 0
+                     transform_data: { translation: [0, 0], scaling: [1, 1], rotation: 0 }
+                     movement: { delta_rotation: 0, delta_scale: [0, 0], delta_translate: [0, 0] }
+               broken: { components: { Quad: { texture_index: zero } } }
+               parent: { children: [{ prefab: broken }] }",
+        )
+        .unwrap();
+        let dialogue: DialogueData = serde_yaml::from_str("{ windows: [], scripts: [] }").unwrap();
+        let problems = validate(&Sources {
+            entities: &EntityManager::new(),
+            components: &ComponentManager::new(),
+            component_handles: &[],
+            textures: &[],
+            spritesheets: &[],
+            backgrounds: &[],
+            maps: &[],
+            prefabs: &prefabs,
+            dialogue: &dialogue,
+        });
+        let places = problems
+            .iter()
+            .map(|problem| (problem.file, problem.path.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            places,
+            vec![
+                ("prefabs.yaml", "prefabs.broken.components.Quad"),
+                ("prefabs.yaml", "prefabs.parent.children[0].components.Quad"),
+                (
+                    "prefabs.yaml",
+                    "prefabs.sprite.components.Animation2D.texture_index"
+                ),
+                (
+                    "prefabs.yaml",
+                    "prefabs.sprite.components.Animation2D.spritesheet_index"
+                ),
+                (
+                    "prefabs.yaml",
+                    "prefabs.sprite.components.Animation2D.current_animation"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn dialogue_is_checked_against_what_it_draws_with() {
+        let textures: Vec<Texture> =
+            serde_yaml::from_str("[{ index: 0, size: { x: 16, y: 16 } }]").unwrap();
+        let spritesheets: Vec<Spritesheet> = serde_yaml::from_str(
+            "[{ index: 0, pitch: 2, position: { x: 0, y: 0 }, size: { x: 32, y: 16 },
+                frame_size: { x: 16, y: 16 } }]",
+        )
+        .unwrap();
+        let dialogue: DialogueData = serde_yaml::from_str(
+            "
+             windows:
+               - index: 0
+                 bounds: { position: { x: 0, y: 0 }, size: { x: 400, y: 100 } }
+                 texture_index: 0
+                 frame:
+                   texture_index: 3
+                   region: { position: { x: 0, y: 0 }, size: { x: 16, y: 16 } }
+                   border: { left: 4, right: 4, top: 4, bottom: 4 }
+                 name_plate:
+                   frame:
+                     texture_index: 0
+                     region: { position: { x: 0, y: 0 }, size: { x: 16, y: 16 } }
+                     border: { left: 4, right: 4, top: 4, bottom: 4 }
+                   offset: 16
+                   height: 20
+                   padding: 4
+                 padding: 10
+                 font: Mono
+                 text_height: 20
+                 text_color: { r: 1.0, g: 1.0, b: 1.0, a: 1.0 }
+                 characters_per_second: 30
+                 atlas_texture_index: 5
+                 icons: { texture_index: 0, spritesheet_index: 2 }
+             scripts:
+               - index: 0
+                 name: Lost
+                 window: 1
+                 lines:
+                   - text: Hi.
+                     portrait: { texture_index: 0, spritesheet_index: 0, frame: 9 }
+               - { index: 2, name: Empty, window: 0, lines: [] }",
+        )
+        .unwrap();
+
+        let problems = validate(&Sources {
+            entities: &EntityManager::new(),
+            components: &ComponentManager::new(),
+            component_handles: &[],
+            textures: &textures,
+            spritesheets: &spritesheets,
+            backgrounds: &[],
+            maps: &[],
+            prefabs: &serde_yaml::from_str("prefabs: {}").unwrap(),
+            dialogue: &dialogue,
+        });
+        let places = problems
+            .iter()
+            .map(|problem| (problem.file, problem.path.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            places,
+            vec![
+                ("dialogue.yaml", "scripts[1].index"),
+                ("dialogue.yaml", "windows[0].texture_index"),
+                ("dialogue.yaml", "windows[0].frame.texture_index"),
+                ("dialogue.yaml", "windows[0].atlas_texture_index"),
+                ("dialogue.yaml", "windows[0].icons.spritesheet_index"),
+                ("dialogue.yaml", "scripts[0].window"),
+                ("dialogue.yaml", "scripts[0].lines[0].portrait.frame"),
+                ("dialogue.yaml", "scripts[1].lines"),
+            ]
+        );
+        assert_eq!(
+            problems[1].message,
+            "texture 0 is 16x16, but the window is 400x100"
+        );
+    }
+}